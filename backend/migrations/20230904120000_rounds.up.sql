@@ -0,0 +1,12 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS rounds
+(
+    id          UUID PRIMARY KEY,
+    user_id     INTEGER     NOT NULL REFERENCES user_creds (id),
+    city_id     INTEGER     NOT NULL,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+    expires_at  TIMESTAMPTZ NOT NULL,
+    answered_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS rounds_user_id_idx ON rounds (user_id);
@@ -0,0 +1,12 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS rounds_game_round_number_idx;
+
+ALTER TABLE rounds
+    DROP COLUMN IF EXISTS game_id,
+    DROP COLUMN IF EXISTS round_number,
+    DROP COLUMN IF EXISTS guess_lat,
+    DROP COLUMN IF EXISTS guess_lng,
+    DROP COLUMN IF EXISTS distance,
+    DROP COLUMN IF EXISTS score;
+
+DROP TABLE IF EXISTS games;
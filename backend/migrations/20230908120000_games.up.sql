@@ -0,0 +1,25 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS games
+(
+    id          UUID PRIMARY KEY,
+    user_id     INTEGER     NOT NULL REFERENCES user_creds (id),
+    num_rounds  INTEGER     NOT NULL,
+    total_score INTEGER     NOT NULL DEFAULT 0,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+    finished_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS games_user_id_idx ON games (user_id);
+
+-- Standalone rounds from before games existed expire after half an hour anyway, there is nothing to keep
+DELETE FROM rounds;
+
+ALTER TABLE rounds
+    ADD COLUMN game_id      UUID    NOT NULL REFERENCES games (id) ON DELETE CASCADE,
+    ADD COLUMN round_number INTEGER NOT NULL,
+    ADD COLUMN guess_lat    REAL,
+    ADD COLUMN guess_lng    REAL,
+    ADD COLUMN distance     REAL,
+    ADD COLUMN score        INTEGER;
+
+CREATE UNIQUE INDEX IF NOT EXISTS rounds_game_round_number_idx ON rounds (game_id, round_number);
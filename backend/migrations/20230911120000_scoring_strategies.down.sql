@@ -0,0 +1,7 @@
+-- Add down migration script here
+ALTER TABLE rounds
+    DROP COLUMN IF EXISTS scoring;
+
+ALTER TABLE games
+    DROP COLUMN IF EXISTS mode,
+    DROP COLUMN IF EXISTS scoring;
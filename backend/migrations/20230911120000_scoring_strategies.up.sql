@@ -0,0 +1,10 @@
+-- Add up migration script here
+ALTER TABLE games
+    ADD COLUMN mode    TEXT NOT NULL DEFAULT 'classic',
+    ADD COLUMN scoring TEXT NOT NULL DEFAULT 'linear_clamped';
+
+-- Rounds that were already scored keep no strategy, they were scored before strategies were recorded
+ALTER TABLE rounds
+    ADD COLUMN scoring TEXT;
+
+UPDATE rounds SET scoring = 'linear_clamped' WHERE answered_at IS NULL;
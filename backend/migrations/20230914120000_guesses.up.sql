@@ -0,0 +1,24 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS guesses
+(
+    id         SERIAL PRIMARY KEY,
+    user_id    INTEGER     NOT NULL REFERENCES user_creds (id),
+    round_id   UUID        REFERENCES rounds (id) ON DELETE SET NULL,
+    city_id    INTEGER     NOT NULL,
+    guess_lat  REAL        NOT NULL,
+    guess_lng  REAL        NOT NULL,
+    distance   REAL        NOT NULL,
+    score      INTEGER     NOT NULL,
+    scoring    TEXT,
+    game_mode  TEXT        NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS guesses_user_id_created_at_idx ON guesses (user_id, created_at);
+
+-- Every round answered so far already holds its guess, carry those over
+INSERT INTO guesses (user_id, round_id, city_id, guess_lat, guess_lng, distance, score, scoring, game_mode, created_at)
+SELECT r.user_id, r.id, r.city_id, r.guess_lat, r.guess_lng, r.distance, r.score, r.scoring, g.mode, r.answered_at
+FROM rounds r
+         JOIN games g ON g.id = r.game_id
+WHERE r.answered_at IS NOT NULL;
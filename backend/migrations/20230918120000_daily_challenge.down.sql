@@ -0,0 +1,6 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS games_daily_date_idx;
+DROP INDEX IF EXISTS games_user_daily_date_idx;
+
+ALTER TABLE games
+    DROP COLUMN IF EXISTS daily_date;
@@ -0,0 +1,8 @@
+-- Add up migration script here
+ALTER TABLE games
+    ADD COLUMN daily_date DATE;
+
+-- Every user only gets to play each daily challenge once
+CREATE UNIQUE INDEX IF NOT EXISTS games_user_daily_date_idx ON games (user_id, daily_date) WHERE daily_date IS NOT NULL;
+
+CREATE INDEX IF NOT EXISTS games_daily_date_idx ON games (daily_date, total_score DESC) WHERE daily_date IS NOT NULL;
@@ -0,0 +1,21 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS cities
+(
+    id                       INTEGER PRIMARY KEY,
+    city                     TEXT    NOT NULL,
+    state                    TEXT    NOT NULL,
+    country_code             TEXT    NOT NULL,
+    latitude                 REAL    NOT NULL CHECK (latitude BETWEEN -90 AND 90),
+    longitude                REAL    NOT NULL CHECK (longitude BETWEEN -180 AND 180),
+    population               BIGINT  NOT NULL CHECK (population >= 0),
+    rank                     INTEGER NOT NULL,
+    growth_from_2000_to_2013 REAL,
+    alternate_names          TEXT[]  NOT NULL DEFAULT '{}'
+);
+
+CREATE INDEX IF NOT EXISTS cities_country_state_idx ON cities (country_code, state);
+CREATE INDEX IF NOT EXISTS cities_population_idx ON cities (population);
+CREATE INDEX IF NOT EXISTS cities_location_idx ON cities (latitude, longitude);
+
+-- Rounds and guesses keep pointing at cities by id (their city_id column), there is no foreign key yet
+-- because the table is only filled by the seed command after this migration ran
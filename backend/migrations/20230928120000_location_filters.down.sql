@@ -0,0 +1,6 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS games_filter_key_idx;
+
+ALTER TABLE games
+    DROP COLUMN IF EXISTS filter_key,
+    DROP COLUMN IF EXISTS location_filter;
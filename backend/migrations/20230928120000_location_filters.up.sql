@@ -0,0 +1,7 @@
+-- Add up migration script here
+ALTER TABLE games
+    ADD COLUMN location_filter JSONB NOT NULL DEFAULT '{}',
+    ADD COLUMN filter_key      TEXT  NOT NULL DEFAULT '';
+
+-- Leaderboards of filtered games are built from the finished games with the same filter
+CREATE INDEX IF NOT EXISTS games_filter_key_idx ON games (filter_key, user_id) WHERE finished_at IS NOT NULL;
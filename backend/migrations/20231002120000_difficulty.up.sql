@@ -0,0 +1,7 @@
+-- Add up migration script here
+ALTER TABLE games
+    ADD COLUMN difficulty TEXT NOT NULL DEFAULT 'medium';
+
+-- Leaderboards for other settings are grouped by filter and difficulty together
+DROP INDEX IF EXISTS games_filter_key_idx;
+CREATE INDEX IF NOT EXISTS games_settings_idx ON games (filter_key, difficulty, user_id) WHERE finished_at IS NOT NULL;
@@ -0,0 +1,14 @@
+-- Add down migration script here
+ALTER TABLE rounds
+    DROP COLUMN IF EXISTS target_lng,
+    DROP COLUMN IF EXISTS target_lat,
+    DROP COLUMN IF EXISTS target_region,
+    DROP COLUMN IF EXISTS target_name;
+
+DROP INDEX IF EXISTS games_map_id_idx;
+
+ALTER TABLE games
+    DROP COLUMN IF EXISTS map_id;
+
+DROP TABLE IF EXISTS map_locations;
+DROP TABLE IF EXISTS custom_maps;
@@ -0,0 +1,39 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS custom_maps
+(
+    id          UUID PRIMARY KEY,
+    owner_id    INTEGER     NOT NULL REFERENCES user_creds (id),
+    name        TEXT        NOT NULL,
+    description TEXT        NOT NULL DEFAULT '',
+    visibility  TEXT        NOT NULL DEFAULT 'private',
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at  TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS custom_maps_owner_id_idx ON custom_maps (owner_id);
+CREATE INDEX IF NOT EXISTS custom_maps_public_idx ON custom_maps (updated_at DESC) WHERE visibility = 'public';
+
+CREATE TABLE IF NOT EXISTS map_locations
+(
+    id        SERIAL PRIMARY KEY,
+    map_id    UUID    NOT NULL REFERENCES custom_maps (id) ON DELETE CASCADE,
+    city_id   INTEGER,
+    name      TEXT    NOT NULL,
+    region    TEXT,
+    latitude  REAL    NOT NULL CHECK (latitude BETWEEN -90 AND 90),
+    longitude REAL    NOT NULL CHECK (longitude BETWEEN -180 AND 180)
+);
+
+CREATE INDEX IF NOT EXISTS map_locations_map_id_idx ON map_locations (map_id);
+
+ALTER TABLE games
+    ADD COLUMN map_id UUID REFERENCES custom_maps (id);
+
+CREATE INDEX IF NOT EXISTS games_map_id_idx ON games (map_id, user_id) WHERE map_id IS NOT NULL AND finished_at IS NOT NULL;
+
+-- Locations that aren't catalog cities are copied onto the round, so editing the map never changes old rounds
+ALTER TABLE rounds
+    ADD COLUMN target_name   TEXT,
+    ADD COLUMN target_region TEXT,
+    ADD COLUMN target_lat    REAL,
+    ADD COLUMN target_lng    REAL;
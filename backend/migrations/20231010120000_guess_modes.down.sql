@@ -0,0 +1,9 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS games_settings_idx;
+CREATE INDEX IF NOT EXISTS games_settings_idx ON games (filter_key, difficulty, user_id) WHERE finished_at IS NOT NULL;
+
+ALTER TABLE rounds
+    DROP COLUMN IF EXISTS guessed_region;
+
+ALTER TABLE games
+    DROP COLUMN IF EXISTS guess_mode;
@@ -0,0 +1,11 @@
+-- Add up migration script here
+ALTER TABLE games
+    ADD COLUMN guess_mode TEXT NOT NULL DEFAULT 'point';
+
+-- Code of the country or state a guess fell in, only set in the country and state modes
+ALTER TABLE rounds
+    ADD COLUMN guessed_region TEXT;
+
+-- Leaderboards for other settings are grouped by guess mode as well
+DROP INDEX IF EXISTS games_settings_idx;
+CREATE INDEX IF NOT EXISTS games_settings_idx ON games (filter_key, difficulty, guess_mode, user_id) WHERE finished_at IS NOT NULL;
@@ -0,0 +1,6 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS games_settings_idx;
+CREATE INDEX IF NOT EXISTS games_settings_idx ON games (filter_key, difficulty, guess_mode, user_id) WHERE finished_at IS NOT NULL;
+
+ALTER TABLE games
+    DROP COLUMN IF EXISTS location_source;
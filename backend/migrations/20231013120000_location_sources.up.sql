@@ -0,0 +1,8 @@
+-- Add up migration script here
+ALTER TABLE games
+    ADD COLUMN location_source TEXT NOT NULL DEFAULT 'cities';
+
+-- Leaderboards for other settings are grouped by location source as well
+DROP INDEX IF EXISTS games_settings_idx;
+CREATE INDEX IF NOT EXISTS games_settings_idx ON games (filter_key, difficulty, guess_mode, location_source, user_id)
+    WHERE finished_at IS NOT NULL;
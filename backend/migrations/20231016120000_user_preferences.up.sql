@@ -0,0 +1,10 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS user_preferences
+(
+    user_id       INTEGER PRIMARY KEY REFERENCES user_creds (id) ON DELETE CASCADE,
+    distance_unit TEXT        NOT NULL DEFAULT 'kilometers',
+    map_type      TEXT        NOT NULL DEFAULT 'roadmap',
+    difficulty    TEXT        NOT NULL DEFAULT 'medium',
+    guess_mode    TEXT        NOT NULL DEFAULT 'point',
+    updated_at    TIMESTAMPTZ NOT NULL DEFAULT now()
+);
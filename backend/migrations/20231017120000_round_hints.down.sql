@@ -0,0 +1,4 @@
+-- Add down migration script here
+ALTER TABLE rounds
+    DROP COLUMN IF EXISTS hint_penalty,
+    DROP COLUMN IF EXISTS hints;
@@ -0,0 +1,5 @@
+-- Add up migration script here
+-- Hints taken for a round, and the percent of the best score they cost together
+ALTER TABLE rounds
+    ADD COLUMN hints        TEXT[]  NOT NULL DEFAULT '{}',
+    ADD COLUMN hint_penalty INTEGER NOT NULL DEFAULT 0;
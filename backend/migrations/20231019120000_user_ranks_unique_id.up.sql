@@ -0,0 +1,21 @@
+-- Add up migration script here
+-- Users whose first games finished at the same time got a row each, fold them into one
+CREATE TEMPORARY TABLE merged_ranks AS
+SELECT id, SUM(total_score) AS total_score, SUM(num_guesses) AS num_guesses
+FROM user_ranks
+GROUP BY id
+HAVING COUNT(*) > 1;
+
+DELETE FROM user_ranks WHERE id IN (SELECT id FROM merged_ranks);
+
+INSERT INTO user_ranks (id, total_score, num_guesses)
+SELECT id, total_score, num_guesses FROM merged_ranks;
+
+DROP TABLE merged_ranks;
+
+UPDATE user_ranks AS t SET rank = r.ranking FROM (
+    SELECT id, DENSE_RANK() OVER (ORDER BY total_score DESC) AS ranking FROM user_ranks
+) AS r WHERE t.id = r.id;
+
+-- Every user has one row, finished games are added to it with an upsert
+CREATE UNIQUE INDEX IF NOT EXISTS user_ranks_id ON user_ranks (id);
@@ -0,0 +1,7 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS user_ranks_id_scoring;
+DELETE FROM user_ranks WHERE scoring <> 'linear_clamped';
+CREATE UNIQUE INDEX IF NOT EXISTS user_ranks_id ON user_ranks (id);
+
+ALTER TABLE user_ranks
+    DROP COLUMN IF EXISTS scoring;
@@ -0,0 +1,8 @@
+-- Add up migration script here
+-- Scores of different strategies don't add up, every user gets a row on the leaderboard of each strategy they played.
+-- Rows from before are taken to be scored the default way
+ALTER TABLE user_ranks
+    ADD COLUMN scoring TEXT NOT NULL DEFAULT 'linear_clamped';
+
+DROP INDEX IF EXISTS user_ranks_id;
+CREATE UNIQUE INDEX IF NOT EXISTS user_ranks_id_scoring ON user_ranks (id, scoring);
@@ -0,0 +1,6 @@
+-- Add down migration script here
+ALTER TABLE guesses
+    DROP COLUMN IF EXISTS target_lng,
+    DROP COLUMN IF EXISTS target_lat,
+    DROP COLUMN IF EXISTS target_region,
+    DROP COLUMN IF EXISTS target_name;
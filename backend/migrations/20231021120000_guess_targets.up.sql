@@ -0,0 +1,16 @@
+-- Add up migration script here
+-- Places that aren't catalog cities are copied onto the guess like they are onto the round, so the guess history keeps them
+ALTER TABLE guesses
+    ADD COLUMN target_name   TEXT,
+    ADD COLUMN target_region TEXT,
+    ADD COLUMN target_lat    REAL,
+    ADD COLUMN target_lng    REAL;
+
+UPDATE guesses AS g
+SET target_name   = r.target_name,
+    target_region = r.target_region,
+    target_lat    = r.target_lat,
+    target_lng    = r.target_lng
+FROM rounds r
+WHERE r.id = g.round_id
+  AND r.target_lat IS NOT NULL;
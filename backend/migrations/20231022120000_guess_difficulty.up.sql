@@ -0,0 +1,10 @@
+-- Add up migration script here
+-- The difficulty multiplies the score, the stats need it to tell how much of the best score a guess got
+ALTER TABLE guesses
+    ADD COLUMN difficulty TEXT NOT NULL DEFAULT 'medium';
+
+UPDATE guesses AS g
+SET difficulty = ga.difficulty
+FROM rounds r
+         JOIN games ga ON ga.id = r.game_id
+WHERE r.id = g.round_id;
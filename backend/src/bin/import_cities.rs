@@ -0,0 +1,9 @@
+use backend::import::run;
+
+/// Imports a city dataset into a catalog file, see [ImportOptions](backend::import::ImportOptions)
+fn main() {
+    if let Err(err) = run(std::env::args().skip(1)) {
+        eprintln!("Import failed: {:?}", err);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,13 @@
+use backend::import::seed;
+use dotenvy::dotenv;
+
+/// Seeds the cities table from a catalog file, see [seed](backend::import::seed)
+#[tokio::main]
+async fn main() {
+    dotenv().ok();
+
+    if let Err(err) = seed(std::env::args().skip(1)).await {
+        eprintln!("Seeding failed: {:?}", err);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,563 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+
+use rand::seq::SliceRandom;
+use tracing::{info, warn};
+
+use crate::db::Store;
+use crate::error::AppError;
+use crate::models::city::{City, CityProblem, RawCity, ValidationReport};
+use crate::models::filter::{Area, LocationFilter};
+use crate::spatial::SphereTree;
+use crate::AppResult;
+
+/// The city catalog shared by every request, see [CityCatalog]
+pub type Catalog = Arc<CityCatalog>;
+
+/// Every city the game knows about, read from the `cities` table once at startup and indexed for the lookups the game needs.
+/// The table is the source of truth, the catalog is a copy of it kept in memory so picking and looking up
+/// cities doesn't cost a query on every request.
+///
+/// Cities are identified by their id, which is what rounds and guesses store.
+#[derive(Debug, Default)]
+pub struct CityCatalog {
+    cities: Vec<City>,
+
+    /// Position in `cities` by id
+    by_id: HashMap<i32, usize>,
+
+    /// Positions in `cities` by state name
+    by_state: HashMap<String, Vec<usize>>,
+
+    /// Positions in `cities` by country code
+    by_country: HashMap<String, Vec<usize>>,
+
+    /// Positions in `cities`, least populated first
+    by_population: Vec<usize>,
+
+    /// Positions in `cities`, rank 1 first. Ids and ranks only line up for the bundled cities
+    by_rank: Vec<usize>,
+
+    /// Positions in `cities` by where they are, for finding the cities closest to a spot on the map or around it
+    by_location: SphereTree,
+
+    report: ValidationReport,
+}
+
+impl CityCatalog {
+    /// Builds the catalog out of a list of cities, checking them the same way [load](CityCatalog::load) does
+    pub fn from_cities(cities: Vec<City>) -> Self {
+        let report = ValidationReport {
+            entries: cities.len(),
+            ..Default::default()
+        };
+
+        Self::build(cities.into_iter().enumerate().collect(), report)
+    }
+
+    /// Validates cities as they were read from a file, or put together by an [importer](crate::import),
+    /// and builds the catalog out of the ones that pass
+    pub fn from_raw(raw_cities: Vec<RawCity>) -> Self {
+        let mut report = ValidationReport {
+            entries: raw_cities.len(),
+            ..Default::default()
+        };
+        let entries = raw_cities
+            .into_iter()
+            .enumerate()
+            .filter_map(|(entry, raw)| raw.validate(entry, &mut report).map(|city| (entry, city)))
+            .collect();
+
+        Self::build(entries, report)
+    }
+
+    /// Indexes the cities that passed validation, leaving out out of range coordinates and duplicates.
+    /// A city reusing an id, or the name, state and country of a city before it, is left out in favour of the first one.
+    fn build(entries: Vec<(usize, City)>, mut report: ValidationReport) -> Self {
+        let mut catalog = Self::default();
+        let mut seen_names: HashSet<(String, String, String)> = HashSet::new();
+
+        for (entry, city) in entries {
+            let problem = if !(-90.0..=90.0).contains(&city.latitude) {
+                Some(CityProblem::OutOfRange {
+                    field: "latitude",
+                    value: city.latitude as f64,
+                })
+            } else if !(-180.0..=180.0).contains(&city.longitude) {
+                Some(CityProblem::OutOfRange {
+                    field: "longitude",
+                    value: city.longitude as f64,
+                })
+            } else if catalog.by_id.contains_key(&city.id) {
+                Some(CityProblem::DuplicateId { id: city.id })
+            } else if seen_names.contains(&name_key(&city)) {
+                Some(CityProblem::DuplicateCity {
+                    city: city.city.clone(),
+                    state: city.state.clone(),
+                    country_code: city.country_code.clone(),
+                })
+            } else {
+                None
+            };
+
+            if let Some(problem) = problem {
+                report.add(entry, Some(&city.city), problem, true);
+                continue;
+            }
+
+            let index = catalog.cities.len();
+            seen_names.insert(name_key(&city));
+            catalog.by_id.insert(city.id, index);
+            catalog.by_state.entry(city.state.clone()).or_default().push(index);
+            catalog.by_country.entry(city.country_code.clone()).or_default().push(index);
+            catalog.cities.push(city);
+        }
+
+        let mut by_population: Vec<usize> = (0..catalog.cities.len()).collect();
+        by_population.sort_by_key(|&index| catalog.cities[index].population);
+        catalog.by_population = by_population;
+        let mut by_rank: Vec<usize> = (0..catalog.cities.len()).collect();
+        by_rank.sort_by_key(|&index| (catalog.cities[index].rank, catalog.cities[index].id));
+        catalog.by_rank = by_rank;
+        catalog.by_location = SphereTree::new(
+            catalog
+                .cities
+                .iter()
+                .map(|city| [city.latitude, city.longitude])
+                .collect(),
+        );
+
+        report.loaded = catalog.cities.len();
+        catalog.report = report;
+
+        catalog
+    }
+
+    /// Reads the catalog from a json file holding a list of cities.
+    /// Entries that fail validation are left out and listed in the [report](CityCatalog::report),
+    /// only a file that can't be read or isn't a list at all is an error.
+    ///
+    /// # Arguments:
+    /// * path: &[Path] - The json file to read
+    ///
+    /// # Returns:
+    /// [AppResult]<[Self](CityCatalog)>
+    pub fn load(path: &Path) -> AppResult<Self> {
+        let city_string = std::fs::read_to_string(path)?;
+        let raw_cities: Vec<RawCity> = serde_json::from_str(&city_string)?;
+
+        let catalog = Self::from_raw(raw_cities);
+        catalog.log_report(&path.display().to_string());
+
+        Ok(catalog)
+    }
+
+    /// Reads the catalog from the `cities` table.
+    /// A database without any cities yet is seeded from the city file first, see [from_env](CityCatalog::from_env),
+    /// so a fresh install works without running the seed command.
+    ///
+    /// # Arguments:
+    /// * store: &[Store] - The database to read the cities from
+    ///
+    /// # Returns:
+    /// [AppResult]<[Self](CityCatalog)>
+    pub async fn from_store(store: &Store) -> AppResult<Self> {
+        if store.count_cities().await? == 0 {
+            warn!("The cities table is empty, seeding it from the city file");
+            let file_catalog = Self::from_env()?;
+            let seeded = store.seed_cities(file_catalog.all()).await?;
+            info!("Seeded {} cities", seeded);
+        }
+
+        let catalog = Self::from_cities(store.get_cities().await?);
+        catalog.log_report("the cities table");
+
+        Ok(catalog)
+    }
+
+    /// Logs a summary of the validation report, and every issue in it
+    fn log_report(&self, source: &str) {
+        info!("Loaded {} of {} cities from {}", self.report.loaded, self.report.entries, source);
+
+        for issue in &self.report.issues {
+            warn!(
+                "City entry {} ({}): {}{}",
+                issue.entry,
+                issue.city.as_deref().unwrap_or("no name"),
+                issue.problem,
+                if issue.skipped { ", skipped" } else { "" }
+            );
+        }
+    }
+
+    /// What was wrong with the cities the catalog was built from
+    pub fn report(&self) -> &ValidationReport {
+        &self.report
+    }
+
+    /// Reads the catalog from the file set in the .env file
+    ///
+    /// # .env variables
+    /// * CITY_FILE (optional) - Path of the city json file, defaults to `resources/city_json/cities.json`
+    pub fn from_env() -> AppResult<Self> {
+        let city_file = std::env::var("CITY_FILE").unwrap_or_else(|_| "resources/city_json/cities.json".to_string());
+
+        Self::load(Path::new(&city_file))
+    }
+
+    pub fn len(&self) -> usize {
+        self.cities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cities.is_empty()
+    }
+
+    /// Every city, in the order they were loaded
+    pub fn all(&self) -> &[City] {
+        &self.cities
+    }
+
+    /// Looks up a city by its id, if there is one
+    pub fn find(&self, id: i32) -> Option<&City> {
+        self.by_id.get(&id).map(|&index| &self.cities[index])
+    }
+
+    /// The city at the given place when every city is lined up by rank, 0 being the highest ranked one
+    pub fn nth_ranked(&self, position: usize) -> Option<&City> {
+        self.by_rank.get(position).map(|&index| &self.cities[index])
+    }
+
+    /// Looks up a city by its id
+    ///
+    /// # Returns:
+    /// [AppResult]<&[City]> - [AppError::CityNotFound] when no city has that id
+    pub fn get(&self, id: i32) -> AppResult<&City> {
+        self.find(id).ok_or(AppError::CityNotFound)
+    }
+
+    /// Every city in the given state
+    pub fn in_state<'a>(&'a self, state: &str) -> impl Iterator<Item = &'a City> + 'a {
+        self.by_state
+            .get(state)
+            .into_iter()
+            .flatten()
+            .map(|&index| &self.cities[index])
+    }
+
+    /// Every city in the country with the given [ISO 3166-1 alpha-2](https://en.wikipedia.org/wiki/ISO_3166-1_alpha-2) code
+    pub fn in_country<'a>(&'a self, country_code: &str) -> impl Iterator<Item = &'a City> + 'a {
+        self.by_country
+            .get(&country_code.to_uppercase())
+            .into_iter()
+            .flatten()
+            .map(|&index| &self.cities[index])
+    }
+
+    /// Names of every state that has at least one city, in no particular order
+    pub fn states(&self) -> impl Iterator<Item = &str> {
+        self.by_state.keys().map(String::as_str)
+    }
+
+    /// Every city with a population between `min` and `max`, both inclusive, least populated first
+    pub fn with_population_between(&self, min: u64, max: u64) -> impl Iterator<Item = &City> {
+        let start = self
+            .by_population
+            .partition_point(|&index| self.cities[index].population < min);
+        let end = self
+            .by_population
+            .partition_point(|&index| self.cities[index].population <= max);
+
+        self.by_population[start..end.max(start)]
+            .iter()
+            .map(|&index| &self.cities[index])
+    }
+
+    /// Every city the filter lets through. Starts from the smallest index the filter can use,
+    /// so narrow filters don't have to look at every city.
+    pub fn matching<'a>(&'a self, filter: &LocationFilter) -> Vec<&'a City> {
+        let candidates: Box<dyn Iterator<Item = &'a City> + '_> = match (&filter.state, &filter.country_code, &filter.area) {
+            (_, Some(code), _) => Box::new(self.in_country(code)),
+            (Some(state), None, _) => Box::new(
+                self.by_state
+                    .iter()
+                    .filter(|(name, _)| name.eq_ignore_ascii_case(state))
+                    .flat_map(|(_, indexes)| indexes.iter().map(|&index| &self.cities[index])),
+            ),
+            (
+                None,
+                None,
+                Some(Area::Radius {
+                    latitude,
+                    longitude,
+                    radius_km,
+                }),
+            // A little past the radius, so rounding never leaves out a city on the edge the filter itself would let through
+            ) => Box::new(
+                self.within_radius(*latitude, *longitude, radius_km + 1.0)
+                    .into_iter()
+                    .map(|(city, _)| city),
+            ),
+            (None, None, _) if filter.min_population.is_some() || filter.max_population.is_some() => Box::new(
+                self.with_population_between(filter.min_population.unwrap_or(0), filter.max_population.unwrap_or(u64::MAX)),
+            ),
+            (None, None, _) => Box::new(self.cities.iter()),
+        };
+
+        candidates.filter(|city| filter.matches(city)).collect()
+    }
+
+    /// Picks a random city the filter lets through
+    ///
+    /// # Returns:
+    /// [AppResult]<&[City]> - [AppError::InvalidFilter] when no city matches
+    pub fn random_matching(&self, filter: &LocationFilter) -> AppResult<&City> {
+        self.matching(filter)
+            .choose(&mut rand::thread_rng())
+            .copied()
+            .ok_or_else(|| AppError::InvalidFilter("No city matches these settings".to_string()))
+    }
+
+    /// Picks a random city the filter lets through, staying away from the excluded cities when it can.
+    /// Rounds use this to keep clear of the places earlier rounds of the game were at, when every matching
+    /// city is excluded one of them is picked anyway rather than ending the game.
+    ///
+    /// # Arguments:
+    /// * filter: &[LocationFilter] - The cities to pick from
+    /// * excluded: &[HashSet]<i32> - Ids of the cities to stay away from, see [ids_near](CityCatalog::ids_near)
+    ///
+    /// # Returns:
+    /// [AppResult]<&[City]> - [AppError::InvalidFilter] when no city matches
+    pub fn random_matching_outside(&self, filter: &LocationFilter, excluded: &HashSet<i32>) -> AppResult<&City> {
+        let matching = self.matching(filter);
+        let outside: Vec<&City> = matching
+            .iter()
+            .copied()
+            .filter(|city| !excluded.contains(&city.id))
+            .collect();
+
+        let mut rng = rand::thread_rng();
+        outside
+            .choose(&mut rng)
+            .or_else(|| matching.choose(&mut rng))
+            .copied()
+            .ok_or_else(|| AppError::InvalidFilter("No city matches these settings".to_string()))
+    }
+
+    /// Picks a random city the filter lets through, cities with more people coming up more often
+    ///
+    /// # Returns:
+    /// [AppResult]<&[City]> - [AppError::CityNotFound] when no city matches
+    pub fn random_by_population(&self, filter: &LocationFilter) -> AppResult<&City> {
+        self.matching(filter)
+            .choose_weighted(&mut rand::thread_rng(), |city| city.population.max(1))
+            .copied()
+            .map_err(|_| AppError::CityNotFound)
+    }
+
+    /// The city closest to a spot on the map, used to tell players where their guess landed
+    ///
+    /// # Returns:
+    /// [Option]<(&[City], f32)> - The city and how far it is from the spot in kilometers, none when the catalog is empty
+    pub fn nearest(&self, latitude: f32, longitude: f32) -> Option<(&City, f32)> {
+        self.by_location
+            .nearest(latitude, longitude)
+            .map(|(index, distance)| (&self.cities[index], distance))
+    }
+
+    /// The `k` cities closest to a spot on the map, closest first, with how far they are from it in kilometers
+    pub fn k_nearest(&self, latitude: f32, longitude: f32, k: usize) -> Vec<(&City, f32)> {
+        self.by_location
+            .k_nearest(latitude, longitude, k)
+            .into_iter()
+            .map(|(index, distance)| (&self.cities[index], distance))
+            .collect()
+    }
+
+    /// Every city within `radius_km` of a spot on the map, closest first, with how far they are from it in kilometers
+    pub fn within_radius(&self, latitude: f32, longitude: f32, radius_km: f32) -> Vec<(&City, f32)> {
+        self.by_location
+            .within_radius(latitude, longitude, radius_km)
+            .into_iter()
+            .map(|(index, distance)| (&self.cities[index], distance))
+            .collect()
+    }
+
+    /// Ids of every city within `radius_km` of any of the given places, see [random_matching_outside](CityCatalog::random_matching_outside)
+    pub fn ids_near<'a>(&self, places: impl IntoIterator<Item = &'a City>, radius_km: f32) -> HashSet<i32> {
+        places
+            .into_iter()
+            .flat_map(|place| self.within_radius(place.latitude, place.longitude, radius_km))
+            .map(|(city, _)| city.id)
+            .collect()
+    }
+
+    /// Cities whose name, or one of their other names, contains the search text, ignoring case
+    pub fn search<'a>(&'a self, text: &str) -> impl Iterator<Item = &'a City> + 'a {
+        let text = text.trim().to_lowercase();
+
+        self.cities.iter().filter(move |city| {
+            city.city.to_lowercase().contains(&text)
+                || city
+                    .alternate_names
+                    .iter()
+                    .any(|name| name.to_lowercase().contains(&text))
+        })
+    }
+
+    /// Picks a random city
+    ///
+    /// # Returns:
+    /// [AppResult]<&[City]> - [AppError::CityNotFound] when the catalog is empty
+    pub fn random(&self) -> AppResult<&City> {
+        self.cities
+            .choose(&mut rand::thread_rng())
+            .ok_or(AppError::CityNotFound)
+    }
+}
+
+/// What makes two cities the same city as far as duplicates go
+fn name_key(city: &City) -> (String, String, String) {
+    (city.city.to_lowercase(), city.state.to_lowercase(), city.country_code.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn city(id: i32, name: &str, state: &str) -> City {
+        City {
+            id,
+            city: name.to_string(),
+            state: state.to_string(),
+            country_code: "US".to_string(),
+            latitude: 40.0,
+            longitude: -100.0,
+            population: 1000 * id as u64,
+            rank: id,
+            ..City::default()
+        }
+    }
+
+    fn raw(id: i32, name: &str, state: &str) -> RawCity {
+        RawCity {
+            id: Some(json!(id)),
+            city: Some(name.to_string()),
+            latitude: Some(json!(40.0)),
+            longitude: Some(json!(-100.0)),
+            population: Some(json!(1000)),
+            rank: Some(json!(id)),
+            state: Some(state.to_string()),
+            ..RawCity::default()
+        }
+    }
+
+    fn skipped_problems(catalog: &CityCatalog) -> Vec<(usize, &CityProblem)> {
+        catalog
+            .report()
+            .issues
+            .iter()
+            .filter(|issue| issue.skipped)
+            .map(|issue| (issue.entry, &issue.problem))
+            .collect()
+    }
+
+    #[test]
+    fn duplicate_ids_keep_the_first_city() {
+        let catalog = CityCatalog::from_cities(vec![
+            city(1, "Boise City", "Idaho"),
+            city(2, "Nampa", "Idaho"),
+            city(1, "Meridian", "Idaho"),
+        ]);
+
+        assert_eq!(catalog.len(), 2);
+        assert_eq!(catalog.find(1).unwrap().city, "Boise City");
+        assert_eq!(skipped_problems(&catalog), vec![(2, &CityProblem::DuplicateId { id: 1 })]);
+    }
+
+    #[test]
+    fn duplicate_names_keep_the_first_city() {
+        let catalog = CityCatalog::from_cities(vec![
+            city(1, "Springfield", "Illinois"),
+            city(2, "Springfield", "Missouri"),
+            city(3, "SPRINGFIELD", "illinois"),
+        ]);
+
+        assert_eq!(catalog.len(), 2);
+        assert!(catalog.find(3).is_none());
+        assert_eq!(
+            skipped_problems(&catalog),
+            vec![(
+                2,
+                &CityProblem::DuplicateCity {
+                    city: "SPRINGFIELD".to_string(),
+                    state: "illinois".to_string(),
+                    country_code: "US".to_string(),
+                }
+            )]
+        );
+
+        // The same name in another country is another city
+        let abroad = City {
+            country_code: "CA".to_string(),
+            ..city(3, "Springfield", "Illinois")
+        };
+        let catalog = CityCatalog::from_cities(vec![city(1, "Springfield", "Illinois"), abroad]);
+        assert_eq!(catalog.len(), 2);
+        assert!(catalog.report().is_clean());
+    }
+
+    #[test]
+    fn out_of_range_cities_are_left_out() {
+        let catalog = CityCatalog::from_cities(vec![
+            City { latitude: 95.0, ..city(1, "North", "Alaska") },
+            City { longitude: 181.0, ..city(2, "East", "Alaska") },
+            city(3, "Anchorage", "Alaska"),
+        ]);
+
+        assert_eq!(catalog.all().iter().map(|city| city.id).collect::<Vec<_>>(), vec![3]);
+        assert_eq!(
+            skipped_problems(&catalog),
+            vec![
+                (0, &CityProblem::OutOfRange { field: "latitude", value: 95.0 }),
+                (1, &CityProblem::OutOfRange { field: "longitude", value: 181.0 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn the_report_counts_every_entry() {
+        let catalog = CityCatalog::from_raw(vec![
+            raw(1, "Boise City", "Idaho"),
+            RawCity { latitude: None, ..raw(2, "Nampa", "Idaho") },
+            raw(1, "Meridian", "Idaho"),
+            RawCity { growth_from_2000_to_2013: Some(json!("fast")), ..raw(4, "Eagle", "Idaho") },
+            raw(5, "boise city", "IDAHO"),
+        ]);
+        let report = catalog.report();
+
+        assert_eq!(report.entries, 5);
+        assert_eq!(report.loaded, 2);
+        assert_eq!(report.skipped(), 3);
+        assert_eq!(catalog.len(), 2);
+        assert!(!report.is_clean());
+
+        // One issue for every skipped entry, and one kept entry with unreadable growth
+        let skipped: Vec<usize> = report.issues.iter().filter(|issue| issue.skipped).map(|issue| issue.entry).collect();
+        assert_eq!(skipped, vec![1, 2, 4]);
+        let kept: Vec<usize> = report.issues.iter().filter(|issue| !issue.skipped).map(|issue| issue.entry).collect();
+        assert_eq!(kept, vec![3]);
+    }
+
+    #[test]
+    fn a_clean_list_loads_every_city() {
+        let catalog = CityCatalog::from_raw(vec![raw(1, "Boise City", "Idaho"), raw(2, "Nampa", "Idaho")]);
+
+        assert!(catalog.report().is_clean());
+        assert_eq!(catalog.report().loaded, 2);
+        assert_eq!(catalog.report().skipped(), 0);
+    }
+}
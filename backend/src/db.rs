@@ -1,14 +1,25 @@
 use axum::Json;
+use chrono::NaiveDate;
 use serde_json::Value;
 
 use sqlx::postgres::PgPoolOptions;
-use sqlx::{PgPool, Row};
+use sqlx::{PgConnection, PgPool, Row};
 use tracing::{error, info};
+use uuid::Uuid;
 use crate::AppResult;
 
 use crate::error::AppError;
+use crate::models::city::City;
+use crate::models::custom_map::{CustomMap, MapLocation, NewMapLocation, Visibility, MAX_LOCATIONS_PER_MAP};
+use crate::models::daily::{DailyArchiveEntry, DailyLeaderBoardRow};
+use crate::models::game::{Game, GameMode, GameSettings};
+use crate::models::guess::Guess;
+use crate::models::hint::HintKind;
+use crate::models::preferences::UserPreferences;
 use crate::models::location::Location;
+use crate::models::round::Round;
 use crate::models::user::{LoggedInUser, User, UserRankInfo, UserForClaims, UserSignup, LeaderBoardRow};
+use crate::scoring::ScoringKind;
 
 #[derive(Clone)]
 pub struct Store {
@@ -83,15 +94,48 @@ impl Store {
         }
     }
 
+    /// Checks whether a user with the given id has signed up
+    /// # Returns:
+    /// Result<bool, [AppError]>
+    pub async fn user_exists(&self, id: i32) -> Result<bool, AppError> {
+        let (exists,): (bool,) = sqlx::query_as(
+            r#"
+                SELECT EXISTS(SELECT 1 FROM user_creds WHERE id = $1)
+            "#)
+            .bind(id)
+            .fetch_one(&self.conn_pool)
+            .await?;
+
+        Ok(exists)
+    }
+
+    /// Gets a single users row of the leaderboard of a scoring strategy, users who never finished a game scored that way don't have one
+    /// # Returns:
+    /// Result<[Option]<[LeaderBoardRow]>, [AppError]>
+    pub async fn get_user_rank(&self, id: i32, scoring: ScoringKind) -> Result<Option<LeaderBoardRow>, AppError> {
+        let row = sqlx::query_as::<_, LeaderBoardRow>(
+            r#"
+                SELECT id, rank, total_score, num_guesses FROM user_ranks WHERE id = $1 AND scoring = $2
+            "#)
+            .bind(id)
+            .bind(scoring)
+            .fetch_optional(&self.conn_pool)
+            .await?;
+
+        Ok(row)
+    }
+
     /// Gets the top 100 users in the database by ranking. If there are less than 100 users, it will get how many it can.
+    /// Every scoring strategy has a leaderboard of its own, scores out of 100 and out of 5000 can't be added up
     /// # Returns:
     /// Result<[Vec]<[UserAndRank]>, [AppError]>
-    pub async fn get_top_num_users(&self, num_users: i32) -> Result<Vec<LeaderBoardRow>, AppError> {
+    pub async fn get_top_num_users(&self, num_users: i32, scoring: ScoringKind) -> Result<Vec<LeaderBoardRow>, AppError> {
         let mut rows = sqlx::query_as::<_, LeaderBoardRow>(
             r#"
-                SELECT id, rank, total_score, num_guesses FROM user_ranks WHERE rank > 0 AND rank <= $1
+                SELECT id, rank, total_score, num_guesses FROM user_ranks WHERE rank > 0 AND rank <= $1 AND scoring = $2
             "#)
             .bind(num_users)
+            .bind(scoring)
             .fetch_all(&self.conn_pool)
             .await?;
 
@@ -102,48 +146,858 @@ impl Store {
         Ok(rows)
     }
 
+    /// Gets the leaderboard of games played with other [settings](GameSettings) than the main leaderboard,
+    /// adding up the finished classic games every user played with those exact settings and scoring strategy
+    /// # Arguments
+    /// * settings - The filter, difficulty, guess mode and location source of the games
+    /// * scoring - How the games were scored
+    /// * num_users - How many users to get at most
+    /// # Returns:
+    /// Result<[Vec]<[LeaderBoardRow]>, [AppError]>
+    pub async fn get_settings_leaderboard(
+        &self,
+        settings: &GameSettings,
+        scoring: ScoringKind,
+        num_users: i64,
+    ) -> AppResult<Vec<LeaderBoardRow>> {
+        let rows = sqlx::query_as::<_, LeaderBoardRow>(
+            r#"
+                SELECT user_id AS id,
+                       CAST(DENSE_RANK() OVER (ORDER BY SUM(total_score) DESC) AS INTEGER) AS rank,
+                       CAST(SUM(total_score) AS INTEGER) AS total_score,
+                       CAST(SUM(num_rounds) AS INTEGER) AS num_guesses
+                FROM games
+                WHERE filter_key = $1 AND difficulty = $2 AND guess_mode = $3 AND location_source = $4 AND map_id IS NULL
+                      AND scoring = $5 AND mode = 'classic' AND finished_at IS NOT NULL
+                GROUP BY user_id
+                ORDER BY rank, user_id
+                LIMIT $6
+            "#,
+        )
+            .bind(settings.filter.key())
+            .bind(settings.difficulty)
+            .bind(settings.guess_mode)
+            .bind(settings.location_source)
+            .bind(scoring)
+            .bind(num_users)
+            .fetch_all(&self.conn_pool)
+            .await?;
+
+        Ok(rows)
+    }
 
 
 
 
-/// Updates and reorganizes the user_ranks table
+
+/// Updates and reorganizes the user_ranks table, adding a finished game to the users totals on the leaderboard of its scoring strategy.
+/// Runs on the given connection, so it can be part of a bigger transaction.
+/// # Arguments
+/// * conn - Connection or transaction to run the update on
+/// * score - Total score of the finished game
+/// * num_guesses - Number of guesses made in the game
+/// * id - Id of the user
+/// * scoring - How the game was scored
 /// # Returns
 /// * [Result]<(), [AppError]>
-///
-/// #
-pub async fn update_score(&self, score: f32, id: i32) -> AppResult<()> {
+pub async fn update_score(conn: &mut PgConnection, score: i32, num_guesses: i32, id: i32, scoring: ScoringKind) -> AppResult<()> {
 
     info!("Updating score table");
-    // Updating the table and organizing it
-    let res = sqlx::query(
+    // Users get their row the first time one of their games is finished, two games finishing
+    // at the same time both add to that one row
+    sqlx::query(
         r#"
-            UPDATE user_ranks SET total_score = total_score + $1, num_guesses = num_guesses + 1 WHERE id = $2
+            INSERT INTO user_ranks (id, total_score, num_guesses, scoring) VALUES ($3, $1, $2, $4)
+            ON CONFLICT (id, scoring) DO UPDATE
+            SET total_score = user_ranks.total_score + EXCLUDED.total_score,
+                num_guesses = user_ranks.num_guesses + EXCLUDED.num_guesses
             "#
     )
         .bind(score)
+        .bind(num_guesses)
         .bind(id)
-        .execute(&self.conn_pool)
+        .bind(scoring)
+        .execute(&mut *conn)
         .await?;
-    //TODO: determine if we need to comment or uncomment this as the rows affect by this query could be more than one
-    let rows_affected = res.rows_affected();
-    if rows_affected < 1 || rows_affected > 1 { // Should only ever affect one row, no more no less
-        return Err(AppError::InternalServerError);
-    }
 
     sqlx::query(
         r#"
             UPDATE user_ranks AS t SET rank = r.ranking from (
-                SELECT id, DENSE_RANK() OVER (ORDER BY total_score DESC) AS ranking FROM user_ranks
+                SELECT id, scoring, DENSE_RANK() OVER (PARTITION BY scoring ORDER BY total_score DESC) AS ranking FROM user_ranks
             )
-            AS r WHERE t.id = r.id
+            AS r WHERE t.id = r.id AND t.scoring = r.scoring
             "#
     )
-        .execute(&self.conn_pool)
+        .execute(&mut *conn)
         .await?;
 
     Ok(())
 }
 
+    /// Stores a freshly created game
+    /// # Returns
+    /// * [Result]<(), [AppError]>
+    pub async fn create_game(&self, game: &Game) -> AppResult<()> {
+        Self::insert_game(&self.conn_pool, game).await?;
+
+        Ok(())
+    }
+
+    /// Inserts a game, doing nothing if the user already has the daily challenge of that day
+    /// # Returns
+    /// * [Result]<u64, [AppError]> - The number of games inserted
+    async fn insert_game<'e, E>(executor: E, game: &Game) -> AppResult<u64>
+    where
+        E: sqlx::Executor<'e, Database = sqlx::Postgres>,
+    {
+        let res = sqlx::query(
+            r#"
+                INSERT INTO games (id, user_id, num_rounds, mode, scoring, total_score, created_at, daily_date,
+                                   location_filter, filter_key, difficulty, map_id, guess_mode, location_source)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+                ON CONFLICT (user_id, daily_date) WHERE daily_date IS NOT NULL DO NOTHING
+            "#,
+        )
+            .bind(game.id)
+            .bind(game.user_id)
+            .bind(game.num_rounds)
+            .bind(game.mode)
+            .bind(game.scoring)
+            .bind(game.total_score)
+            .bind(game.created_at)
+            .bind(game.daily_date)
+            .bind(sqlx::types::Json(&game.location_filter))
+            .bind(&game.filter_key)
+            .bind(game.difficulty)
+            .bind(game.map_id)
+            .bind(game.guess_mode)
+            .bind(game.location_source)
+            .execute(executor)
+            .await?;
+
+        Ok(res.rows_affected())
+    }
+
+    /// Gets the users game for the daily challenge of the given date, starting it if they haven't yet.
+    /// Opening the challenge twice at the same time still only ever creates one game.
+    /// # Returns
+    /// * [Result]<[Game], [AppError]>
+    pub async fn get_or_create_daily_game(&self, user_id: i32, date: NaiveDate) -> AppResult<Game> {
+        let new_game = Game::new_daily(user_id, date);
+        if Self::insert_game(&self.conn_pool, &new_game).await? == 1 {
+            info!("Started daily challenge {} for user {}", date, user_id);
+        }
+
+        let game = sqlx::query_as::<_, Game>(
+            r#"
+                SELECT * FROM games WHERE user_id = $1 AND daily_date = $2
+            "#,
+        )
+            .bind(user_id)
+            .bind(date)
+            .fetch_one(&self.conn_pool)
+            .await?;
+
+        Ok(game)
+    }
+
+    /// Gets the leaderboard of the daily challenge of the given date, only finished games are on it.
+    /// Ties are broken by whoever finished first.
+    /// # Returns
+    /// * [Result]<[Vec]<[DailyLeaderBoardRow]>, [AppError]>
+    pub async fn get_daily_leaderboard(&self, date: NaiveDate, num_users: i64) -> AppResult<Vec<DailyLeaderBoardRow>> {
+        let rows = sqlx::query_as::<_, DailyLeaderBoardRow>(
+            r#"
+                SELECT RANK() OVER (ORDER BY total_score DESC) AS rank, user_id, total_score, finished_at FROM games
+                WHERE daily_date = $1 AND finished_at IS NOT NULL
+                ORDER BY total_score DESC, finished_at
+                LIMIT $2
+            "#,
+        )
+            .bind(date)
+            .bind(num_users)
+            .fetch_all(&self.conn_pool)
+            .await?;
+
+        Ok(rows)
+    }
+
+    /// Gets every past day of the daily challenge that somebody finished, newest first
+    /// # Arguments
+    /// * before - Only days before this one are included, so the running challenge stays out of the archive
+    /// # Returns
+    /// * [Result]<[Vec]<[DailyArchiveEntry]>, [AppError]>
+    pub async fn get_daily_archive(&self, before: NaiveDate) -> AppResult<Vec<DailyArchiveEntry>> {
+        let entries = sqlx::query_as::<_, DailyArchiveEntry>(
+            r#"
+                SELECT daily_date, COUNT(*) AS num_players, MAX(total_score) AS best_score FROM games
+                WHERE daily_date < $1 AND finished_at IS NOT NULL
+                GROUP BY daily_date
+                ORDER BY daily_date DESC
+            "#,
+        )
+            .bind(before)
+            .fetch_all(&self.conn_pool)
+            .await?;
+
+        Ok(entries)
+    }
+
+    /// Looks up a game by its id
+    /// # Returns
+    /// * [Result]<[Option]<[Game]>, [AppError]>
+    pub async fn get_game(&self, id: Uuid) -> AppResult<Option<Game>> {
+        let game = sqlx::query_as::<_, Game>(
+            r#"
+                SELECT * FROM games WHERE id = $1
+            "#,
+        )
+            .bind(id)
+            .fetch_optional(&self.conn_pool)
+            .await?;
+
+        Ok(game)
+    }
+
+    /// Gets the most recent game of the given mode the user has not finished yet, if there is one
+    /// # Returns
+    /// * [Result]<[Option]<[Game]>, [AppError]>
+    pub async fn get_active_game(&self, user_id: i32, mode: GameMode) -> AppResult<Option<Game>> {
+        let game = sqlx::query_as::<_, Game>(
+            r#"
+                SELECT * FROM games
+                WHERE user_id = $1 AND mode = $2 AND finished_at IS NULL
+                ORDER BY created_at DESC LIMIT 1
+            "#,
+        )
+            .bind(user_id)
+            .bind(mode)
+            .fetch_optional(&self.conn_pool)
+            .await?;
+
+        Ok(game)
+    }
+
+    /// Stores a freshly issued round, the answer never leaves the server
+    /// # Returns
+    /// * [Result]<(), [AppError]>
+    pub async fn create_round(&self, round: &Round) -> AppResult<()> {
+        sqlx::query(
+            r#"
+                INSERT INTO rounds (id, game_id, round_number, user_id, city_id, created_at, expires_at, scoring,
+                                    target_name, target_region, target_lat, target_lng)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+            "#,
+        )
+            .bind(round.id)
+            .bind(round.game_id)
+            .bind(round.round_number)
+            .bind(round.user_id)
+            .bind(round.city_id)
+            .bind(round.created_at)
+            .bind(round.expires_at)
+            .bind(round.scoring)
+            .bind(&round.target_name)
+            .bind(&round.target_region)
+            .bind(round.target_lat)
+            .bind(round.target_lng)
+            .execute(&self.conn_pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Looks up a round by its opaque id
+    /// # Returns
+    /// * [Result]<[Option]<[Round]>, [AppError]>
+    pub async fn get_round(&self, id: Uuid) -> AppResult<Option<Round>> {
+        let round = sqlx::query_as::<_, Round>(
+            r#"
+                SELECT * FROM rounds WHERE id = $1
+            "#,
+        )
+            .bind(id)
+            .fetch_optional(&self.conn_pool)
+            .await?;
+
+        Ok(round)
+    }
+
+    /// Gets every round issued so far for a game, in the order they were played
+    /// # Returns
+    /// * [Result]<[Vec]<[Round]>, [AppError]>
+    pub async fn get_rounds_for_game(&self, game_id: Uuid) -> AppResult<Vec<Round>> {
+        let rounds = sqlx::query_as::<_, Round>(
+            r#"
+                SELECT * FROM rounds WHERE game_id = $1 ORDER BY round_number
+            "#,
+        )
+            .bind(game_id)
+            .fetch_all(&self.conn_pool)
+            .await?;
+
+        Ok(rounds)
+    }
+
+    /// Removes a round that was never answered, so it can be issued again with a new city
+    /// # Returns
+    /// * [Result]<(), [AppError]>
+    pub async fn delete_unanswered_round(&self, id: Uuid) -> AppResult<()> {
+        sqlx::query(
+            r#"
+                DELETE FROM rounds WHERE id = $1 AND answered_at IS NULL
+            "#,
+        )
+            .bind(id)
+            .execute(&self.conn_pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Records a hint taken for a round that is still open, adding what it costs to the penalty of the round.
+    /// Taking a hint a second time changes nothing, it is only paid for once.
+    ///
+    /// # Arguments
+    /// * penalty_percent - Percent of the best score the hint costs
+    ///
+    /// # Returns
+    /// * [Result]<(), [AppError]>
+    pub async fn add_hint(&self, round: &Round, kind: HintKind, penalty_percent: i32) -> AppResult<()> {
+        sqlx::query(
+            r#"
+                UPDATE rounds SET hints = array_append(hints, $2), hint_penalty = hint_penalty + $3
+                WHERE id = $1 AND answered_at IS NULL AND expires_at > now() AND NOT ($2 = ANY(hints))
+            "#,
+        )
+            .bind(round.id)
+            .bind(kind)
+            .bind(penalty_percent)
+            .execute(&self.conn_pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Records the guess for a round, adds its score to the game and stores it in the guess history,
+    /// all in one transaction.
+    /// The check on `answered_at` happens in the same statement that marks the round answered,
+    /// so two guesses racing for the same round can't both be scored.
+    /// When this was the last round the game is finished and, if it is [ranked](Game::is_ranked), its total goes onto the leaderboard.
+    ///
+    /// # Arguments
+    /// * guessed_region - Code of the country or state the guess fell in, for games guessing regions
+    ///
+    /// # Returns
+    /// * [Result]<[Game], [AppError]> - The game after this round was added
+    pub async fn answer_round(
+        &self,
+        round: &Round,
+        guess_lat: f32,
+        guess_lng: f32,
+        distance: f32,
+        score: i32,
+        guessed_region: Option<&str>,
+    ) -> AppResult<Game> {
+        let mut tx = self.conn_pool.begin().await?;
+
+        let res = sqlx::query(
+            r#"
+                UPDATE rounds SET answered_at = now(), guess_lat = $2, guess_lng = $3, distance = $4, score = $5,
+                    guessed_region = $6
+                WHERE id = $1 AND answered_at IS NULL AND expires_at > now()
+            "#,
+        )
+            .bind(round.id)
+            .bind(guess_lat)
+            .bind(guess_lng)
+            .bind(distance)
+            .bind(score)
+            .bind(guessed_region)
+            .execute(&mut *tx)
+            .await?;
+
+        if res.rows_affected() != 1 {
+            return Err(AppError::RoundAlreadyAnswered);
+        }
+
+        let game = sqlx::query_as::<_, Game>(
+            r#"
+                UPDATE games SET total_score = total_score + $2,
+                    finished_at = CASE WHEN $3 >= num_rounds THEN now() ELSE NULL END
+                WHERE id = $1
+                RETURNING *
+            "#,
+        )
+            .bind(round.game_id)
+            .bind(score)
+            .bind(round.round_number)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        sqlx::query(
+            r#"
+                INSERT INTO guesses (user_id, round_id, city_id, target_name, target_region, target_lat, target_lng,
+                                     guess_lat, guess_lng, distance, score, scoring, difficulty, game_mode)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+            "#,
+        )
+            .bind(round.user_id)
+            .bind(round.id)
+            .bind(round.city_id)
+            .bind(&round.target_name)
+            .bind(&round.target_region)
+            .bind(round.target_lat)
+            .bind(round.target_lng)
+            .bind(guess_lat)
+            .bind(guess_lng)
+            .bind(distance)
+            .bind(score)
+            .bind(round.scoring)
+            .bind(game.difficulty)
+            .bind(game.mode)
+            .execute(&mut *tx)
+            .await?;
+
+        if game.is_finished() && game.is_ranked() {
+            info!("Game {} finished, adding it to the leaderboard", game.id);
+            Self::update_score(&mut tx, game.total_score, game.num_rounds, game.user_id, game.scoring).await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(game)
+    }
+
+    /// Looks up a single guess from the guess history
+    /// # Returns
+    /// * [Result]<[Option]<[Guess]>, [AppError]>
+    pub async fn get_guess(&self, id: i32) -> AppResult<Option<Guess>> {
+        let guess = sqlx::query_as::<_, Guess>(
+            r#"
+                SELECT * FROM guesses WHERE id = $1
+            "#,
+        )
+            .bind(id)
+            .fetch_optional(&self.conn_pool)
+            .await?;
+
+        Ok(guess)
+    }
+
+    /// Gets a page of a users guess history, newest first
+    /// # Arguments
+    /// * user_id - The user whose guesses we want
+    /// * limit - How many guesses to return at most
+    /// * offset - How many of the newest guesses to skip
+    /// # Returns
+    /// * [Result]<[Vec]<[Guess]>, [AppError]>
+    pub async fn get_guesses_for_user(&self, user_id: i32, limit: i64, offset: i64) -> AppResult<Vec<Guess>> {
+        let guesses = sqlx::query_as::<_, Guess>(
+            r#"
+                SELECT * FROM guesses WHERE user_id = $1 ORDER BY created_at DESC, id DESC LIMIT $2 OFFSET $3
+            "#,
+        )
+            .bind(user_id)
+            .bind(limit)
+            .bind(offset)
+            .fetch_all(&self.conn_pool)
+            .await?;
+
+        Ok(guesses)
+    }
+
+    /// Gets a users whole guess history, oldest first. Used to compute their stats
+    /// # Returns
+    /// * [Result]<[Vec]<[Guess]>, [AppError]>
+    pub async fn get_all_guesses_for_user(&self, user_id: i32) -> AppResult<Vec<Guess>> {
+        let guesses = sqlx::query_as::<_, Guess>(
+            r#"
+                SELECT * FROM guesses WHERE user_id = $1 ORDER BY created_at, id
+            "#,
+        )
+            .bind(user_id)
+            .fetch_all(&self.conn_pool)
+            .await?;
+
+        Ok(guesses)
+    }
+
+    /// Counts every guess a user has ever made
+    /// # Returns
+    /// * [Result]<i64, [AppError]>
+    pub async fn count_guesses_for_user(&self, user_id: i32) -> AppResult<i64> {
+        let (count,): (i64,) = sqlx::query_as(
+            r#"
+                SELECT COUNT(*) FROM guesses WHERE user_id = $1
+            "#,
+        )
+            .bind(user_id)
+            .fetch_one(&self.conn_pool)
+            .await?;
+
+        Ok(count)
+    }
+
+    /// Gets the guesses made during a game, in the order the rounds were played
+    /// # Returns
+    /// * [Result]<[Vec]<[Guess]>, [AppError]>
+    pub async fn get_guesses_for_game(&self, game_id: Uuid) -> AppResult<Vec<Guess>> {
+        let guesses = sqlx::query_as::<_, Guess>(
+            r#"
+                SELECT g.* FROM guesses g JOIN rounds r ON r.id = g.round_id
+                WHERE r.game_id = $1
+                ORDER BY r.round_number
+            "#,
+        )
+            .bind(game_id)
+            .fetch_all(&self.conn_pool)
+            .await?;
+
+        Ok(guesses)
+    }
+
+    /// Writes cities into the `cities` table in one transaction.
+    /// Cities that are already there are updated, so seeding the same file twice changes nothing.
+    /// Cities missing from the list are kept, old rounds and guesses may still point at them.
+    ///
+    /// # Returns
+    /// * [Result]<u64, [AppError]> - The number of cities inserted or updated
+    pub async fn seed_cities(&self, cities: &[City]) -> AppResult<u64> {
+        let mut tx = self.conn_pool.begin().await?;
+        let mut seeded = 0;
+
+        for city in cities {
+            let res = sqlx::query(
+                r#"
+                    INSERT INTO cities (id, city, state, country_code, latitude, longitude, population, rank,
+                                        growth_from_2000_to_2013, alternate_names)
+                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+                    ON CONFLICT (id) DO UPDATE SET
+                        city = EXCLUDED.city,
+                        state = EXCLUDED.state,
+                        country_code = EXCLUDED.country_code,
+                        latitude = EXCLUDED.latitude,
+                        longitude = EXCLUDED.longitude,
+                        population = EXCLUDED.population,
+                        rank = EXCLUDED.rank,
+                        growth_from_2000_to_2013 = EXCLUDED.growth_from_2000_to_2013,
+                        alternate_names = EXCLUDED.alternate_names
+                "#,
+            )
+                .bind(city.id)
+                .bind(&city.city)
+                .bind(&city.state)
+                .bind(&city.country_code)
+                .bind(city.latitude)
+                .bind(city.longitude)
+                .bind(i64::try_from(city.population).unwrap_or(i64::MAX))
+                .bind(city.rank)
+                .bind(city.growth_from_2000_to_2013)
+                .bind(&city.alternate_names)
+                .execute(&mut *tx)
+                .await?;
+
+            seeded += res.rows_affected();
+        }
+
+        tx.commit().await?;
+
+        Ok(seeded)
+    }
+
+    /// Gets every city, most populated first
+    /// # Returns
+    /// * [Result]<[Vec]<[City]>, [AppError]>
+    pub async fn get_cities(&self) -> AppResult<Vec<City>> {
+        let cities = sqlx::query_as::<_, City>(
+            r#"
+                SELECT * FROM cities ORDER BY rank, id
+            "#,
+        )
+            .fetch_all(&self.conn_pool)
+            .await?;
+
+        Ok(cities)
+    }
+
+    /// Looks up a single city by its id
+    /// # Returns
+    /// * [Result]<[City], [AppError]> - [AppError::CityNotFound] when no city has that id
+    pub async fn get_city(&self, id: i32) -> AppResult<City> {
+        let city = sqlx::query_as::<_, City>(
+            r#"
+                SELECT * FROM cities WHERE id = $1
+            "#,
+        )
+            .bind(id)
+            .fetch_optional(&self.conn_pool)
+            .await?;
+
+        city.ok_or(AppError::CityNotFound)
+    }
+
+    /// How many cities are in the `cities` table
+    /// # Returns
+    /// * [Result]<i64, [AppError]>
+    pub async fn count_cities(&self) -> AppResult<i64> {
+        let count: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM cities")
+            .fetch_one(&self.conn_pool)
+            .await?;
+
+        Ok(count.0)
+    }
+
+    /// Stores a freshly created custom map
+    /// # Returns
+    /// * [Result]<(), [AppError]>
+    pub async fn create_map(&self, map: &CustomMap) -> AppResult<()> {
+        sqlx::query(
+            r#"
+                INSERT INTO custom_maps (id, owner_id, name, description, visibility, created_at, updated_at)
+                VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+        )
+            .bind(map.id)
+            .bind(map.owner_id)
+            .bind(&map.name)
+            .bind(&map.description)
+            .bind(map.visibility)
+            .bind(map.created_at)
+            .bind(map.updated_at)
+            .execute(&self.conn_pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Changes the name, description and visibility of a map
+    /// # Returns
+    /// * [Result]<(), [AppError]>
+    pub async fn update_map(&self, id: Uuid, name: &str, description: &str, visibility: Visibility) -> AppResult<()> {
+        sqlx::query(
+            r#"
+                UPDATE custom_maps SET name = $2, description = $3, visibility = $4, updated_at = now()
+                WHERE id = $1
+            "#,
+        )
+            .bind(id)
+            .bind(name)
+            .bind(description)
+            .bind(visibility)
+            .execute(&self.conn_pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Looks up a custom map by its id
+    /// # Returns
+    /// * [Result]<[Option]<[CustomMap]>, [AppError]>
+    pub async fn get_map(&self, id: Uuid) -> AppResult<Option<CustomMap>> {
+        let map = sqlx::query_as::<_, CustomMap>(
+            r#"
+                SELECT * FROM custom_maps WHERE id = $1
+            "#,
+        )
+            .bind(id)
+            .fetch_optional(&self.conn_pool)
+            .await?;
+
+        Ok(map)
+    }
+
+    /// Gets the public maps, most recently changed first
+    /// # Returns
+    /// * [Result]<[Vec]<[CustomMap]>, [AppError]>
+    pub async fn get_public_maps(&self, limit: i64) -> AppResult<Vec<CustomMap>> {
+        let maps = sqlx::query_as::<_, CustomMap>(
+            r#"
+                SELECT * FROM custom_maps WHERE visibility = 'public'
+                ORDER BY updated_at DESC LIMIT $1
+            "#,
+        )
+            .bind(limit)
+            .fetch_all(&self.conn_pool)
+            .await?;
+
+        Ok(maps)
+    }
+
+    /// Gets every map a user made, whatever its visibility, most recently changed first
+    /// # Returns
+    /// * [Result]<[Vec]<[CustomMap]>, [AppError]>
+    pub async fn get_maps_for_user(&self, owner_id: i32) -> AppResult<Vec<CustomMap>> {
+        let maps = sqlx::query_as::<_, CustomMap>(
+            r#"
+                SELECT * FROM custom_maps WHERE owner_id = $1
+                ORDER BY updated_at DESC
+            "#,
+        )
+            .bind(owner_id)
+            .fetch_all(&self.conn_pool)
+            .await?;
+
+        Ok(maps)
+    }
+
+    /// Adds locations to a map in one transaction, as long as the map stays within [MAX_LOCATIONS_PER_MAP].
+    /// The map row is locked while counting, so two requests adding at the same time can't both squeeze in.
+    /// # Returns
+    /// * [Result]<u64, [AppError]> - The number of locations added, [AppError::InvalidMap] when they don't fit
+    pub async fn add_map_locations(&self, map_id: Uuid, locations: &[NewMapLocation]) -> AppResult<u64> {
+        let mut tx = self.conn_pool.begin().await?;
+
+        sqlx::query("SELECT id FROM custom_maps WHERE id = $1 FOR UPDATE")
+            .bind(map_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or(AppError::MapNotFound)?;
+
+        let count: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM map_locations WHERE map_id = $1")
+            .bind(map_id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        if count.0 + locations.len() as i64 > MAX_LOCATIONS_PER_MAP {
+            return Err(AppError::InvalidMap(format!(
+                "A map can hold at most {} locations",
+                MAX_LOCATIONS_PER_MAP
+            )));
+        }
+
+        for location in locations {
+            sqlx::query(
+                r#"
+                    INSERT INTO map_locations (map_id, city_id, name, region, latitude, longitude)
+                    VALUES ($1, $2, $3, $4, $5, $6)
+                "#,
+            )
+                .bind(map_id)
+                .bind(location.city_id)
+                .bind(&location.name)
+                .bind(&location.region)
+                .bind(location.latitude)
+                .bind(location.longitude)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        sqlx::query("UPDATE custom_maps SET updated_at = now() WHERE id = $1")
+            .bind(map_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(locations.len() as u64)
+    }
+
+    /// Removes a location from a map. Rounds already played there keep their answer, see [Round::at_location]
+    /// # Returns
+    /// * [Result]<(), [AppError]>
+    pub async fn remove_map_location(&self, map_id: Uuid, location_id: i32) -> AppResult<()> {
+        sqlx::query(
+            r#"
+                DELETE FROM map_locations WHERE id = $1 AND map_id = $2
+            "#,
+        )
+            .bind(location_id)
+            .bind(map_id)
+            .execute(&self.conn_pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Gets every location on a map, in the order they were added
+    /// # Returns
+    /// * [Result]<[Vec]<[MapLocation]>, [AppError]>
+    pub async fn get_map_locations(&self, map_id: Uuid) -> AppResult<Vec<MapLocation>> {
+        let locations = sqlx::query_as::<_, MapLocation>(
+            r#"
+                SELECT * FROM map_locations WHERE map_id = $1 ORDER BY id
+            "#,
+        )
+            .bind(map_id)
+            .fetch_all(&self.conn_pool)
+            .await?;
+
+        Ok(locations)
+    }
+
+    /// Gets the leaderboard of a custom map, ranking every user by the best game they finished on it.
+    /// Maps have few locations, adding up the games would let anybody climb the board by replaying the same places.
+    /// Only games scored the given way are compared
+    /// # Returns:
+    /// Result<[Vec]<[LeaderBoardRow]>, [AppError]>
+    pub async fn get_map_leaderboard(&self, map_id: Uuid, scoring: ScoringKind, num_users: i64) -> AppResult<Vec<LeaderBoardRow>> {
+        let rows = sqlx::query_as::<_, LeaderBoardRow>(
+            r#"
+                SELECT user_id AS id,
+                       CAST(DENSE_RANK() OVER (ORDER BY MAX(total_score) DESC) AS INTEGER) AS rank,
+                       CAST(MAX(total_score) AS INTEGER) AS total_score,
+                       CAST(SUM(num_rounds) AS INTEGER) AS num_guesses
+                FROM games
+                WHERE map_id = $1 AND scoring = $2 AND finished_at IS NOT NULL
+                GROUP BY user_id
+                ORDER BY rank, user_id
+                LIMIT $3
+            "#,
+        )
+            .bind(map_id)
+            .bind(scoring)
+            .bind(num_users)
+            .fetch_all(&self.conn_pool)
+            .await?;
+
+        Ok(rows)
+    }
+
+    /// Gets the preferences of a user, the defaults when they never saved any
+    /// # Returns
+    /// * [Result]<[UserPreferences], [AppError]>
+    pub async fn get_preferences(&self, user_id: i32) -> AppResult<UserPreferences> {
+        let preferences = sqlx::query_as::<_, UserPreferences>(
+            r#"
+                SELECT user_id, distance_unit, map_type, difficulty, guess_mode
+                FROM user_preferences WHERE user_id = $1
+            "#,
+        )
+            .bind(user_id)
+            .fetch_optional(&self.conn_pool)
+            .await?;
+
+        Ok(preferences.unwrap_or_else(|| UserPreferences::defaults(user_id)))
+    }
+
+    /// Saves the preferences of a user, replacing the ones saved before
+    /// # Returns
+    /// * [Result]<(), [AppError]>
+    pub async fn save_preferences(&self, preferences: &UserPreferences) -> AppResult<()> {
+        sqlx::query(
+            r#"
+                INSERT INTO user_preferences (user_id, distance_unit, map_type, difficulty, guess_mode)
+                VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT (user_id) DO UPDATE SET
+                    distance_unit = EXCLUDED.distance_unit,
+                    map_type = EXCLUDED.map_type,
+                    difficulty = EXCLUDED.difficulty,
+                    guess_mode = EXCLUDED.guess_mode,
+                    updated_at = now()
+            "#,
+        )
+            .bind(preferences.user_id)
+            .bind(preferences.distance_unit)
+            .bind(preferences.map_type)
+            .bind(preferences.difficulty)
+            .bind(preferences.guess_mode)
+            .execute(&self.conn_pool)
+            .await?;
+
+        Ok(())
+    }
+
 /*
     /// TODO: somehow make this more efficient? Maybe use the current user rank and only look at things above it since it will never go below?
     pub async fn get_rank_from_score(&self, score: i32) -> Result<i32, AppError> {
@@ -7,7 +7,7 @@ use image::ImageError;
 use serde_json::json;
 use sqlx::Error;
 use std::fmt::Debug;
-use std::num::ParseFloatError;
+use std::num::{ParseFloatError, ParseIntError};
 use std::string::ParseError;
 
 /// Handling all our errors that the backend could run into
@@ -24,11 +24,46 @@ pub enum AppError {
     InternalServerError,
     InvalidPassword,
 
+    /// Round errors
+    RoundNotFound,
+    RoundExpired,
+    RoundAlreadyAnswered,
+
+    /// City errors
+    CityNotFound,
+
+    /// Game errors
+    GameNotFound,
+    DailyNotFound,
+
+    /// Game setting errors, with what was wrong with them
+    InvalidFilter(String),
+    InvalidDifficulty(String),
+    InvalidGuessMode(String),
+    InvalidLocationSource(String),
+
+    /// Boundary file errors, with what was wrong with the file
+    InvalidBoundaries(String),
+
+    /// Settings page errors, with what was wrong with the preferences
+    InvalidPreferences(String),
+
+    /// Hint errors, with why the hint can't be taken
+    InvalidHint(String),
+
+    /// Custom map errors
+    MapNotFound,
+    InvalidMap(String),
+
     RequestError(reqwest::Error),
     ImageError(ImageError),
     DecodeError(DecodeError),
 
     ParseError(ParseFloatError),
+    ParseIntError(ParseIntError),
+
+    IoError(std::io::Error),
+    JsonError(serde_json::Error),
 
     Any(anyhow::Error),
 }
@@ -64,6 +99,33 @@ impl IntoResponse for AppError {
                 StatusCode::UNAUTHORIZED,
                 "Invalid username or password".to_string(),
             ),
+            AppError::RoundNotFound => (StatusCode::NOT_FOUND, "Round not found.".to_string()),
+            AppError::RoundExpired => (
+                StatusCode::GONE,
+                "This round has expired, start a new one.".to_string(),
+            ),
+            AppError::RoundAlreadyAnswered => (
+                StatusCode::CONFLICT,
+                "This round has already been answered.".to_string(),
+            ),
+            AppError::CityNotFound => (StatusCode::NOT_FOUND, "City not found.".to_string()),
+            AppError::GameNotFound => (StatusCode::NOT_FOUND, "Game not found.".to_string()),
+            AppError::DailyNotFound => (
+                StatusCode::NOT_FOUND,
+                "There is no daily challenge for that day yet.".to_string(),
+            ),
+            AppError::InvalidFilter(message) => (StatusCode::BAD_REQUEST, message),
+            AppError::InvalidDifficulty(message) => (StatusCode::BAD_REQUEST, message),
+            AppError::InvalidGuessMode(message) => (StatusCode::BAD_REQUEST, message),
+            AppError::InvalidLocationSource(message) => (StatusCode::BAD_REQUEST, message),
+            AppError::InvalidBoundaries(message) => {
+                let message = format!("Boundary error: {}", message);
+                (StatusCode::INTERNAL_SERVER_ERROR, message)
+            }
+            AppError::InvalidPreferences(message) => (StatusCode::BAD_REQUEST, message),
+            AppError::InvalidHint(message) => (StatusCode::BAD_REQUEST, message),
+            AppError::MapNotFound => (StatusCode::NOT_FOUND, "Map not found.".to_string()),
+            AppError::InvalidMap(message) => (StatusCode::BAD_REQUEST, message),
             AppError::RequestError(err) => {
                 let message = format!("Error making request: {}", err);
                 (StatusCode::BAD_REQUEST, message)
@@ -80,6 +142,18 @@ impl IntoResponse for AppError {
                 let message = format!("Parsing Distance error: {}", err);
                 (StatusCode::SERVICE_UNAVAILABLE, message)
             }
+            AppError::ParseIntError(err) => {
+                let message = format!("Parsing error: {}", err);
+                (StatusCode::INTERNAL_SERVER_ERROR, message)
+            }
+            AppError::IoError(err) => {
+                let message = format!("File error: {}", err);
+                (StatusCode::INTERNAL_SERVER_ERROR, message)
+            }
+            AppError::JsonError(err) => {
+                let message = format!("Json error: {}", err);
+                (StatusCode::INTERNAL_SERVER_ERROR, message)
+            }
         };
 
         let body = Json(json!({"error": error_message}));
@@ -117,3 +191,21 @@ impl From<ParseFloatError> for AppError {
         AppError::ParseError(value)
     }
 }
+
+impl From<ParseIntError> for AppError {
+    fn from(value: ParseIntError) -> Self {
+        AppError::ParseIntError(value)
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(value: std::io::Error) -> Self {
+        AppError::IoError(value)
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(value: serde_json::Error) -> Self {
+        AppError::JsonError(value)
+    }
+}
@@ -0,0 +1,384 @@
+//! Distances, bearings and points on the earth, in `f64`.
+//!
+//! Every function takes and gives latitudes and longitudes in degrees, latitudes between -90 and 90 and longitudes
+//! between -180 and 180, and distances in kilometers.
+//! The spherical functions treat the earth as a ball with the [mean radius](EARTH_RADIUS_KM), which is off by up to
+//! half a percent. [distance_km] follows the [WGS84](https://en.wikipedia.org/wiki/World_Geodetic_System) ellipsoid,
+//! the shape GPS and the map providers use, and is the one scores are computed with.
+
+use std::f64::consts::PI;
+
+/// Mean radius of the earth
+pub const EARTH_RADIUS_KM: f64 = 6371.0;
+
+pub const KM_TO_MILES: f64 = 0.621371;
+
+/// Radius of the [WGS84](https://en.wikipedia.org/wiki/World_Geodetic_System) ellipsoid at the equator
+pub const WGS84_EQUATORIAL_RADIUS_KM: f64 = 6378.137;
+
+/// How much flatter the [WGS84](https://en.wikipedia.org/wiki/World_Geodetic_System) ellipsoid is at the poles
+pub const WGS84_FLATTENING: f64 = 1.0 / 298.257223563;
+
+/// Most iterations [vincenty_km] runs before giving up, only points on nearly opposite sides of the earth need more than a handful
+const VINCENTY_MAX_ITERATIONS: usize = 200;
+
+/// Change in longitude on the auxiliary sphere at which [vincenty_km] counts as converged, about 0.06 mm on the ground
+const VINCENTY_TOLERANCE: f64 = 1e-12;
+
+/// The great circle distance between two points on a spherical earth, using the
+/// [haversine formula](https://en.wikipedia.org/wiki/Haversine_formula)
+///
+/// # Arguments:
+/// * lat1: f64 - [Latitude](https://en.wikipedia.org/wiki/Latitude) of the first point
+/// * lng1: f64 - [Longitude](https://en.wikipedia.org/wiki/Longitude) of the first point
+/// * lat2: f64 - Latitude of the second point
+/// * lng2: f64 - Longitude of the second point
+///
+/// # Returns:
+/// [f64] - Kilometers between the points, between 0 and half the circumference of the earth
+pub fn haversine_km(lat1: f64, lng1: f64, lat2: f64, lng2: f64) -> f64 {
+    EARTH_RADIUS_KM * central_angle(lat1, lng1, lat2, lng2)
+}
+
+/// The distance between two points on the WGS84 ellipsoid, using
+/// [Vincenty's inverse formula](https://en.wikipedia.org/wiki/Vincenty%27s_formulae), accurate to well under a millimeter.
+///
+/// # Returns:
+/// [Option]<[f64]> - Kilometers between the points, none when the formula doesn't converge,
+/// which only happens for points on nearly opposite sides of the earth
+pub fn vincenty_km(lat1: f64, lng1: f64, lat2: f64, lng2: f64) -> Option<f64> {
+    let a = WGS84_EQUATORIAL_RADIUS_KM;
+    let f = WGS84_FLATTENING;
+    let b = (1.0 - f) * a;
+
+    // Latitudes on the auxiliary sphere
+    let u1 = ((1.0 - f) * lat1.to_radians().tan()).atan();
+    let u2 = ((1.0 - f) * lat2.to_radians().tan()).atan();
+    let (sin_u1, cos_u1) = u1.sin_cos();
+    let (sin_u2, cos_u2) = u2.sin_cos();
+
+    let l = normalize_radians(lng2.to_radians() - lng1.to_radians());
+    let mut lambda = l;
+
+    for _ in 0..VINCENTY_MAX_ITERATIONS {
+        let (sin_lambda, cos_lambda) = lambda.sin_cos();
+
+        let sin_sigma = ((cos_u2 * sin_lambda).powi(2) + (cos_u1 * sin_u2 - sin_u1 * cos_u2 * cos_lambda).powi(2)).sqrt();
+        if sin_sigma == 0.0 {
+            // The same point
+            return Some(0.0);
+        }
+        let cos_sigma = sin_u1 * sin_u2 + cos_u1 * cos_u2 * cos_lambda;
+        let sigma = sin_sigma.atan2(cos_sigma);
+
+        let sin_alpha = cos_u1 * cos_u2 * sin_lambda / sin_sigma;
+        let cos_sq_alpha = 1.0 - sin_alpha * sin_alpha;
+
+        // Both points on the equator make the geodesic run along it
+        let cos_2sigma_m = if cos_sq_alpha == 0.0 {
+            0.0
+        } else {
+            cos_sigma - 2.0 * sin_u1 * sin_u2 / cos_sq_alpha
+        };
+
+        let c = f / 16.0 * cos_sq_alpha * (4.0 + f * (4.0 - 3.0 * cos_sq_alpha));
+        let previous = lambda;
+        lambda = l
+            + (1.0 - c)
+                * f
+                * sin_alpha
+                * (sigma + c * sin_sigma * (cos_2sigma_m + c * cos_sigma * (-1.0 + 2.0 * cos_2sigma_m.powi(2))));
+
+        if lambda.abs() > PI {
+            return None;
+        }
+
+        if (lambda - previous).abs() < VINCENTY_TOLERANCE {
+            let u_sq = cos_sq_alpha * (a * a - b * b) / (b * b);
+            let big_a = 1.0 + u_sq / 16384.0 * (4096.0 + u_sq * (-768.0 + u_sq * (320.0 - 175.0 * u_sq)));
+            let big_b = u_sq / 1024.0 * (256.0 + u_sq * (-128.0 + u_sq * (74.0 - 47.0 * u_sq)));
+            let delta_sigma = big_b
+                * sin_sigma
+                * (cos_2sigma_m
+                    + big_b / 4.0
+                        * (cos_sigma * (-1.0 + 2.0 * cos_2sigma_m.powi(2))
+                            - big_b / 6.0
+                                * cos_2sigma_m
+                                * (-3.0 + 4.0 * sin_sigma.powi(2))
+                                * (-3.0 + 4.0 * cos_2sigma_m.powi(2))));
+
+            return Some(b * big_a * (sigma - delta_sigma));
+        }
+    }
+
+    None
+}
+
+/// The distance between two points on the WGS84 ellipsoid, see [vincenty_km].
+/// The few pairs of nearly opposite points it can't handle get the [spherical distance](haversine_km) instead,
+/// which is within half a percent.
+///
+/// # Returns:
+/// [f64] - Kilometers between the points
+pub fn distance_km(lat1: f64, lng1: f64, lat2: f64, lng2: f64) -> f64 {
+    vincenty_km(lat1, lng1, lat2, lng2).unwrap_or_else(|| haversine_km(lat1, lng1, lat2, lng2))
+}
+
+/// The direction to head in from the first point to get to the second one along the great circle between them.
+/// The direction changes along the way, except when heading straight north, south or along the equator.
+///
+/// # Returns:
+/// [f64] - Degrees clockwise from north, from 0 up to but not including 360. 0 for two points in the same place
+pub fn initial_bearing(lat1: f64, lng1: f64, lat2: f64, lng2: f64) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let d_lng = (lng2 - lng1).to_radians();
+
+    let y = d_lng.sin() * lat2.cos();
+    let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * d_lng.cos();
+
+    normalize_bearing(y.atan2(x).to_degrees())
+}
+
+/// The point halfway along the great circle between two points
+///
+/// # Returns:
+/// (f64, f64) - Latitude and longitude of the midpoint
+pub fn midpoint(lat1: f64, lng1: f64, lat2: f64, lng2: f64) -> (f64, f64) {
+    intermediate_point(lat1, lng1, lat2, lng2, 0.5)
+}
+
+/// The point a fraction of the way along the great circle between two points, 0 being the first point and 1 the second.
+/// Points on exactly opposite sides of the earth have no single great circle between them, the one heading north is used.
+///
+/// # Returns:
+/// (f64, f64) - Latitude and longitude of the point
+pub fn intermediate_point(lat1: f64, lng1: f64, lat2: f64, lng2: f64, fraction: f64) -> (f64, f64) {
+    let angle = central_angle(lat1, lng1, lat2, lng2);
+    if angle == 0.0 {
+        return (lat1, lng1);
+    }
+    if (PI - angle).abs() < 1e-9 {
+        return destination(lat1, lng1, 0.0, fraction * angle * EARTH_RADIUS_KM);
+    }
+
+    let (phi1, lambda1) = (lat1.to_radians(), lng1.to_radians());
+    let (phi2, lambda2) = (lat2.to_radians(), lng2.to_radians());
+
+    let a = ((1.0 - fraction) * angle).sin() / angle.sin();
+    let b = (fraction * angle).sin() / angle.sin();
+
+    let x = a * phi1.cos() * lambda1.cos() + b * phi2.cos() * lambda2.cos();
+    let y = a * phi1.cos() * lambda1.sin() + b * phi2.cos() * lambda2.sin();
+    let z = a * phi1.sin() + b * phi2.sin();
+
+    (
+        z.atan2((x * x + y * y).sqrt()).to_degrees(),
+        normalize_longitude(y.atan2(x).to_degrees()),
+    )
+}
+
+/// Points spread evenly along the great circle between two points, both ends included, for drawing the shortest way between them
+///
+/// # Arguments:
+/// * segments: usize - How many pieces the line is cut into, at least 1
+///
+/// # Returns:
+/// [Vec]<(f64, f64)> - `segments + 1` latitudes and longitudes, starting at the first point
+pub fn great_circle(lat1: f64, lng1: f64, lat2: f64, lng2: f64, segments: usize) -> Vec<(f64, f64)> {
+    let segments = segments.max(1);
+
+    (0..=segments)
+        .map(|step| intermediate_point(lat1, lng1, lat2, lng2, step as f64 / segments as f64))
+        .collect()
+}
+
+/// Where you end up heading out from a point in the given direction for the given distance along a great circle
+///
+/// # Arguments:
+/// * bearing: f64 - Degrees clockwise from north to head in
+/// * distance_km: f64 - How far to go
+///
+/// # Returns:
+/// (f64, f64) - Latitude and longitude of the point reached
+pub fn destination(lat: f64, lng: f64, bearing: f64, distance_km: f64) -> (f64, f64) {
+    let angle = distance_km / EARTH_RADIUS_KM;
+    let (phi, lambda) = (lat.to_radians(), lng.to_radians());
+    let theta = bearing.to_radians();
+
+    let sin_phi2 = (phi.sin() * angle.cos() + phi.cos() * angle.sin() * theta.cos()).clamp(-1.0, 1.0);
+    let phi2 = sin_phi2.asin();
+    let lambda2 = lambda + (theta.sin() * angle.sin() * phi.cos()).atan2(angle.cos() - phi.sin() * sin_phi2);
+
+    (phi2.to_degrees(), normalize_longitude(lambda2.to_degrees()))
+}
+
+/// Angle between two points as seen from the center of the earth, in radians
+fn central_angle(lat1: f64, lng1: f64, lat2: f64, lng2: f64) -> f64 {
+    let d_lat = (lat2 - lat1).to_radians();
+    let d_lng = (lng2 - lng1).to_radians();
+
+    let a = (d_lat / 2.0).sin().powi(2) + lat1.to_radians().cos() * lat2.to_radians().cos() * (d_lng / 2.0).sin().powi(2);
+
+    2.0 * a.sqrt().atan2((1.0 - a).max(0.0).sqrt())
+}
+
+/// Wraps an angle in radians into -π up to π
+fn normalize_radians(angle: f64) -> f64 {
+    (angle + PI).rem_euclid(2.0 * PI) - PI
+}
+
+/// Wraps a longitude into -180 up to 180
+fn normalize_longitude(longitude: f64) -> f64 {
+    (longitude + 180.0).rem_euclid(360.0) - 180.0
+}
+
+/// Wraps a bearing into 0 up to 360
+fn normalize_bearing(bearing: f64) -> f64 {
+    let bearing = bearing.rem_euclid(360.0);
+
+    // Tiny negative angles come out of rem_euclid as exactly 360
+    if bearing >= 360.0 {
+        0.0
+    } else {
+        bearing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// Degrees, minutes and seconds
+    fn dms(degrees: f64, minutes: f64, seconds: f64) -> f64 {
+        degrees.signum() * (degrees.abs() + minutes / 60.0 + seconds / 3600.0)
+    }
+
+    fn latitude() -> impl Strategy<Value = f64> {
+        -90.0..=90.0f64
+    }
+
+    fn longitude() -> impl Strategy<Value = f64> {
+        -180.0..180.0f64
+    }
+
+    #[test]
+    fn vincenty_matches_reference_distances() {
+        // Flinders Peak to Buninyong, the worked example from Vincenty's paper
+        let flinders = (dms(-37.0, 57.0, 3.72030), dms(144.0, 25.0, 29.52440));
+        let buninyong = (dms(-37.0, 39.0, 10.15610), dms(143.0, 55.0, 35.38390));
+        let distance = vincenty_km(flinders.0, flinders.1, buninyong.0, buninyong.1).unwrap();
+        assert!((distance - 54.972271).abs() < 1e-6, "{}", distance);
+
+        // JFK to LHR, from the GeographicLib documentation
+        let distance = vincenty_km(40.6, -73.8, 51.6, -0.5).unwrap();
+        assert!((distance - 5551.759400).abs() < 1e-5, "{}", distance);
+
+        // A quarter meridian and a degree along the equator
+        let distance = vincenty_km(0.0, 0.0, 90.0, 0.0).unwrap();
+        assert!((distance - 10001.965729).abs() < 1e-5, "{}", distance);
+        let distance = vincenty_km(0.0, 0.0, 0.0, 1.0).unwrap();
+        assert!((distance - 111.319491).abs() < 1e-5, "{}", distance);
+    }
+
+    #[test]
+    fn haversine_matches_reference_distances() {
+        assert!((haversine_km(0.0, 0.0, 0.0, 90.0) - PI / 2.0 * EARTH_RADIUS_KM).abs() < 1e-9);
+        assert!((haversine_km(90.0, 0.0, -90.0, 0.0) - PI * EARTH_RADIUS_KM).abs() < 1e-9);
+        assert!((haversine_km(51.5007, 0.1246, 40.6892, 74.0445) - 5574.840456848555).abs() < 1e-6);
+    }
+
+    #[test]
+    fn bearing_and_destination_match_reference_values() {
+        assert!((initial_bearing(0.0, 0.0, 10.0, 0.0) - 0.0).abs() < 1e-9);
+        assert!((initial_bearing(0.0, 0.0, 0.0, 10.0) - 90.0).abs() < 1e-9);
+        assert!((initial_bearing(0.0, 0.0, -10.0, 0.0) - 180.0).abs() < 1e-9);
+        assert!((initial_bearing(0.0, 0.0, 0.0, -10.0) - 270.0).abs() < 1e-9);
+
+        let (lat, lng) = destination(0.0, 0.0, 90.0, PI / 2.0 * EARTH_RADIUS_KM);
+        assert!(lat.abs() < 1e-9 && (lng - 90.0).abs() < 1e-9);
+
+        let (lat, lng) = midpoint(0.0, 170.0, 0.0, -170.0);
+        assert!(lat.abs() < 1e-9 && (lng.abs() - 180.0).abs() < 1e-9, "{} {}", lat, lng);
+    }
+
+    #[test]
+    fn nearly_opposite_points_still_get_a_distance() {
+        assert!(vincenty_km(0.0, 0.0, 0.5, 179.7).is_none());
+
+        let distance = distance_km(0.0, 0.0, 0.5, 179.7);
+        assert!(distance > 19_900.0 && distance < 20_040.0, "{}", distance);
+    }
+
+    proptest! {
+        #[test]
+        fn distances_are_symmetric(lat1 in latitude(), lng1 in longitude(), lat2 in latitude(), lng2 in longitude()) {
+            prop_assert!((haversine_km(lat1, lng1, lat2, lng2) - haversine_km(lat2, lng2, lat1, lng1)).abs() < 1e-6);
+            prop_assert!((distance_km(lat1, lng1, lat2, lng2) - distance_km(lat2, lng2, lat1, lng1)).abs() < 1e-6);
+        }
+
+        #[test]
+        fn distances_stay_on_the_earth(lat1 in latitude(), lng1 in longitude(), lat2 in latitude(), lng2 in longitude()) {
+            let distance = haversine_km(lat1, lng1, lat2, lng2);
+            prop_assert!((0.0..=PI * EARTH_RADIUS_KM + 1e-6).contains(&distance));
+
+            // Half the polar circumference of the ellipsoid is the furthest two points get
+            let distance = distance_km(lat1, lng1, lat2, lng2);
+            prop_assert!((0.0..=20_004.0).contains(&distance));
+        }
+
+        #[test]
+        fn ellipsoid_stays_close_to_the_sphere(lat1 in latitude(), lng1 in longitude(), lat2 in latitude(), lng2 in longitude()) {
+            let sphere = haversine_km(lat1, lng1, lat2, lng2);
+            let ellipsoid = distance_km(lat1, lng1, lat2, lng2);
+            prop_assert!((ellipsoid - sphere).abs() <= sphere * 0.0056 + 1e-6, "{} vs {}", ellipsoid, sphere);
+        }
+
+        #[test]
+        fn triangle_inequality_holds(
+            lat1 in latitude(), lng1 in longitude(),
+            lat2 in latitude(), lng2 in longitude(),
+            lat3 in latitude(), lng3 in longitude(),
+        ) {
+            let direct = haversine_km(lat1, lng1, lat3, lng3);
+            let detour = haversine_km(lat1, lng1, lat2, lng2) + haversine_km(lat2, lng2, lat3, lng3);
+            prop_assert!(direct <= detour + 1e-6);
+        }
+
+        #[test]
+        fn destination_is_as_far_as_asked(lat in -89.0..89.0f64, lng in longitude(), bearing in 0.0..360.0f64, distance in 0.0..20_000.0f64) {
+            let (lat2, lng2) = destination(lat, lng, bearing, distance);
+            prop_assert!((-90.0..=90.0).contains(&lat2) && (-180.0..180.0).contains(&lng2));
+            prop_assert!((haversine_km(lat, lng, lat2, lng2) - distance).abs() < 1e-6, "{} {}", lat2, lng2);
+        }
+
+        #[test]
+        fn bearing_points_at_the_destination(lat in -80.0..80.0f64, lng in longitude(), bearing in 0.0..360.0f64, distance in 1.0..10_000.0f64) {
+            let (lat2, lng2) = destination(lat, lng, bearing, distance);
+            let back = initial_bearing(lat, lng, lat2, lng2);
+            let difference = (back - bearing).abs();
+            prop_assert!(difference.min(360.0 - difference) < 1e-6, "{} vs {}", back, bearing);
+        }
+
+        #[test]
+        fn midpoint_is_halfway(lat1 in latitude(), lng1 in longitude(), lat2 in latitude(), lng2 in longitude()) {
+            let (lat, lng) = midpoint(lat1, lng1, lat2, lng2);
+            let total = haversine_km(lat1, lng1, lat2, lng2);
+            prop_assert!((haversine_km(lat1, lng1, lat, lng) - total / 2.0).abs() < 1e-5);
+            prop_assert!((haversine_km(lat, lng, lat2, lng2) - total / 2.0).abs() < 1e-5);
+        }
+
+        #[test]
+        fn great_circle_connects_both_ends(lat1 in latitude(), lng1 in longitude(), lat2 in latitude(), lng2 in longitude(), segments in 1usize..50) {
+            let points = great_circle(lat1, lng1, lat2, lng2, segments);
+            prop_assert_eq!(points.len(), segments + 1);
+
+            let (first, last) = (points[0], points[segments]);
+            prop_assert!(haversine_km(first.0, first.1, lat1, lng1) < 1e-6);
+            prop_assert!(haversine_km(last.0, last.1, lat2, lng2) < 1e-6);
+
+            let length: f64 = points.windows(2).map(|pair| haversine_km(pair[0].0, pair[0].1, pair[1].0, pair[1].1)).sum();
+            prop_assert!((length - haversine_km(lat1, lng1, lat2, lng2)).abs() < 1e-5);
+        }
+    }
+}
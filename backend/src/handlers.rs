@@ -1,29 +1,52 @@
 use argon2::Config;
 
-use axum::extract::State;
-use axum::response::Html;
-use axum::Form;
-use http::header::{LOCATION, SET_COOKIE};
-use http::{HeaderValue, Response, StatusCode};
+use axum::extract::{Multipart, Path, Query, State};
+use axum::response::{Html, IntoResponse};
+use axum::{Form, Json};
+use http::header::{CACHE_CONTROL, CONTENT_DISPOSITION, CONTENT_TYPE, ETAG, IF_NONE_MATCH, LOCATION, SET_COOKIE};
+use http::{HeaderMap, HeaderValue, Response, StatusCode};
 use hyper::Body;
 
 use jsonwebtoken::Header;
 
 use tera::Context;
 use tracing::{error, info};
+use uuid::Uuid;
+use chrono::NaiveDate;
+use std::collections::HashSet;
+use std::future::Future;
+use rand::seq::SliceRandom;
 
+use crate::catalog::{Catalog, CityCatalog};
 use crate::db::Store;
 
 use crate::error::AppError;
-
-use crate::models::city::City;
-use crate::{get_score_from_distance, get_timestamp_after_8_hours, haversine_distance};
-
-use crate::models::city_with_image::CityAndImage;
+use crate::AppResult;
+use crate::imagery::prefetch::RoundPool;
+use crate::imagery::{Imagery, ImageryStats, MapType, ResultMapRequest};
+use crate::map_files::{self, MapFile, MapFileFormat};
+use crate::regions::land::NEAR_CITY_RADIUS_KM;
+use crate::regions::{Boundaries, BoundarySet, MIN_REGIONS};
+
+use crate::{geodesy, get_timestamp_after_8_hours, haversine_distance};
+
+use crate::models::custom_map::{CustomMap, MapForm, MapLocation, MapLocationForm, MapPage, MapSearchQuery, MAX_NAME_LENGTH};
+use crate::models::daily::{today, DailyLeaderBoard};
+use crate::models::difficulty::Difficulty;
+use crate::models::filter::LocationFilter;
+use crate::models::game::{Game, GameMode, GameSettingsForm, GameSummary};
+use crate::models::guess_mode::GuessMode;
+use crate::models::hint::{HintForm, HintKind, HintPage, ZOOM_OUT_LEVELS};
 use crate::models::leaderboard::LeaderBoard;
 use crate::models::location::Location;
-use crate::models::maps::StaticGuessMap;
-use crate::models::page::DistancePage;
+use crate::models::location_source::LocationSource;
+use crate::models::preferences::{DistanceUnit, PreferencesForm};
+use crate::models::page::{
+    CityPage, DistancePage, FormatQuery, NearbyCitiesPage, NearbyQuery, NearestCityPage, PagePackage, ResponseFormat,
+};
+use crate::models::city::City;
+use crate::models::round::{Round, EXCLUSION_RADIUS_KM};
+use crate::models::stats::UserStats;
 use crate::models::user::{Claims, OptionalClaims, User, UserSignup, KEYS};
 
 use crate::template::TEMPLATES;
@@ -39,7 +62,10 @@ use crate::template::TEMPLATES;
 /// * [State](State)
 /// * [OptionalClaims](OptionalClaims)
 pub async fn root(
-    State(_database): State<Store>,
+    State(database): State<Store>,
+    State(catalog): State<Catalog>,
+    State(boundaries): State<Boundaries>,
+    State(round_pool): State<RoundPool>,
     OptionalClaims(claims): OptionalClaims,
 ) -> Result<Html<String>, AppError> {
     let mut context = Context::new();
@@ -50,8 +76,37 @@ pub async fn root(
 
         info!("IS logged in is true");
 
-        let page = CityAndImage::get_city_in_page_form().await?;
+        let preferences = database.get_preferences(claims_data.id).await?;
+
+        let game = match database.get_active_game(claims_data.id, GameMode::Classic).await? {
+            Some(game) => game,
+            None => {
+                // Played the way the user prefers, a region mode that can't be played falls back to pinpointing
+                let game = Game::new(claims_data.id, GameMode::Classic)
+                    .with_difficulty(preferences.difficulty)
+                    .with_guess_mode(preferences.guess_mode);
+                let game = match check_guess_mode(&catalog, &boundaries, &game) {
+                    Ok(()) => game,
+                    Err(_) => game.with_guess_mode(GuessMode::Point),
+                };
+                database.create_game(&game).await?;
+                info!("Started game {} for user {}", game.id, claims_data.id);
+                game
+            }
+        };
+
+        // The answer is kept server side, the page only ever sees the round id
+        let round = next_round_in_game(&database, &round_pool, &catalog, &boundaries, &game).await?;
+
+        let page = PagePackage::new(round.id, preferences.map_type).with_hints(&round);
         context.insert("page", &page);
+        context.insert("filter_description", &game.location_filter.describe());
+        if let Some(map_id) = game.map_id {
+            context.insert("map", &database.get_map(map_id).await?);
+        }
+        context.insert("game", &game);
+        context.insert("round", &round);
+        context.insert("hints", &HintPage::for_round(&round, &game, &catalog)?);
         "pages.html"
     } else {
         // not logged in
@@ -72,6 +127,587 @@ pub async fn root(
     Ok(Html(rendered))
 }
 
+/// Finds the round of the game the user should be playing right now.
+///
+/// Reloading the page gives back the same round instead of a new city, only a round that ran out
+/// of time is thrown away and issued again. Random cities come out of the [RoundPool] when it has one ready.
+/// The image is not fetched here, the page loads it from [round_image].
+///
+/// # Returns:
+/// [Result]<[Round], [AppError]>
+async fn next_round_in_game(
+    database: &Store,
+    round_pool: &RoundPool,
+    catalog: &CityCatalog,
+    boundaries: &BoundarySet,
+    game: &Game,
+) -> Result<Round, AppError> {
+    let rounds = database.get_rounds_for_game(game.id).await?;
+
+    match rounds.iter().find(|r| r.answered_at.is_none()) {
+        Some(open_round) if !open_round.is_expired() => Ok(open_round.clone()),
+        open_round => {
+            if let Some(expired_round) = open_round {
+                database.delete_unanswered_round(expired_round.id).await?;
+            }
+
+            let round_number = rounds.iter().filter(|r| r.answered_at.is_some()).count() as i32 + 1;
+
+            // Some games, like the daily challenge, decide their cities up front, games on a custom map pick
+            // from its locations, games on land pick any spot on it and the rest pick from what their filter allows.
+            // Picked places stay clear of the places earlier rounds were at
+            let earlier: Vec<City> = rounds.iter().filter_map(|r| r.target(catalog).ok()).collect();
+            let round = match (game.city_id_for_round(round_number, catalog), game.map_id) {
+                (Some(city_id), _) => Round::new(game, round_number, city_id),
+                (None, Some(map_id)) => {
+                    let location = random_map_location(database, map_id, &rounds).await?;
+                    Round::at_location(game, round_number, &location)
+                }
+                (None, None) if game.location_source.is_land() => {
+                    random_land_round(catalog, boundaries, game, round_number, &earlier)?
+                }
+                (None, None) => {
+                    let excluded = catalog.ids_near(&earlier, EXCLUSION_RADIUS_KM);
+                    let city = round_pool.random_city_matching(&game.city_filter(boundaries), game.difficulty, &excluded)?;
+                    Round::new(game, round_number, city.id)
+                }
+            };
+
+            database.create_round(&round).await?;
+
+            Ok(round)
+        }
+    }
+}
+
+/// The settings page for starting a game with a [location filter](crate::models::filter::LocationFilter), [Difficulty], [GuessMode]
+/// and [LocationSource]
+/// # Returns:
+/// [Result]<[Html]<[String]>, [AppError]>
+pub async fn new_game_settings(State(database): State<Store>, claims: Claims) -> Result<Html<String>, AppError> {
+    let settings = GameSettingsForm::default();
+    let preferences = database.get_preferences(claims.id).await?;
+
+    let mut context = Context::new();
+    context.insert("filter_form", &settings.filter);
+    context.insert("difficulties", Difficulty::presets());
+    context.insert("difficulty", &preferences.difficulty);
+    context.insert("guess_modes", GuessMode::options());
+    context.insert("guess_mode", &preferences.guess_mode);
+    context.insert("location_sources", LocationSource::options());
+    context.insert("location_source", &LocationSource::default());
+
+    let rendered = TEMPLATES
+        .render("new_game.html", &context)
+        .unwrap_or_else(|err| {
+            error!("Template rendering error: {}", err);
+            panic!()
+        });
+
+    Ok(Html(rendered))
+}
+
+/// Starts a new game with the settings from the form, a location filter, a difficulty, a guess mode and a location source,
+/// and sends the user to its first round.
+/// The game the user was playing is left unfinished, the newest game is always the one being played.
+///
+/// The settings are checked here, and so is whether any city matches them, so a game can never
+/// run out of cities halfway through. Region modes need their boundaries to be loaded and places in at least
+/// [MIN_REGIONS] of their regions, see [check_guess_mode], and games on land the land.
+/// # Returns:
+/// [Result]<[Response]<[Body]>, [AppError]> - [AppError::InvalidFilter], [AppError::InvalidGuessMode] or
+/// [AppError::InvalidLocationSource] for settings that can't be played
+pub async fn new_game(
+    State(database): State<Store>,
+    State(catalog): State<Catalog>,
+    State(boundaries): State<Boundaries>,
+    claims: Claims,
+    Form(settings): Form<GameSettingsForm>,
+) -> Result<Response<Body>, AppError> {
+    let settings = settings.validate()?;
+    let difficulty = settings.difficulty;
+
+    if settings.location_source.is_land() && boundaries.land.is_empty() {
+        return Err(AppError::InvalidLocationSource("There is no land to pick locations from".to_string()));
+    }
+
+    let game = Game::new(claims.id, GameMode::Classic)
+        .with_filter(settings.filter)
+        .with_difficulty(difficulty)
+        .with_guess_mode(settings.guess_mode)
+        .with_location_source(settings.location_source);
+    check_guess_mode(&catalog, &boundaries, &game)?;
+    if !game.location_source.is_land() && catalog.matching(&game.city_filter(&boundaries)).is_empty() {
+        return Err(AppError::InvalidFilter("No city matches these settings".to_string()));
+    }
+
+    database.create_game(&game).await?;
+    info!(
+        "Started {} {} game {} on {} for user {} with filter {:?}",
+        difficulty.as_str(),
+        game.guess_mode.as_str(),
+        game.id,
+        game.location_source.as_str(),
+        claims.id,
+        game.filter_key
+    );
+
+    Ok(see_other("/"))
+}
+
+/// Makes sure the rounds of a game in a region mode can be judged and aren't all in the same region.
+/// The boundaries have to be loaded, and the cities the game picks from have to be in at least [MIN_REGIONS]
+/// of their regions, games on land need a layer with that many regions.
+///
+/// # Returns:
+/// [AppResult]<()> - [AppError::InvalidGuessMode] when the game can't be played in its guess mode
+fn check_guess_mode(catalog: &CityCatalog, boundaries: &BoundarySet, game: &Game) -> AppResult<()> {
+    let Some(regions) = boundaries.layer(game.guess_mode) else {
+        return Ok(());
+    };
+    if regions.is_empty() {
+        return Err(AppError::InvalidGuessMode(format!(
+            "There are no {} boundaries to guess with",
+            game.guess_mode.as_str()
+        )));
+    }
+
+    let num_regions = match boundaries.known_regions(game.guess_mode) {
+        Some(known) if !game.location_source.is_land() => catalog
+            .matching(&game.city_filter(boundaries))
+            .into_iter()
+            .map(|city| known.label(city))
+            .collect::<HashSet<_>>()
+            .len(),
+        _ => regions.len(),
+    };
+    if num_regions < MIN_REGIONS {
+        return Err(AppError::InvalidGuessMode(format!(
+            "The places of these settings are in {} of the {} boundaries, guessing needs at least {}",
+            num_regions,
+            game.guess_mode.as_str(),
+            MIN_REGIONS
+        )));
+    }
+
+    Ok(())
+}
+
+/// Picks a random location of a custom map, preferring the ones that haven't come up in the game yet
+///
+/// # Returns:
+/// [Result]<[MapLocation], [AppError]> - [AppError::InvalidMap] when the map has no locations
+async fn random_map_location(database: &Store, map_id: Uuid, rounds: &[Round]) -> AppResult<MapLocation> {
+    let locations = database.get_map_locations(map_id).await?;
+
+    let played = |location: &MapLocation| {
+        rounds.iter().any(|round| match location.city_id {
+            Some(city_id) => round.target_lat.is_none() && round.city_id == city_id,
+            None => round.target_lat == Some(location.latitude) && round.target_lng == Some(location.longitude),
+        })
+    };
+    let unplayed: Vec<&MapLocation> = locations.iter().filter(|location| !played(location)).collect();
+
+    let mut rng = rand::thread_rng();
+    let location = if unplayed.is_empty() {
+        locations.choose(&mut rng)
+    } else {
+        unplayed.choose(&mut rng).copied()
+    };
+
+    location
+        .cloned()
+        .ok_or_else(|| AppError::InvalidMap("This map has no locations yet".to_string()))
+}
+
+/// How many spots on land are tried to find one clear of earlier rounds
+const LAND_ATTEMPTS: usize = 10;
+
+/// How many spots on land are tried in a region mode, where a spot also has to be in a region of the boundaries.
+/// The boundaries may only cover a small part of the land
+const REGION_LAND_ATTEMPTS: usize = 200;
+
+/// Picks a random spot on land for a round of a game on [land](LocationSource::is_land), anywhere on it or around a city
+/// picked by its population. The spot is named after its coordinates and the state or country it is in.
+/// A few spots are tried to find one clear of the places earlier rounds were at, if none is the last one is played.
+/// In a region mode only spots in a region of the boundaries are played, anything else couldn't be judged.
+///
+/// # Returns:
+/// [Result]<[Round], [AppError]> - [AppError::InvalidLocationSource] when there is no land to pick from,
+/// [AppError::InvalidGuessMode] when none of the spots tried is in a region
+fn random_land_round(
+    catalog: &CityCatalog,
+    boundaries: &BoundarySet,
+    game: &Game,
+    round_number: i32,
+    earlier: &[City],
+) -> AppResult<Round> {
+    let mut rng = rand::thread_rng();
+    let mut point = None;
+
+    let regions = boundaries.layer(game.guess_mode);
+    let cities = LocationFilter {
+        regions: boundaries.known_regions(game.guess_mode),
+        ..LocationFilter::default()
+    };
+    let attempts = if regions.is_some() { REGION_LAND_ATTEMPTS } else { LAND_ATTEMPTS };
+
+    for _ in 0..attempts {
+        let candidate = match game.location_source {
+            LocationSource::PopulatedLand => {
+                let city = catalog.random_by_population(&cities)?;
+
+                // A city the simplified coastline leaves without land around it is played at its center
+                Some(
+                    boundaries
+                        .land
+                        .random_point_near(&mut rng, city.latitude, city.longitude, NEAR_CITY_RADIUS_KM)
+                        .unwrap_or((city.latitude, city.longitude)),
+                )
+            }
+            _ => boundaries.land.random_point(&mut rng),
+        };
+
+        let Some((latitude, longitude)) = candidate else {
+            break;
+        };
+        if regions.is_some_and(|regions| regions.locate(latitude, longitude).is_none()) {
+            continue;
+        }
+
+        point = candidate;
+        let clear = earlier.iter().all(|place| {
+            haversine_distance(latitude, longitude, place.latitude, place.longitude) > EXCLUSION_RADIUS_KM
+        });
+        if clear {
+            break;
+        }
+    }
+
+    let (latitude, longitude) = point.ok_or_else(|| match regions {
+        Some(_) => AppError::InvalidGuessMode(format!("No land was found in the {} boundaries", game.guess_mode.as_str())),
+        None => AppError::InvalidLocationSource("There is no land to pick locations from".to_string()),
+    })?;
+
+    Ok(Round::at_point(
+        game,
+        round_number,
+        format!("{:.4}, {:.4}", latitude, longitude),
+        boundaries.region_name(latitude, longitude),
+        latitude,
+        longitude,
+    ))
+}
+
+/// Todays daily challenge, the same cities for everybody and only playable once.
+/// Once the user has finished it they are sent to their summary instead.
+/// # Returns:
+/// [Result]<[Response]<[Body]>, [AppError]>
+pub async fn daily_challenge(
+    State(database): State<Store>,
+    State(catalog): State<Catalog>,
+    State(boundaries): State<Boundaries>,
+    State(round_pool): State<RoundPool>,
+    claims: Claims,
+) -> Result<Response<Body>, AppError> {
+    let game = database.get_or_create_daily_game(claims.id, today()).await?;
+
+    if game.is_finished() {
+        let mut response = Response::builder()
+            .status(StatusCode::FOUND)
+            .body(Body::empty())
+            .unwrap();
+
+        response.headers_mut().insert(
+            LOCATION,
+            HeaderValue::from_str(&format!("/games/{}/summary", game.id)).unwrap(),
+        );
+
+        return Ok(response);
+    }
+
+    let round = next_round_in_game(&database, &round_pool, &catalog, &boundaries, &game).await?;
+
+    let mut context = Context::new();
+
+    let preferences = database.get_preferences(claims.id).await?;
+
+    let template_name = {
+        let page = PagePackage::new(round.id, preferences.map_type).with_hints(&round);
+        context.insert("claims", &claims);
+        context.insert("is_logged_in", &true);
+        context.insert("page", &page);
+        context.insert("game", &game);
+        context.insert("round", &round);
+        context.insert("hints", &HintPage::for_round(&round, &game, &catalog)?);
+        "pages.html"
+    };
+
+    // Render html template with that context
+    let rendered = TEMPLATES
+        .render(template_name, &context)
+        .unwrap_or_else(|err| {
+            error!("Template rendering error: {}", err);
+            panic!()
+        });
+
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "text/html")
+        .body(rendered.into())
+        .expect("Failed to build response.");
+
+    Ok(response)
+}
+
+/// Leaderboard of todays daily challenge
+pub async fn daily_leaderboard(
+    State(database): State<Store>,
+    State(catalog): State<Catalog>,
+    Query(query): Query<FormatQuery>,
+) -> Result<axum::response::Response, AppError> {
+    daily_leaderboard_response(&database, &catalog, today(), query.format).await
+}
+
+/// Leaderboard of the daily challenge of any day up to today, past days also show their cities
+pub async fn daily_leaderboard_for_date(
+    State(database): State<Store>,
+    State(catalog): State<Catalog>,
+    Path(date): Path<NaiveDate>,
+    Query(query): Query<FormatQuery>,
+) -> Result<axum::response::Response, AppError> {
+    if date > today() {
+        return Err(AppError::DailyNotFound);
+    }
+
+    daily_leaderboard_response(&database, &catalog, date, query.format).await
+}
+
+async fn daily_leaderboard_response(
+    database: &Store,
+    catalog: &CityCatalog,
+    date: NaiveDate,
+    format: ResponseFormat,
+) -> Result<axum::response::Response, AppError> {
+    let rows = database.get_daily_leaderboard(date, 100).await?; // TODO: change this to NOT A MAGIC NUMBER
+    let leaderboard = DailyLeaderBoard::new(date, rows, catalog);
+
+    if format == ResponseFormat::Json {
+        return Ok(leaderboard.into_response());
+    }
+
+    let mut context = Context::new();
+
+    let template_name = {
+        context.insert("leaderboard", &leaderboard);
+        "daily_leaderboard.html"
+    };
+
+    // Render html template with that context
+    let rendered = TEMPLATES
+        .render(template_name, &context)
+        .unwrap_or_else(|err| {
+            error!("Template rendering error: {}", err);
+            panic!()
+        });
+
+    Ok(Html(rendered).into_response())
+}
+
+/// Every past daily challenge, with how many people finished it and the best score
+pub async fn daily_archive(State(database): State<Store>) -> Result<Html<String>, AppError> {
+    let archive = database.get_daily_archive(today()).await?;
+
+    let mut context = Context::new();
+
+    let template_name = {
+        context.insert("archive", &archive);
+        "daily_archive.html"
+    };
+
+    // Render html template with that context
+    let rendered = TEMPLATES
+        .render(template_name, &context)
+        .unwrap_or_else(|err| {
+            error!("Template rendering error: {}", err);
+            panic!()
+        });
+
+    Ok(Html(rendered))
+}
+
+/// Hit rate and size of the satellite image cache and how full the round pool is, as json.
+/// The cache is left out when it is turned off.
+pub async fn imagery_stats(
+    State(imagery): State<Imagery>,
+    State(round_pool): State<RoundPool>,
+) -> impl IntoResponse {
+    Json(ImageryStats {
+        cache: imagery.cache_stats(),
+        round_pool: round_pool.stats(),
+    })
+}
+
+/// The catalog cities closest to a spot on the map, as json. With a `radius_km` only the cities within it are listed.
+/// Distances are in the unit the user prefers, kilometers for anybody not logged in.
+/// # Returns:
+/// [Result]<[NearbyCitiesPage], [AppError]> - [AppError::InvalidFilter] for coordinates or a radius out of range
+pub async fn nearby_cities(
+    State(database): State<Store>,
+    State(catalog): State<Catalog>,
+    OptionalClaims(claims): OptionalClaims,
+    Query(query): Query<NearbyQuery>,
+) -> Result<NearbyCitiesPage, AppError> {
+    let distance_unit = distance_unit_for(&database, claims.as_ref()).await?;
+
+    NearbyCitiesPage::new(&catalog, &query, distance_unit)
+}
+
+/// The satellite image of a round, only shown to the user the round was issued to.
+/// The image never changes, so browsers may keep it and ask again with the ETag.
+/// # Returns:
+/// [Result]<[Response]<[Body]>, [AppError]>
+pub async fn round_image(
+    State(database): State<Store>,
+    State(catalog): State<Catalog>,
+    State(imagery): State<Imagery>,
+    claims: Claims,
+    Path(round_id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<Response<Body>, AppError> {
+    let round = database
+        .get_round(round_id)
+        .await?
+        .ok_or(AppError::RoundNotFound)?;
+    round.check_owner(claims.id)?;
+
+    // How close the picture is taken depends on the difficulty of the game
+    let game = database
+        .get_game(round.game_id)
+        .await?
+        .ok_or(AppError::GameNotFound)?;
+
+    let city = round.target(&catalog)?;
+    let mut request = game.difficulty.satellite_request(city.latitude, city.longitude);
+    if round.has_hint(HintKind::ZoomOut) {
+        request.zoom = request.zoom.saturating_sub(ZOOM_OUT_LEVELS).max(1);
+    }
+
+    // The ETag is made of the round, never of where the picture was taken, or it would give the answer away
+    let etag = format!("\"{}-round-{}-z{}\"", imagery.name(), round.id, request.zoom);
+
+    png_response(&headers, etag, imagery.satellite_image(&request)).await
+}
+
+/// Takes a hint for a round that is still open and goes back to the round, the hint costs part of the score of the round.
+/// Hints that were already taken are not paid for again.
+/// # Returns:
+/// [Result]<[Response]<[Body]>, [AppError]> - [AppError::InvalidHint] for unknown hints and hints this round can't give
+pub async fn take_hint(
+    State(database): State<Store>,
+    State(catalog): State<Catalog>,
+    claims: Claims,
+    Path(round_id): Path<Uuid>,
+    Form(form): Form<HintForm>,
+) -> Result<Response<Body>, AppError> {
+    let round = database
+        .get_round(round_id)
+        .await?
+        .ok_or(AppError::RoundNotFound)?;
+    round.check_guessable(claims.id)?;
+
+    let game = database
+        .get_game(round.game_id)
+        .await?
+        .ok_or(AppError::GameNotFound)?;
+
+    let kind: HintKind = form.kind.parse().map_err(AppError::InvalidHint)?;
+    if kind.reveal(&game, &round.target(&catalog)?, &catalog).is_none() {
+        return Err(AppError::InvalidHint(format!(
+            "There is no {} hint for this round",
+            kind.as_str()
+        )));
+    }
+
+    database.add_hint(&round, kind, kind.penalty_percent()).await?;
+
+    match game.mode {
+        GameMode::Daily => Ok(see_other("/daily")),
+        GameMode::Classic => Ok(see_other("/")),
+    }
+}
+
+/// The map on the results page of a round, with the guess and the city on it.
+/// Like [round_image] only the owner of the round gets to see it, and only once it has been answered.
+/// # Returns:
+/// [Result]<[Response]<[Body]>, [AppError]>
+pub async fn result_image(
+    State(database): State<Store>,
+    State(catalog): State<Catalog>,
+    State(imagery): State<Imagery>,
+    claims: Claims,
+    Path(round_id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<Response<Body>, AppError> {
+    let round = database
+        .get_round(round_id)
+        .await?
+        .ok_or(AppError::RoundNotFound)?;
+    round.check_owner(claims.id)?;
+
+    // There is nothing to draw before the guess is in
+    let (Some(guess_lat), Some(guess_lng)) = (round.guess_lat, round.guess_lng) else {
+        return Err(AppError::RoundNotFound);
+    };
+
+    let city = round.target(&catalog)?;
+    let preferences = database.get_preferences(claims.id).await?;
+    let request = ResultMapRequest {
+        map_type: preferences.map_type,
+        ..ResultMapRequest::new(guess_lat, guess_lng, city.latitude, city.longitude)
+    };
+
+    let etag = format!("\"{}-result-{}-{}\"", imagery.name(), round.id, request.map_type.as_str());
+
+    png_response(&headers, etag, imagery.result_map(&request)).await
+}
+
+/// Sends a png with caching headers, or a bare `304 Not Modified` when the browser already has this version,
+/// in which case the image is never fetched at all.
+async fn png_response(
+    request_headers: &HeaderMap,
+    etag: String,
+    image: impl Future<Output = AppResult<Vec<u8>>>,
+) -> Result<Response<Body>, AppError> {
+    let browser_has_it = request_headers
+        .get(IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.split(',').any(|tag| tag.trim() == etag || tag.trim() == "*"))
+        .unwrap_or(false);
+
+    let response = Response::builder()
+        .header(ETAG, &etag)
+        // Private, the images belong to one user and must not end up in a shared cache
+        .header(CACHE_CONTROL, "private, max-age=86400");
+
+    if browser_has_it {
+        return Ok(response
+            .status(StatusCode::NOT_MODIFIED)
+            .body(Body::empty())
+            .expect("Failed to build response."));
+    }
+
+    let image_bytes = image.await?;
+
+    let response = response
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "image/png")
+        .body(image_bytes.into())
+        .expect("Failed to build response.");
+
+    Ok(response)
+}
+
 /// ======================================
 /// CRUD -> Create - Read - Update - Delete
 /// ======================================
@@ -168,14 +804,25 @@ pub async fn register(
 }
 
 
+/// The leaderboard of medium pinpoint games played with every city, or of the games played with the location filter,
+/// difficulty, guess mode and location source in the query string.
 /// TODO: Do something with the optional claims, highlight the specific users rank if possible, as in the claims is Some
 pub async fn leaderboard(
     State(database): State<Store>,
-    OptionalClaims(claims): OptionalClaims
+    OptionalClaims(claims): OptionalClaims,
+    Query(settings): Query<GameSettingsForm>,
     ) -> Result<Response<Body>, AppError> {
 
+    let game_settings = settings.clone().validate()?;
 
-    let user_rank_list = database.get_top_num_users(100).await?; // TODO: change this to NOT A MAGIC NUMBER
+    // The board of the scoring strategy new games are played with
+    let scoring = GameMode::Classic.scoring();
+
+    let user_rank_list = if game_settings.is_default() {
+        database.get_top_num_users(100, scoring).await? // TODO: change this to NOT A MAGIC NUMBER
+    } else {
+        database.get_settings_leaderboard(&game_settings, scoring, 100).await?
+    };
     let leaderboard = LeaderBoard::new(user_rank_list);
 
 
@@ -183,6 +830,14 @@ pub async fn leaderboard(
 
     let template_name = {
         context.insert("leaderboard", &leaderboard);
+        context.insert("filter_description", &game_settings.describe());
+        context.insert("filter_form", &settings.filter);
+        context.insert("difficulties", Difficulty::presets());
+        context.insert("difficulty", &game_settings.difficulty);
+        context.insert("guess_modes", GuessMode::options());
+        context.insert("guess_mode", &game_settings.guess_mode);
+        context.insert("location_sources", LocationSource::options());
+        context.insert("location_source", &game_settings.location_source);
         "leaderboard.html"
     };
 
@@ -205,6 +860,8 @@ pub async fn leaderboard(
 
 pub async fn guess_location(
     State(database): State<Store>,
+    State(catalog): State<Catalog>,
+    State(boundaries): State<Boundaries>,
     OptionalClaims(claims): OptionalClaims,
     Form(location): Form<Location>,
 ) -> Result<Response<Body>, AppError> {
@@ -218,32 +875,67 @@ pub async fn guess_location(
         context.insert("claims", &claims_data);
         context.insert("is_logged_in", &true);
 
-        let city_page = City::get_city_in_page_from_rank(location.city_id.clone()).await?;
+        // Resolve the round before anything else, unknown, expired or answered rounds never get scored
+        let round = database
+            .get_round(location.round_id)
+            .await?
+            .ok_or(AppError::RoundNotFound)?;
+        round.check_guessable(claims_data.id)?;
 
+        let city_page = CityPage {
+            city: round.target(&catalog)?,
+        };
 
-        let distance = DistancePage::new(city_page.city.clone(), location.lat, location.lng)?;
+
+        let preferences = database.get_preferences(claims_data.id).await?;
+        let distance = DistancePage::new(city_page.city.clone(), location.lat, location.lng, preferences.distance_unit)?;
 
 
         // Extracting lat and lng from city_page for better code readability
         let (lat, lng) = (city_page.city.latitude, city_page.city.longitude);
-        let distance_int = haversine_distance(lat, lng, location.lat, location.lng);
-
-        let score = get_score_from_distance(distance_int);
+        let distance_int = geodesy::distance_km(lat as f64, lng as f64, location.lat as f64, location.lng as f64) as f32;
+
+        let game = database
+            .get_game(round.game_id)
+            .await?
+            .ok_or(AppError::GameNotFound)?;
+
+        // Region modes only care about which country or state the guess is in, the distance is still shown
+        let strategy = round.scoring.unwrap_or_default().strategy();
+        let region_guess = if game.guess_mode.is_region() {
+            Some(boundaries.judge(game.guess_mode, &city_page.city, location.lat, location.lng)?)
+        } else {
+            None
+        };
+        let base_score = match &region_guess {
+            Some(region_guess) => region_guess.score(strategy.max_score()),
+            None => strategy.score(distance_int),
+        };
+
+        // Hints cost part of the best score, before the difficulty multiplier so they cost the same share at every tier
+        let hint_penalty = round.hint_penalty_points(strategy.max_score());
+        let score = game.difficulty.apply((base_score - hint_penalty).max(0));
 
         info!("Distance aquired, updating score");
-        database.update_score(score, claims_data.id).await?;
-
-        let map = StaticGuessMap::get_static_map_with_markers(
-            location.city_id,
-            location.lat,
-            location.lng,
-        )
-        .await?;
+        let guessed_region = region_guess.as_ref().and_then(|region_guess| region_guess.guessed_code.as_deref());
+        let game = database
+            .answer_round(&round, location.lat, location.lng, distance_int, score, guessed_region)
+            .await?;
 
         // context.insert("distance", &distance); // NEED TO WRITE INTO_RESPONSE FOR distance
-        context.insert("static_map", &map);
         context.insert("distance_page", &distance);
         context.insert("city_page", &city_page);
+        context.insert("game", &game);
+        context.insert("round", &round);
+        context.insert("score", &score);
+        context.insert("hint_penalty", &game.difficulty.apply(hint_penalty));
+        context.insert("difficulty", game.difficulty.preset());
+        context.insert("region_guess", &region_guess);
+        context.insert(
+            "nearest_city",
+            &NearestCityPage::new(&catalog, location.lat, location.lng, preferences.distance_unit),
+        );
+        context.insert("distance_unit", preferences.distance_unit.option());
 
         "guess.html"
     } else {
@@ -269,6 +961,166 @@ pub async fn guess_location(
     Ok(response)
 }
 
+/// Summary of a game, listing every answered round with its city, distance and score
+/// # Returns:
+/// [Result]<[Response]<[Body]>, [AppError]>
+pub async fn game_summary(
+    State(database): State<Store>,
+    State(catalog): State<Catalog>,
+    claims: Claims,
+    Path(game_id): Path<Uuid>,
+) -> Result<Response<Body>, AppError> {
+    let game = database
+        .get_game(game_id)
+        .await?
+        .ok_or(AppError::GameNotFound)?;
+    game.check_owner(claims.id)?;
+
+    let rounds = database.get_rounds_for_game(game.id).await?;
+    let distance_unit = database.get_preferences(claims.id).await?.distance_unit;
+    let summary = GameSummary::new(game, rounds, &catalog, distance_unit)?;
+
+    let mut context = Context::new();
+
+    let template_name = {
+        context.insert("summary", &summary);
+        context.insert("distance_unit", distance_unit.option());
+        "summary.html"
+    };
+
+    // Render html template with that context
+    let rendered = TEMPLATES
+        .render(template_name, &context)
+        .unwrap_or_else(|err| {
+            error!("Template rendering error: {}", err);
+            panic!()
+        });
+
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "text/html")
+        .body(rendered.into())
+        .expect("Failed to build response.");
+
+    Ok(response)
+}
+
+/// The logged in users own profile page, see [user_profile]
+pub async fn my_profile(
+    State(database): State<Store>,
+    State(catalog): State<Catalog>,
+    claims: Claims,
+    Query(query): Query<FormatQuery>,
+) -> Result<axum::response::Response, AppError> {
+    profile_response(&database, &catalog, claims.id, Some(&claims), query.format).await
+}
+
+/// Profile page of any user, showing stats computed from their guess history.
+/// Add `?format=json` to get the stats as json instead. Distances are in the unit the user looking at the page prefers.
+/// # Returns:
+/// [Result]<[Response], [AppError]>
+pub async fn user_profile(
+    State(database): State<Store>,
+    State(catalog): State<Catalog>,
+    OptionalClaims(claims): OptionalClaims,
+    Path(user_id): Path<i32>,
+    Query(query): Query<FormatQuery>,
+) -> Result<axum::response::Response, AppError> {
+    if !database.user_exists(user_id).await? {
+        return Err(AppError::UserDoesNotExist);
+    }
+
+    profile_response(&database, &catalog, user_id, claims.as_ref(), query.format).await
+}
+
+async fn profile_response(
+    database: &Store,
+    catalog: &CityCatalog,
+    user_id: i32,
+    viewer: Option<&Claims>,
+    format: ResponseFormat,
+) -> Result<axum::response::Response, AppError> {
+    let guesses = database.get_all_guesses_for_user(user_id).await?;
+    let rank = database.get_user_rank(user_id, GameMode::Classic.scoring()).await?;
+    let distance_unit = distance_unit_for(database, viewer).await?;
+
+    let stats = UserStats::new(user_id, rank, &guesses, catalog).in_unit(distance_unit);
+
+    if format == ResponseFormat::Json {
+        return Ok(stats.into_response());
+    }
+
+    let mut context = Context::new();
+
+    let template_name = {
+        context.insert("stats", &stats);
+        context.insert("distance_unit", distance_unit.option());
+        "profile.html"
+    };
+
+    // Render html template with that context
+    let rendered = TEMPLATES
+        .render(template_name, &context)
+        .unwrap_or_else(|err| {
+            error!("Template rendering error: {}", err);
+            panic!()
+        });
+
+    Ok(Html(rendered).into_response())
+}
+
+/// Settings page where the user picks their distance unit, map type, and the difficulty and guess mode new games start with.
+/// Add `?format=json` to get the preferences as json instead.
+/// # Returns:
+/// [Result]<[axum::response::Response], [AppError]>
+pub async fn settings(
+    State(database): State<Store>,
+    claims: Claims,
+    Query(query): Query<FormatQuery>,
+) -> Result<axum::response::Response, AppError> {
+    let preferences = database.get_preferences(claims.id).await?;
+
+    if query.format == ResponseFormat::Json {
+        return Ok(preferences.into_response());
+    }
+
+    let mut context = Context::new();
+    context.insert("claims", &claims);
+    context.insert("is_logged_in", &true);
+    context.insert("distance_units", DistanceUnit::options());
+    context.insert("distance_unit", &preferences.distance_unit);
+    context.insert("map_types", MapType::options());
+    context.insert("map_type", &preferences.map_type);
+    context.insert("difficulties", Difficulty::presets());
+    context.insert("difficulty", &preferences.difficulty);
+    context.insert("guess_modes", GuessMode::options());
+    context.insert("guess_mode", &preferences.guess_mode);
+
+    Ok(render_page("settings.html", &context).into_response())
+}
+
+/// Saves the preferences posted from the settings page, and goes back to it
+/// # Returns:
+/// [Result]<[Response]<[Body]>, [AppError]> - [AppError::InvalidPreferences] for a field that isn't one of its options
+pub async fn save_settings(
+    State(database): State<Store>,
+    claims: Claims,
+    Form(form): Form<PreferencesForm>,
+) -> Result<Response<Body>, AppError> {
+    let preferences = form.validate(claims.id)?;
+    database.save_preferences(&preferences).await?;
+
+    Ok(see_other("/settings"))
+}
+
+/// The unit a user wants distances shown in, kilometers for anybody not logged in
+async fn distance_unit_for(database: &Store, claims: Option<&Claims>) -> AppResult<DistanceUnit> {
+    match claims {
+        Some(claims) => Ok(database.get_preferences(claims.id).await?.distance_unit),
+        None => Ok(DistanceUnit::default()),
+    }
+}
+
 pub async fn login(
     State(database): State<Store>,
     Form(creds): Form<User>,
@@ -371,3 +1223,372 @@ pub async fn protected(claims: Claims) -> Result<String, AppError> {
         claims
     ))
 }
+
+
+/// Number of catalog cities shown for a search on the map page
+const MAP_SEARCH_RESULTS: usize = 20;
+
+/// A redirect to the given page after a form was posted
+fn see_other(location: &str) -> Response<Body> {
+    let mut response = Response::builder()
+        .status(StatusCode::SEE_OTHER)
+        .body(Body::empty())
+        .unwrap();
+
+    response
+        .headers_mut()
+        .insert(LOCATION, HeaderValue::from_str(location).unwrap());
+
+    response
+}
+
+/// Renders a template with the given context, the way every page here does
+fn render_page(template_name: &str, context: &Context) -> Html<String> {
+    let rendered = TEMPLATES
+        .render(template_name, context)
+        .unwrap_or_else(|err| {
+            error!("Template rendering error: {}", err);
+            panic!()
+        });
+
+    Html(rendered)
+}
+
+/// Looks up a map the user is allowed to see
+///
+/// # Returns:
+/// [AppResult]<[CustomMap]> - [AppError::MapNotFound] for unknown maps and private maps of other users
+async fn visible_map(database: &Store, map_id: Uuid, user_id: Option<i32>) -> AppResult<CustomMap> {
+    let map = database.get_map(map_id).await?.ok_or(AppError::MapNotFound)?;
+    map.check_visible(user_id)?;
+
+    Ok(map)
+}
+
+/// Lists the public maps, and the maps of the user when logged in
+/// # Returns:
+/// [Result]<[Html]<[String]>, [AppError]>
+pub async fn list_maps(
+    State(database): State<Store>,
+    OptionalClaims(claims): OptionalClaims,
+) -> Result<Html<String>, AppError> {
+    let mut context = Context::new();
+
+    context.insert("public_maps", &database.get_public_maps(100).await?);
+    if let Some(claims_data) = claims {
+        context.insert("is_logged_in", &true);
+        context.insert("my_maps", &database.get_maps_for_user(claims_data.id).await?);
+    } else {
+        context.insert("is_logged_in", &false);
+        context.insert("my_maps", &Vec::<CustomMap>::new());
+    }
+
+    Ok(render_page("maps.html", &context))
+}
+
+/// Creates an empty map and sends its owner to it, so they can start adding locations
+/// # Returns:
+/// [Result]<[Response]<[Body]>, [AppError]>
+pub async fn create_map(
+    State(database): State<Store>,
+    claims: Claims,
+    Form(form): Form<MapForm>,
+) -> Result<Response<Body>, AppError> {
+    let (name, description, visibility) = form.validate()?;
+
+    let map = CustomMap::new(claims.id, name, description, visibility);
+    database.create_map(&map).await?;
+    info!("User {} created map {}", claims.id, map.id);
+
+    Ok(see_other(&format!("/maps/{}", map.id)))
+}
+
+/// The page of a single map, with its locations. The owner also gets the forms to change it
+/// and can search the catalog for cities to add.
+/// # Returns:
+/// [Result]<[Html]<[String]>, [AppError]>
+pub async fn view_map(
+    State(database): State<Store>,
+    State(catalog): State<Catalog>,
+    OptionalClaims(claims): OptionalClaims,
+    Path(map_id): Path<Uuid>,
+    Query(query): Query<MapSearchQuery>,
+) -> Result<Html<String>, AppError> {
+    let user_id = claims.as_ref().map(|claims_data| claims_data.id);
+    let map = visible_map(&database, map_id, user_id).await?;
+    let is_owner = user_id == Some(map.owner_id);
+
+    let search = query.search.unwrap_or_default().trim().to_string();
+    let search_results = if is_owner && !search.is_empty() {
+        catalog
+            .search(&search)
+            .take(MAP_SEARCH_RESULTS)
+            .cloned()
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let mut locations = database.get_map_locations(map.id).await?;
+    let num_locations = locations.len();
+    if !is_owner {
+        locations.clear();
+    }
+
+    let page = MapPage {
+        locations,
+        num_locations,
+        map,
+        is_owner,
+        search_results,
+        search,
+    };
+
+    let mut context = Context::new();
+    context.insert("is_logged_in", &user_id.is_some());
+    context.insert("page", &page);
+
+    Ok(render_page("map.html", &context))
+}
+
+/// Changes the name, description and visibility of a map
+/// # Returns:
+/// [Result]<[Response]<[Body]>, [AppError]>
+pub async fn update_map(
+    State(database): State<Store>,
+    claims: Claims,
+    Path(map_id): Path<Uuid>,
+    Form(form): Form<MapForm>,
+) -> Result<Response<Body>, AppError> {
+    let map = database.get_map(map_id).await?.ok_or(AppError::MapNotFound)?;
+    map.check_owner(claims.id)?;
+
+    let (name, description, visibility) = form.validate()?;
+    database.update_map(map.id, &name, &description, visibility).await?;
+
+    Ok(see_other(&format!("/maps/{}", map.id)))
+}
+
+/// Adds a catalog city, or a point given by its coordinates, to a map
+/// # Returns:
+/// [Result]<[Response]<[Body]>, [AppError]>
+pub async fn add_map_location(
+    State(database): State<Store>,
+    State(catalog): State<Catalog>,
+    claims: Claims,
+    Path(map_id): Path<Uuid>,
+    Form(form): Form<MapLocationForm>,
+) -> Result<Response<Body>, AppError> {
+    let map = database.get_map(map_id).await?.ok_or(AppError::MapNotFound)?;
+    map.check_owner(claims.id)?;
+
+    let location = form.validate(&catalog)?;
+    database.add_map_locations(map.id, &[location]).await?;
+
+    Ok(see_other(&format!("/maps/{}", map.id)))
+}
+
+/// Removes a location from a map
+/// # Returns:
+/// [Result]<[Response]<[Body]>, [AppError]>
+pub async fn remove_map_location(
+    State(database): State<Store>,
+    claims: Claims,
+    Path((map_id, location_id)): Path<(Uuid, i32)>,
+) -> Result<Response<Body>, AppError> {
+    let map = database.get_map(map_id).await?.ok_or(AppError::MapNotFound)?;
+    map.check_owner(claims.id)?;
+
+    database.remove_map_location(map.id, location_id).await?;
+
+    Ok(see_other(&format!("/maps/{}", map.id)))
+}
+
+/// Starts a game on a map and sends the user to its first round
+/// # Returns:
+/// [Result]<[Response]<[Body]>, [AppError]> - [AppError::InvalidMap] when the map has no locations yet
+pub async fn play_map(
+    State(database): State<Store>,
+    claims: Claims,
+    Path(map_id): Path<Uuid>,
+) -> Result<Response<Body>, AppError> {
+    let map = visible_map(&database, map_id, Some(claims.id)).await?;
+
+    if database.get_map_locations(map.id).await?.is_empty() {
+        return Err(AppError::InvalidMap("This map has no locations yet".to_string()));
+    }
+
+    let game = Game::new(claims.id, GameMode::Classic).on_map(map.id);
+    database.create_game(&game).await?;
+    info!("Started game {} for user {} on map {}", game.id, claims.id, map.id);
+
+    Ok(see_other("/"))
+}
+
+/// The leaderboard of a map, for everybody who can see the map
+/// # Returns:
+/// [Result]<[Html]<[String]>, [AppError]>
+pub async fn map_leaderboard(
+    State(database): State<Store>,
+    OptionalClaims(claims): OptionalClaims,
+    Path(map_id): Path<Uuid>,
+) -> Result<Html<String>, AppError> {
+    let map = visible_map(&database, map_id, claims.map(|claims_data| claims_data.id)).await?;
+
+    let leaderboard = LeaderBoard::new(database.get_map_leaderboard(map.id, GameMode::Classic.scoring(), 100).await?);
+
+    let mut context = Context::new();
+    context.insert("leaderboard", &leaderboard);
+    context.insert("filter_description", &format!("Map: {}", map.name));
+    context.insert("map", &map);
+
+    Ok(render_page("leaderboard.html", &context))
+}
+
+/// An uploaded map file, the way the import forms send it
+struct MapUpload {
+    format: MapFileFormat,
+    file_name: Option<String>,
+    contents: String,
+}
+
+/// Reads the `file` field of an import form, and the `format` field if the format
+/// shouldn't be guessed from the file name
+async fn read_map_upload(mut multipart: Multipart) -> AppResult<MapUpload> {
+    let upload_error = |err: axum::extract::multipart::MultipartError| AppError::InvalidMap(format!("Upload failed: {}", err));
+
+    let mut format = None;
+    let mut file = None;
+    while let Some(field) = multipart.next_field().await.map_err(upload_error)? {
+        match field.name() {
+            Some("format") => {
+                let text = field.text().await.map_err(upload_error)?;
+                if !text.trim().is_empty() {
+                    format = Some(text.parse::<MapFileFormat>().map_err(AppError::InvalidMap)?);
+                }
+            }
+            Some("file") => {
+                let file_name = field.file_name().map(str::to_string);
+                file = Some((file_name, field.text().await.map_err(upload_error)?));
+            }
+            _ => {}
+        }
+    }
+
+    let (file_name, contents) = file.ok_or_else(|| AppError::InvalidMap("No file was uploaded".to_string()))?;
+    let format = format
+        .or_else(|| file_name.as_deref().and_then(MapFileFormat::from_file_name))
+        .ok_or_else(|| AppError::InvalidMap("Pick whether the file is GeoJSON, KML or GPX".to_string()))?;
+
+    Ok(MapUpload {
+        format,
+        file_name,
+        contents,
+    })
+}
+
+/// Adds the locations of an uploaded file to a map, and shows which features were left out and why
+async fn import_locations(database: &Store, map: Option<&CustomMap>, file: &MapFile) -> AppResult<Html<String>> {
+    let added = match map {
+        Some(map) if !file.locations.is_empty() => database.add_map_locations(map.id, &file.locations).await?,
+        _ => 0,
+    };
+
+    let mut context = Context::new();
+    context.insert("map", &map);
+    context.insert("added", &added);
+    context.insert("errors", &file.errors);
+
+    Ok(render_page("map_import.html", &context))
+}
+
+/// Creates a map out of an uploaded GeoJSON, KML or GPX file.
+/// The map is named after the file, and isn't created when none of the features could be read.
+/// # Returns:
+/// [Result]<[Html]<[String]>, [AppError]>
+pub async fn import_map(
+    State(database): State<Store>,
+    State(catalog): State<Catalog>,
+    claims: Claims,
+    multipart: Multipart,
+) -> Result<Html<String>, AppError> {
+    let upload = read_map_upload(multipart).await?;
+    let file = map_files::parse(upload.format, &upload.contents, &catalog)?;
+
+    if file.locations.is_empty() {
+        return import_locations(&database, None, &file).await;
+    }
+
+    let name = file
+        .name
+        .clone()
+        .or_else(|| {
+            upload
+                .file_name
+                .as_deref()
+                .map(|file_name| file_name.rsplit_once('.').map_or(file_name, |(stem, _)| stem).to_string())
+        })
+        .unwrap_or_else(|| "Imported map".to_string());
+    let form = MapForm {
+        name: name.chars().take(MAX_NAME_LENGTH).collect(),
+        ..Default::default()
+    };
+    let (name, description, visibility) = form.validate()?;
+
+    let map = CustomMap::new(claims.id, name, description, visibility);
+    database.create_map(&map).await?;
+    info!("User {} imported map {} from {:?}", claims.id, map.id, upload.format);
+
+    import_locations(&database, Some(&map), &file).await
+}
+
+/// Adds the features of an uploaded GeoJSON, KML or GPX file to an existing map
+/// # Returns:
+/// [Result]<[Html]<[String]>, [AppError]>
+pub async fn import_into_map(
+    State(database): State<Store>,
+    State(catalog): State<Catalog>,
+    claims: Claims,
+    Path(map_id): Path<Uuid>,
+    multipart: Multipart,
+) -> Result<Html<String>, AppError> {
+    let map = database.get_map(map_id).await?.ok_or(AppError::MapNotFound)?;
+    map.check_owner(claims.id)?;
+
+    let upload = read_map_upload(multipart).await?;
+    let file = map_files::parse(upload.format, &upload.contents, &catalog)?;
+
+    import_locations(&database, Some(&map), &file).await
+}
+
+/// Downloads a map as a GeoJSON, KML or GPX file. Only the owner gets to, the file holds every answer of the map
+/// # Returns:
+/// [Result]<[Response]<[Body]>, [AppError]>
+pub async fn export_map(
+    State(database): State<Store>,
+    claims: Claims,
+    Path((map_id, format)): Path<(Uuid, String)>,
+) -> Result<Response<Body>, AppError> {
+    let format: MapFileFormat = format.parse().map_err(AppError::InvalidMap)?;
+    let map = visible_map(&database, map_id, Some(claims.id)).await?;
+    if map.owner_id != claims.id {
+        return Err(AppError::InvalidMap("Only the owner can download this map".to_string()));
+    }
+    let locations = database.get_map_locations(map.id).await?;
+
+    let file_name: String = map
+        .name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .collect();
+    let disposition = format!("attachment; filename=\"{}.{}\"", file_name, format.extension());
+
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, format.content_type())
+        .header(CONTENT_DISPOSITION, disposition)
+        .body(Body::from(map_files::export(format, &map, &locations)))
+        .unwrap();
+
+    Ok(response)
+}
@@ -0,0 +1,529 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use axum::async_trait;
+use serde_derive::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::imagery::{Imagery, ImageryProvider, ResultMapRequest, SatelliteRequest};
+use crate::AppResult;
+
+/// How well the image cache is doing, see [ImageCache::stats]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub bytes: u64,
+    pub max_bytes: u64,
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    /// Share of lookups that were answered from disk, between 0 and 1
+    pub hit_rate: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CacheEntry {
+    size: u64,
+    /// Value of [CacheIndex::clock] the last time this entry was read or written
+    last_used: u64,
+}
+
+/// What is on disk and in which order it was last used. Only ever touched behind the mutex,
+/// the files themselves are read and written outside of it.
+#[derive(Debug, Default)]
+struct CacheIndex {
+    entries: HashMap<String, CacheEntry>,
+    /// Entries by when they were last used, the first one is the next to be evicted
+    by_last_used: BTreeMap<u64, String>,
+    total_bytes: u64,
+    clock: u64,
+}
+
+impl CacheIndex {
+    fn touch(&mut self, key: &str) -> bool {
+        self.clock += 1;
+        let clock = self.clock;
+
+        match self.entries.get_mut(key) {
+            Some(entry) => {
+                self.by_last_used.remove(&entry.last_used);
+                entry.last_used = clock;
+                self.by_last_used.insert(clock, key.to_string());
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn insert(&mut self, key: String, size: u64) {
+        self.remove(&key);
+
+        self.clock += 1;
+        self.by_last_used.insert(self.clock, key.clone());
+        self.entries.insert(
+            key,
+            CacheEntry {
+                size,
+                last_used: self.clock,
+            },
+        );
+        self.total_bytes += size;
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.by_last_used.remove(&entry.last_used);
+            self.total_bytes -= entry.size;
+        }
+    }
+
+    /// Drops least recently used entries until everything fits in `max_bytes`, returning the dropped keys
+    fn evict_to(&mut self, max_bytes: u64) -> Vec<String> {
+        let mut evicted = Vec::new();
+
+        while self.total_bytes > max_bytes {
+            let Some((_, key)) = self.by_last_used.pop_first() else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&key) {
+                self.total_bytes -= entry.size;
+            }
+            evicted.push(key);
+        }
+
+        evicted
+    }
+}
+
+/// Processed images stored on disk, so a city that has been shown before doesn't cost another upstream request.
+///
+/// Every image is stored under the [cache key](SatelliteRequest::cache_key) of the request that made it,
+/// so the same location, zoom, size and map type always end up in the same file. Once the cache grows past its size limit the least recently used images are
+/// deleted. After a restart the files are ordered by when they were written, as reads aren't recorded on disk.
+pub struct ImageCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    index: Mutex<CacheIndex>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl ImageCache {
+    /// Opens the cache in the given directory, creating it if needed and picking up any images already in it
+    ///
+    /// # Arguments:
+    /// * dir: [PathBuf] - Where the images are stored
+    /// * max_bytes: u64 - How big all images together may get before old ones are evicted
+    ///
+    /// # Returns:
+    /// * [AppResult]<[Self](ImageCache)>
+    pub fn open(dir: PathBuf, max_bytes: u64) -> AppResult<Self> {
+        std::fs::create_dir_all(&dir)?;
+
+        let mut existing = Vec::new();
+        for dir_entry in std::fs::read_dir(&dir)? {
+            let dir_entry = dir_entry?;
+            let path = dir_entry.path();
+
+            match path.extension().and_then(|e| e.to_str()) {
+                Some("png") => {}
+                // Left behind by a write that never finished
+                Some("tmp") => {
+                    let _ = std::fs::remove_file(&path);
+                    continue;
+                }
+                _ => continue,
+            }
+            let Some(key) = path.file_stem().and_then(|s| s.to_str()).map(str::to_string) else {
+                continue;
+            };
+
+            let metadata = dir_entry.metadata()?;
+            existing.push((metadata.modified().ok(), key, metadata.len()));
+        }
+        existing.sort();
+
+        let mut index = CacheIndex::default();
+        for (_, key, size) in existing {
+            index.insert(key, size);
+        }
+
+        let cache = Self {
+            dir,
+            max_bytes,
+            index: Mutex::new(index),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        };
+
+        // The limit may have been lowered since the images were written
+        let evicted = cache.index.lock().unwrap().evict_to(max_bytes);
+        for key in evicted {
+            cache.evictions.fetch_add(1, Ordering::Relaxed);
+            let _ = std::fs::remove_file(cache.path_for(&key));
+        }
+
+        Ok(cache)
+    }
+
+    fn path_for(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.png", key))
+    }
+
+    /// Reads an image from the cache, counting the lookup as a hit or miss
+    ///
+    /// # Returns:
+    /// * [AppResult]<[Option]<[Vec]<u8>>>
+    pub async fn get(&self, key: &str) -> AppResult<Option<Vec<u8>>> {
+        if !self.index.lock().unwrap().touch(key) {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return Ok(None);
+        }
+
+        match tokio::fs::read(self.path_for(key)).await {
+            Ok(bytes) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Ok(Some(bytes))
+            }
+            // Evicted or deleted by hand in the meantime
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                self.index.lock().unwrap().remove(key);
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                Ok(None)
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Stores an image in the cache, evicting the least recently used images if it no longer fits.
+    /// Images bigger than the whole cache are not stored at all.
+    ///
+    /// # Returns:
+    /// * [AppResult]<()>
+    pub async fn put(&self, key: &str, bytes: &[u8]) -> AppResult<()> {
+        let size = bytes.len() as u64;
+        if size > self.max_bytes {
+            return Ok(());
+        }
+
+        // Written next to the real file and renamed over it, so readers never see half an image
+        let temp_path = self.dir.join(format!("{}.{}.tmp", key, Uuid::new_v4()));
+        tokio::fs::write(&temp_path, bytes).await?;
+        tokio::fs::rename(&temp_path, self.path_for(key)).await?;
+
+        let evicted = {
+            let mut index = self.index.lock().unwrap();
+            index.insert(key.to_string(), size);
+            index.evict_to(self.max_bytes)
+        };
+
+        for evicted_key in evicted {
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+            debug!("Evicting cached image {}", evicted_key);
+
+            match tokio::fs::remove_file(self.path_for(&evicted_key)).await {
+                Ok(()) => {}
+                Err(err) if err.kind() == ErrorKind::NotFound => {}
+                Err(err) => warn!("Could not evict cached image {}: {}", evicted_key, err),
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let (entries, bytes) = {
+            let index = self.index.lock().unwrap();
+            (index.entries.len(), index.total_bytes)
+        };
+
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        let lookups = hits + misses;
+
+        CacheStats {
+            entries,
+            bytes,
+            max_bytes: self.max_bytes,
+            hits,
+            misses,
+            evictions: self.evictions.load(Ordering::Relaxed),
+            hit_rate: if lookups == 0 {
+                0.0
+            } else {
+                hits as f64 / lookups as f64
+            },
+        }
+    }
+}
+
+/// Puts an [ImageCache] in front of another provider. Satellite images are looked up in the cache first
+/// and only fetched from the wrapped provider when they aren't there yet.
+///
+/// Result maps are passed straight through, every guess gives a different map so they would never be hit.
+pub struct CachedProvider {
+    inner: Imagery,
+    cache: ImageCache,
+}
+
+impl CachedProvider {
+    pub fn new(inner: Imagery, cache: ImageCache) -> Self {
+        Self { inner, cache }
+    }
+}
+
+#[async_trait]
+impl ImageryProvider for CachedProvider {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    async fn satellite_image(&self, request: &SatelliteRequest) -> AppResult<Vec<u8>> {
+        let key = request.cache_key();
+
+        // A broken cache shouldn't take the game down with it, fall back to the provider
+        match self.cache.get(&key).await {
+            Ok(Some(bytes)) => return Ok(bytes),
+            Ok(None) => {}
+            Err(err) => warn!("Could not read cached image {}: {:?}", key, err),
+        }
+
+        let bytes = self.inner.satellite_image(request).await?;
+
+        if let Err(err) = self.cache.put(&key, &bytes).await {
+            warn!("Could not cache image {}: {:?}", key, err);
+        }
+
+        Ok(bytes)
+    }
+
+    async fn result_map(&self, request: &ResultMapRequest) -> AppResult<Vec<u8>> {
+        self.inner.result_map(request).await
+    }
+
+    fn cache_stats(&self) -> Option<CacheStats> {
+        Some(self.cache.stats())
+    }
+}
+
+/// Wraps the provider in an [ImageCache] configured from the .env file.
+/// If the cache directory can't be used the provider is returned as is, the game works fine without a cache.
+///
+/// # .env variables
+/// * IMAGE_CACHE_DIR (optional) - Where cached images are kept, defaults to `resources/image_cache`
+/// * IMAGE_CACHE_MAX_MB (optional) - Size limit of the cache in megabytes, defaults to 512. 0 turns the cache off
+pub fn with_cache_from_env(provider: Imagery) -> Imagery {
+    let max_mb: u64 = std::env::var("IMAGE_CACHE_MAX_MB")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(512);
+
+    if max_mb == 0 {
+        info!("Image cache is turned off");
+        return provider;
+    }
+
+    let dir = std::env::var("IMAGE_CACHE_DIR").unwrap_or_else(|_| "resources/image_cache".to_string());
+
+    match ImageCache::open(PathBuf::from(&dir), max_mb * 1024 * 1024) {
+        Ok(cache) => {
+            let stats = cache.stats();
+            info!(
+                "Image cache in {} holds {} images ({} bytes)",
+                dir, stats.entries, stats.bytes
+            );
+            std::sync::Arc::new(CachedProvider::new(provider, cache))
+        }
+        Err(err) => {
+            warn!("Could not open the image cache in {}, running without it: {:?}", dir, err);
+            provider
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::sync::Arc;
+
+    use super::*;
+
+    /// A directory of its own for every test, removed again once the test is done
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            Self(std::env::temp_dir().join(format!("image-cache-{}", Uuid::new_v4())))
+        }
+
+        fn path(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn is_cached(dir: &TempDir, key: &str) -> bool {
+        dir.path().join(format!("{}.png", key)).exists()
+    }
+
+    #[tokio::test]
+    async fn lookups_are_counted_as_hits_and_misses() {
+        let dir = TempDir::new();
+        let cache = ImageCache::open(dir.path().to_path_buf(), 100).unwrap();
+
+        assert_eq!(cache.get("a").await.unwrap(), None);
+        cache.put("a", &[1, 2, 3]).await.unwrap();
+        assert_eq!(cache.get("a").await.unwrap(), Some(vec![1, 2, 3]));
+        assert_eq!(cache.get("a").await.unwrap(), Some(vec![1, 2, 3]));
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses), (2, 1));
+        assert_eq!((stats.entries, stats.bytes, stats.max_bytes), (1, 3, 100));
+        assert!((stats.hit_rate - 2.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn the_least_recently_used_image_is_evicted() {
+        let dir = TempDir::new();
+        let cache = ImageCache::open(dir.path().to_path_buf(), 30).unwrap();
+
+        for key in ["a", "b", "c"] {
+            cache.put(key, &[0; 10]).await.unwrap();
+        }
+        // Reading a makes b the oldest
+        cache.get("a").await.unwrap();
+        cache.put("d", &[0; 10]).await.unwrap();
+
+        assert!(!is_cached(&dir, "b"));
+        assert!(["a", "c", "d"].iter().all(|key| is_cached(&dir, key)));
+        assert_eq!(cache.get("b").await.unwrap(), None);
+
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.bytes, stats.evictions), (3, 30, 1));
+    }
+
+    #[tokio::test]
+    async fn a_big_image_evicts_as_many_as_it_needs() {
+        let dir = TempDir::new();
+        let cache = ImageCache::open(dir.path().to_path_buf(), 30).unwrap();
+
+        for key in ["a", "b", "c"] {
+            cache.put(key, &[0; 10]).await.unwrap();
+        }
+        cache.put("big", &[0; 25]).await.unwrap();
+
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.bytes, stats.evictions), (1, 25, 3));
+        assert!(is_cached(&dir, "big"));
+    }
+
+    #[tokio::test]
+    async fn images_bigger_than_the_cache_are_not_stored() {
+        let dir = TempDir::new();
+        let cache = ImageCache::open(dir.path().to_path_buf(), 30).unwrap();
+        cache.put("a", &[0; 10]).await.unwrap();
+
+        cache.put("huge", &[0; 31]).await.unwrap();
+
+        assert!(!is_cached(&dir, "huge"));
+        assert!(is_cached(&dir, "a"));
+        assert_eq!(cache.stats().evictions, 0);
+    }
+
+    #[tokio::test]
+    async fn storing_an_image_again_replaces_it() {
+        let dir = TempDir::new();
+        let cache = ImageCache::open(dir.path().to_path_buf(), 30).unwrap();
+
+        cache.put("a", &[0; 10]).await.unwrap();
+        cache.put("a", &[1; 20]).await.unwrap();
+
+        assert_eq!(cache.get("a").await.unwrap(), Some(vec![1; 20]));
+        assert_eq!((cache.stats().entries, cache.stats().bytes), (1, 20));
+    }
+
+    #[tokio::test]
+    async fn images_deleted_by_hand_are_misses() {
+        let dir = TempDir::new();
+        let cache = ImageCache::open(dir.path().to_path_buf(), 30).unwrap();
+        cache.put("a", &[0; 10]).await.unwrap();
+
+        std::fs::remove_file(dir.path().join("a.png")).unwrap();
+
+        assert_eq!(cache.get("a").await.unwrap(), None);
+        let stats = cache.stats();
+        assert_eq!((stats.misses, stats.entries, stats.bytes), (1, 0, 0));
+    }
+
+    #[tokio::test]
+    async fn reopening_picks_up_the_images_within_the_new_limit() {
+        let dir = TempDir::new();
+        {
+            let cache = ImageCache::open(dir.path().to_path_buf(), 100).unwrap();
+            for key in ["a", "b", "c"] {
+                cache.put(key, &[0; 10]).await.unwrap();
+            }
+        }
+        std::fs::write(dir.path().join("half.1234.tmp"), [0; 5]).unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "not an image").unwrap();
+
+        let cache = ImageCache::open(dir.path().to_path_buf(), 20).unwrap();
+
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.bytes, stats.evictions), (2, 20, 1));
+        assert!(!dir.path().join("half.1234.tmp").exists());
+        assert!(dir.path().join("notes.txt").exists());
+    }
+
+    /// Hands out the same picture for every request, counting how often it was asked
+    #[derive(Default)]
+    struct CountingProvider {
+        requests: AtomicU64,
+    }
+
+    #[async_trait]
+    impl ImageryProvider for CountingProvider {
+        fn name(&self) -> &'static str {
+            "counting"
+        }
+
+        async fn satellite_image(&self, _request: &SatelliteRequest) -> AppResult<Vec<u8>> {
+            self.requests.fetch_add(1, Ordering::Relaxed);
+            Ok(vec![7; 10])
+        }
+
+        async fn result_map(&self, _request: &ResultMapRequest) -> AppResult<Vec<u8>> {
+            self.requests.fetch_add(1, Ordering::Relaxed);
+            Ok(vec![8; 10])
+        }
+    }
+
+    #[tokio::test]
+    async fn the_provider_is_only_asked_for_images_not_cached_yet() {
+        let dir = TempDir::new();
+        let inner = Arc::new(CountingProvider::default());
+        let provider = CachedProvider::new(inner.clone(), ImageCache::open(dir.path().to_path_buf(), 100).unwrap());
+        let request = SatelliteRequest::new(43.6, -116.2);
+
+        assert_eq!(provider.satellite_image(&request).await.unwrap(), vec![7; 10]);
+        assert_eq!(provider.satellite_image(&request).await.unwrap(), vec![7; 10]);
+        assert_eq!(inner.requests.load(Ordering::Relaxed), 1);
+
+        provider.satellite_image(&SatelliteRequest::new(40.7, -74.0)).await.unwrap();
+        assert_eq!(inner.requests.load(Ordering::Relaxed), 2);
+
+        let stats = provider.cache_stats().unwrap();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 2, 2));
+    }
+}
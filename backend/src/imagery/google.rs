@@ -0,0 +1,92 @@
+use axum::async_trait;
+use tracing::info;
+
+use crate::crop_image_bytes;
+use crate::imagery::{ImageryProvider, ResultMapRequest, SatelliteRequest};
+use crate::AppResult;
+
+/// Gets images from the [Google Static Maps API](https://developers.google.com/maps/documentation/maps-static/overview)
+pub struct GoogleProvider {
+    api_key: String,
+    client: reqwest::Client,
+}
+
+impl GoogleProvider {
+    pub fn new(api_key: String) -> Self {
+        Self {
+            api_key,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    async fn fetch(&self, request_string: String) -> AppResult<Vec<u8>> {
+        let google_response = self
+            .client
+            .get(request_string)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let image_bytes = google_response.bytes().await?;
+
+        Ok(image_bytes.to_vec())
+    }
+}
+
+#[async_trait]
+impl ImageryProvider for GoogleProvider {
+    fn name(&self) -> &'static str {
+        "google"
+    }
+
+    /// Google sometimes prints the name of the place at the bottom of the picture,
+    /// so the bottom of the picture is always cut off, see [crop_image_bytes]
+    async fn satellite_image(&self, request: &SatelliteRequest) -> AppResult<Vec<u8>> {
+        let request_string = format!(
+            "https://maps.googleapis.com/maps/api/staticmap?key={}&sensor=true&size={}x{}&maptype={}&center={},%20{}&zoom={}",
+            self.api_key,
+            request.width,
+            request.height,
+            request.map_type.as_str(),
+            request.lat,
+            request.lng,
+            request.zoom
+        );
+
+        let image_bytes = self.fetch(request_string).await?;
+
+        crop_image_bytes(&image_bytes)
+    }
+
+    async fn result_map(&self, request: &ResultMapRequest) -> AppResult<Vec<u8>> {
+        let request_string = format!(
+            "https://maps.googleapis.com/maps/api/staticmap?\
+        maptype={map_type}&\
+        visible={guess_lat},{guess_lng}&\
+        visible={real_lat},{real_lng}&\
+        size={width}x{height}&\
+        markers=color:blue%7Clabel:Guess%7C{guess_lat},{guess_lng}&\
+        markers=color:red%7Ccolor:green%7C{real_lat},{real_lng}&\
+        path=color:0x0000ff|weight:5|{path}&\
+        key={key}",
+            guess_lat = request.guess_lat,
+            guess_lng = request.guess_lng,
+            real_lat = request.real_lat,
+            real_lng = request.real_lng,
+            path = request
+                .path()
+                .iter()
+                .map(|(lat, lng)| format!("{:.5},{:.5}", lat, lng))
+                .collect::<Vec<_>>()
+                .join("|"),
+            width = request.width,
+            height = request.height,
+            map_type = request.map_type.as_str(),
+            key = self.api_key
+        );
+
+        info!("Fetching result map from google");
+
+        self.fetch(request_string).await
+    }
+}
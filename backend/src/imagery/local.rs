@@ -0,0 +1,220 @@
+use std::io::{Cursor, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use axum::async_trait;
+use image::{ImageOutputFormat, Rgb, RgbImage};
+use tracing::debug;
+
+use crate::imagery::{ImageryProvider, ResultMapRequest, SatelliteRequest};
+use crate::AppResult;
+
+const BACKGROUND: Rgb<u8> = Rgb([46, 64, 51]);
+const GRID: Rgb<u8> = Rgb([60, 82, 66]);
+const OCEAN: Rgb<u8> = Rgb([28, 52, 84]);
+const GUESS_MARKER: Rgb<u8> = Rgb([40, 90, 230]);
+const REAL_MARKER: Rgb<u8> = Rgb([220, 40, 40]);
+const PATH: Rgb<u8> = Rgb([0, 0, 255]);
+
+/// Serves satellite images that were downloaded ahead of time, so the game can be developed and
+/// tested without an API key or a network connection.
+///
+/// Images are looked up in the `satellite` folder of the directory, first by the full request,
+/// then by location only:
+/// * `{lat}_{lng}_z{zoom}_{width}x{height}_{map_type}.png`
+/// * `{lat}_{lng}.png`
+/// * `default.png`
+///
+/// with the coordinates written with 4 decimal places, e.g. `40.7128_-74.0059.png`.
+/// When none of those exist a plain placeholder picture is made up instead.
+/// Result maps are always drawn locally.
+pub struct LocalProvider {
+    dir: PathBuf,
+}
+
+impl LocalProvider {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Every file name a satellite request could be stored under, most specific first
+    pub fn file_names(request: &SatelliteRequest) -> Vec<String> {
+        vec![
+            format!(
+                "{:.4}_{:.4}_z{}_{}x{}_{}.png",
+                request.lat,
+                request.lng,
+                request.zoom,
+                request.width,
+                request.height,
+                request.map_type.as_str()
+            ),
+            format!("{:.4}_{:.4}.png", request.lat, request.lng),
+            "default.png".to_string(),
+        ]
+    }
+
+    async fn read_if_exists(path: &Path) -> AppResult<Option<Vec<u8>>> {
+        match tokio::fs::read(path).await {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+#[async_trait]
+impl ImageryProvider for LocalProvider {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    async fn satellite_image(&self, request: &SatelliteRequest) -> AppResult<Vec<u8>> {
+        let satellite_dir = self.dir.join("satellite");
+
+        for file_name in Self::file_names(request) {
+            if let Some(bytes) = Self::read_if_exists(&satellite_dir.join(&file_name)).await? {
+                debug!("Serving local image {}", file_name);
+                return Ok(bytes);
+            }
+        }
+
+        debug!("No local image for {}, {}, using a placeholder", request.lat, request.lng);
+        placeholder(request.width, request.height)
+    }
+
+    async fn result_map(&self, request: &ResultMapRequest) -> AppResult<Vec<u8>> {
+        draw_result_map(request)
+    }
+}
+
+/// A plain picture with a grid on it, so it is obvious no real image was found
+fn placeholder(width: u32, height: u32) -> AppResult<Vec<u8>> {
+    let img = RgbImage::from_fn(width, height, |x, y| {
+        if x % 40 == 0 || y % 40 == 0 {
+            GRID
+        } else {
+            BACKGROUND
+        }
+    });
+
+    encode_png(img)
+}
+
+/// Draws both points of a result map onto a plain
+/// [equirectangular](https://en.wikipedia.org/wiki/Equirectangular_projection) map fitted around them
+fn draw_result_map(request: &ResultMapRequest) -> AppResult<Vec<u8>> {
+    let (width, height) = (request.width.max(1), request.height.max(1));
+
+    // Fit both points with some room around them, never zooming in closer than a couple of degrees
+    let center_lat = (request.guess_lat + request.real_lat) / 2.0;
+    let center_lng = (request.guess_lng + request.real_lng) / 2.0;
+    let span_lat = ((request.guess_lat - request.real_lat).abs() * 1.4).max(2.0);
+    let span_lng = ((request.guess_lng - request.real_lng).abs() * 1.4).max(2.0);
+
+    // Keep degrees square on the picture, whichever span needs more room decides the scale
+    let degrees_per_pixel = (span_lng / width as f32).max(span_lat / height as f32);
+
+    let to_pixel = |lat: f32, lng: f32| -> (i64, i64) {
+        let x = (lng - center_lng) / degrees_per_pixel + width as f32 / 2.0;
+        let y = (center_lat - lat) / degrees_per_pixel + height as f32 / 2.0;
+        (x.round() as i64, y.round() as i64)
+    };
+
+    let mut img = RgbImage::from_pixel(width, height, OCEAN);
+
+    // A line every 1, 5 or 10 degrees, depending on how far out the map is
+    let grid_step = match span_lng.max(span_lat) {
+        span if span < 10.0 => 1.0,
+        span if span < 60.0 => 5.0,
+        _ => 10.0,
+    };
+    let (min_lng, max_lng) = (
+        center_lng - width as f32 / 2.0 * degrees_per_pixel,
+        center_lng + width as f32 / 2.0 * degrees_per_pixel,
+    );
+    let (min_lat, max_lat) = (
+        center_lat - height as f32 / 2.0 * degrees_per_pixel,
+        center_lat + height as f32 / 2.0 * degrees_per_pixel,
+    );
+    let mut lng = (min_lng / grid_step).ceil() * grid_step;
+    while lng <= max_lng {
+        let (x, _) = to_pixel(center_lat, lng);
+        draw_line(&mut img, (x, 0), (x, height as i64), GRID);
+        lng += grid_step;
+    }
+    let mut lat = (min_lat / grid_step).ceil() * grid_step;
+    while lat <= max_lat {
+        let (_, y) = to_pixel(lat, center_lng);
+        draw_line(&mut img, (0, y), (width as i64, y), GRID);
+        lat += grid_step;
+    }
+
+    let guess = to_pixel(request.guess_lat, request.guess_lng);
+    let real = to_pixel(request.real_lat, request.real_lng);
+
+    // The shortest way between them, leaving out the piece that wraps around the antimeridian
+    for piece in request.path().windows(2) {
+        let ((from_lat, from_lng), (to_lat, to_lng)) = (piece[0], piece[1]);
+        if (from_lng - to_lng).abs() <= 180.0 {
+            draw_line(&mut img, to_pixel(from_lat, from_lng), to_pixel(to_lat, to_lng), PATH);
+        }
+    }
+    draw_marker(&mut img, guess, GUESS_MARKER);
+    draw_marker(&mut img, real, REAL_MARKER);
+
+    encode_png(img)
+}
+
+fn put_pixel(img: &mut RgbImage, x: i64, y: i64, color: Rgb<u8>) {
+    if x >= 0 && y >= 0 && x < img.width() as i64 && y < img.height() as i64 {
+        img.put_pixel(x as u32, y as u32, color);
+    }
+}
+
+/// [Bresenham's line](https://en.wikipedia.org/wiki/Bresenham%27s_line_algorithm), three pixels wide
+fn draw_line(img: &mut RgbImage, from: (i64, i64), to: (i64, i64), color: Rgb<u8>) {
+    let (mut x, mut y) = from;
+    let dx = (to.0 - x).abs();
+    let dy = -(to.1 - y).abs();
+    let step_x = if x < to.0 { 1 } else { -1 };
+    let step_y = if y < to.1 { 1 } else { -1 };
+    let mut error = dx + dy;
+
+    loop {
+        for offset in -1..=1 {
+            put_pixel(img, x + offset, y, color);
+            put_pixel(img, x, y + offset, color);
+        }
+
+        if x == to.0 && y == to.1 {
+            break;
+        }
+
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += step_y;
+        }
+    }
+}
+
+fn draw_marker(img: &mut RgbImage, center: (i64, i64), color: Rgb<u8>) {
+    let radius: i64 = 8;
+    for dx in -radius..=radius {
+        for dy in -radius..=radius {
+            if dx * dx + dy * dy <= radius * radius {
+                put_pixel(img, center.0 + dx, center.1 + dy, color);
+            }
+        }
+    }
+}
+
+fn encode_png(img: RgbImage) -> AppResult<Vec<u8>> {
+    let mut encoded = Vec::new();
+    img.write_to(&mut Cursor::new(&mut encoded), ImageOutputFormat::Png)?;
+    Ok(encoded)
+}
@@ -0,0 +1,281 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use axum::async_trait;
+use serde_derive::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::geodesy;
+use crate::imagery::cache::{with_cache_from_env, CacheStats};
+use crate::imagery::google::GoogleProvider;
+use crate::imagery::local::LocalProvider;
+use crate::imagery::prefetch::PoolStats;
+use crate::AppResult;
+
+pub mod cache;
+pub mod google;
+pub mod local;
+pub mod prefetch;
+
+/// Where the pictures shown to the players come from, shared by every request
+pub type Imagery = Arc<dyn ImageryProvider>;
+
+/// What kind of picture to take, or map to show. Stored as text in the database for the [preferences](crate::models::preferences::UserPreferences)
+#[derive(Serialize, Deserialize, sqlx::Type, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum MapType {
+    /// Nothing but the satellite photo
+    #[default]
+    Satellite,
+
+    /// The satellite photo with roads and labels drawn on top
+    Hybrid,
+
+    /// The plain street map
+    Roadmap,
+
+    /// The street map with hills and mountains shaded in
+    Terrain,
+}
+
+/// How a map type is shown on the settings page
+#[derive(Serialize, Clone, Copy, Debug)]
+pub struct MapTypeOption {
+    pub map_type: MapType,
+    pub name: &'static str,
+    pub description: &'static str,
+}
+
+const MAP_TYPE_OPTIONS: [MapTypeOption; 4] = [
+    MapTypeOption {
+        map_type: MapType::Satellite,
+        name: "Satellite",
+        description: "the satellite photo",
+    },
+    MapTypeOption {
+        map_type: MapType::Hybrid,
+        name: "Hybrid",
+        description: "the satellite photo with roads and names",
+    },
+    MapTypeOption {
+        map_type: MapType::Roadmap,
+        name: "Road map",
+        description: "the plain street map",
+    },
+    MapTypeOption {
+        map_type: MapType::Terrain,
+        name: "Terrain",
+        description: "the street map with hills and mountains",
+    },
+];
+
+impl MapType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MapType::Satellite => "satellite",
+            MapType::Hybrid => "hybrid",
+            MapType::Roadmap => "roadmap",
+            MapType::Terrain => "terrain",
+        }
+    }
+
+    /// Every map type, in the order the settings page lists them
+    pub fn options() -> &'static [MapTypeOption] {
+        &MAP_TYPE_OPTIONS
+    }
+
+    pub fn option(self) -> &'static MapTypeOption {
+        &MAP_TYPE_OPTIONS[self as usize]
+    }
+}
+
+impl FromStr for MapType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "satellite" => Ok(MapType::Satellite),
+            "hybrid" => Ok(MapType::Hybrid),
+            "roadmap" => Ok(MapType::Roadmap),
+            "terrain" => Ok(MapType::Terrain),
+            other => Err(format!("Unknown map type: {}", other)),
+        }
+    }
+}
+
+/// A satellite picture centered on a point
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct SatelliteRequest {
+    pub lat: f32,
+    pub lng: f32,
+    pub zoom: u8,
+    pub width: u32,
+    pub height: u32,
+    pub map_type: MapType,
+}
+
+impl SatelliteRequest {
+    /// The picture every round has been shown with so far, 640x400 satellite at zoom 14
+    pub fn new(lat: f32, lng: f32) -> Self {
+        Self {
+            lat,
+            lng,
+            zoom: 14,
+            width: 640,
+            height: 400,
+            map_type: MapType::Satellite,
+        }
+    }
+
+    /// A hash of everything that changes the picture, used to name cached images.
+    /// Anybody can work it out from the coordinates, so it must never be sent to the browser.
+    /// Coordinates are rounded to 6 decimal places, about 10 cm, so floating point noise doesn't split entries.
+    pub fn cache_key(&self) -> String {
+        let description = format!(
+            "satellite:{:.6}:{:.6}:{}:{}x{}:{}",
+            self.lat,
+            self.lng,
+            self.zoom,
+            self.width,
+            self.height,
+            self.map_type.as_str()
+        );
+
+        format!("{:016x}", fnv1a(description.as_bytes()))
+    }
+}
+
+/// How many straight pieces the line on a result map is drawn with
+const RESULT_PATH_SEGMENTS: usize = 32;
+
+/// A map of the results of a round, with a marker on the guess, a marker on the answer and a line between them
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct ResultMapRequest {
+    pub guess_lat: f32,
+    pub guess_lng: f32,
+    pub real_lat: f32,
+    pub real_lng: f32,
+    pub width: u32,
+    pub height: u32,
+
+    /// The kind of map drawn under the markers, picked in the users [preferences](crate::models::preferences::UserPreferences)
+    pub map_type: MapType,
+}
+
+impl ResultMapRequest {
+    pub fn new(guess_lat: f32, guess_lng: f32, real_lat: f32, real_lng: f32) -> Self {
+        Self {
+            guess_lat,
+            guess_lng,
+            real_lat,
+            real_lng,
+            width: 1000,
+            height: 600,
+            map_type: MapType::Satellite,
+        }
+    }
+
+    /// Points along the shortest way from the guess to the real location, for drawing the line between them.
+    /// On a flat map that is a curve rather than a straight line, unless both are on the same meridian or the equator.
+    ///
+    /// # Returns:
+    /// [Vec]<(f32, f32)> - Latitudes and longitudes, starting at the guess and ending at the real location
+    pub fn path(&self) -> Vec<(f32, f32)> {
+        geodesy::great_circle(
+            self.guess_lat as f64,
+            self.guess_lng as f64,
+            self.real_lat as f64,
+            self.real_lng as f64,
+            RESULT_PATH_SEGMENTS,
+        )
+        .into_iter()
+        .map(|(lat, lng)| (lat as f32, lng as f32))
+        .collect()
+    }
+
+    /// Same as [SatelliteRequest::cache_key], for result maps
+    pub fn cache_key(&self) -> String {
+        // The line used to be drawn straight, the new name keeps those images from being served again
+        let description = format!(
+            "result-geodesic:{:.6}:{:.6}:{:.6}:{:.6}:{}x{}:{}",
+            self.guess_lat,
+            self.guess_lng,
+            self.real_lat,
+            self.real_lng,
+            self.width,
+            self.height,
+            self.map_type.as_str()
+        );
+
+        format!("{:016x}", fnv1a(description.as_bytes()))
+    }
+}
+
+/// [FNV-1a](https://en.wikipedia.org/wiki/Fowler%E2%80%93Noll%E2%80%93Vo_hash_function), unlike the std hasher
+/// it is guaranteed to give the same result on every build, which matters for keys that outlive the process
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Everything worth knowing about how the images are being served
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ImageryStats {
+    /// Left out when the cache is turned off
+    pub cache: Option<CacheStats>,
+    pub round_pool: PoolStats,
+}
+
+/// Something that can hand us pictures of the Earth. Every image comes back as png bytes,
+/// ready to be shown to the player.
+#[async_trait]
+pub trait ImageryProvider: Send + Sync {
+    /// Short name of the provider, used for logging
+    fn name(&self) -> &'static str;
+
+    /// Fetches a satellite picture for a round. Anything that would give away the location,
+    /// like a caption on the picture, must already be removed.
+    async fn satellite_image(&self, request: &SatelliteRequest) -> AppResult<Vec<u8>>;
+
+    /// Fetches the map shown on the results page of a round
+    async fn result_map(&self, request: &ResultMapRequest) -> AppResult<Vec<u8>>;
+
+    /// How the cache in front of this provider is doing, if there is one
+    fn cache_stats(&self) -> Option<CacheStats> {
+        None
+    }
+}
+
+/// Picks the imagery provider from the .env file.
+///
+/// Without an `IMAGERY_PROVIDER` the Google provider is used when a `GOOGLE_KEY` is set,
+/// otherwise the local provider is used so the game still runs without a key or network.
+/// Either way the provider sits behind the image cache, see [with_cache_from_env].
+///
+/// # .env variables
+/// * IMAGERY_PROVIDER (optional) - `google` or `local`
+/// * GOOGLE_KEY - Needed by the Google provider
+/// * IMAGERY_DIR (optional) - Where the local provider looks for images, defaults to `resources/imagery`
+/// * IMAGE_CACHE_DIR, IMAGE_CACHE_MAX_MB (optional) - See [with_cache_from_env]
+pub fn provider_from_env() -> Imagery {
+    let google_key = std::env::var("GOOGLE_KEY").ok();
+    let provider_name = std::env::var("IMAGERY_PROVIDER")
+        .unwrap_or_else(|_| if google_key.is_some() { "google" } else { "local" }.to_string());
+
+    let provider: Imagery = match (provider_name.as_str(), google_key) {
+        ("google", Some(key)) => Arc::new(GoogleProvider::new(key)),
+        (name, _) => {
+            if name != "local" {
+                warn!("Imagery provider {} is not usable, falling back to local images", name);
+            }
+            let dir = std::env::var("IMAGERY_DIR").unwrap_or_else(|_| "resources/imagery".to_string());
+            Arc::new(LocalProvider::new(PathBuf::from(dir)))
+        }
+    };
+
+    info!("Using the {} imagery provider", provider.name());
+    with_cache_from_env(provider)
+}
@@ -0,0 +1,379 @@
+use std::collections::{HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde_derive::{Deserialize, Serialize};
+use tokio::sync::Notify;
+use tracing::{debug, info, warn};
+
+use crate::catalog::Catalog;
+use crate::imagery::{Imagery, SatelliteRequest};
+use crate::models::city::City;
+use crate::models::difficulty::Difficulty;
+use crate::models::filter::LocationFilter;
+use crate::AppResult;
+
+/// Longest the filler waits before trying again after the provider failed
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// How the round pool is doing, shown next to the cache stats
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct PoolStats {
+    pub ready: usize,
+    pub capacity: usize,
+    /// Rounds that were handed out straight from the pool
+    pub served_from_pool: u64,
+    /// Rounds that found the pool empty and got a city whose image wasn't fetched yet
+    pub served_directly: u64,
+    pub failed_fetches: u64,
+}
+
+struct PoolInner {
+    imagery: Imagery,
+    catalog: Catalog,
+    capacity: usize,
+    fetch_timeout: Duration,
+    ready: Mutex<VecDeque<City>>,
+    /// Woken whenever a round is taken, so the filler knows there is room again
+    taken: Notify,
+    served_from_pool: AtomicU64,
+    served_directly: AtomicU64,
+    failed_fetches: AtomicU64,
+}
+
+/// A bounded pool of random cities whose satellite images have already been fetched, cropped and put in the
+/// [image cache](crate::imagery::cache::ImageCache), so the round image shows up right away instead of
+/// waiting on the imagery provider.
+///
+/// A background task keeps the pool topped up. When the provider is slow or failing the task backs off
+/// and the pool simply runs dry, rounds then get a city whose image is fetched when the page asks for it.
+#[derive(Clone)]
+pub struct RoundPool {
+    inner: Arc<PoolInner>,
+}
+
+impl RoundPool {
+    /// Creates the pool and starts filling it in the background. A capacity of 0 never fetches anything,
+    /// every image is then fetched when the page asks for it.
+    ///
+    /// # Arguments:
+    /// * imagery: [Imagery] - Where the images come from
+    /// * catalog: [Catalog] - The cities to pick from
+    /// * capacity: usize - How many rounds are kept ready
+    /// * fetch_timeout: [Duration] - How long a single fetch may take before it counts as failed
+    pub fn start(imagery: Imagery, catalog: Catalog, capacity: usize, fetch_timeout: Duration) -> Self {
+        let pool = Self {
+            inner: Arc::new(PoolInner {
+                imagery,
+                catalog,
+                capacity,
+                fetch_timeout,
+                ready: Mutex::new(VecDeque::with_capacity(capacity)),
+                taken: Notify::new(),
+                served_from_pool: AtomicU64::new(0),
+                served_directly: AtomicU64::new(0),
+                failed_fetches: AtomicU64::new(0),
+            }),
+        };
+
+        if capacity > 0 {
+            tokio::spawn(Self::fill(pool.inner.clone()));
+        }
+
+        pool
+    }
+
+    /// Builds the pool from the .env file. Without an image cache there is nowhere to keep what was fetched,
+    /// so the pool is turned off.
+    ///
+    /// # .env variables
+    /// * ROUND_POOL_SIZE (optional) - How many rounds are kept ready, defaults to 8. 0 turns the pool off
+    /// * ROUND_POOL_TIMEOUT_SECS (optional) - How long a single fetch may take, defaults to 10 seconds
+    pub fn from_env(imagery: Imagery, catalog: Catalog) -> Self {
+        let capacity = std::env::var("ROUND_POOL_SIZE")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(8);
+        let capacity = if imagery.cache_stats().is_some() { capacity } else { 0 };
+        let timeout_secs = std::env::var("ROUND_POOL_TIMEOUT_SECS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(10);
+
+        info!("Keeping {} rounds ready in the background", capacity);
+
+        Self::start(imagery, catalog, capacity, Duration::from_secs(timeout_secs))
+    }
+
+    /// Takes a city whose image is ready out of the pool, if there is one
+    ///
+    /// # Returns:
+    /// [Option]<[City]>
+    pub fn pop(&self) -> Option<City> {
+        self.take(|_| true)
+    }
+
+    /// Takes the first ready city the predicate accepts out of the pool, counting the pool as empty when none does
+    fn take(&self, accept: impl Fn(&City) -> bool) -> Option<City> {
+        let city = {
+            let mut ready = self.inner.ready.lock().unwrap();
+            ready
+                .iter()
+                .position(accept)
+                .and_then(|position| ready.remove(position))
+        };
+
+        match city {
+            Some(_) => {
+                self.inner.served_from_pool.fetch_add(1, Ordering::Relaxed);
+                self.inner.taken.notify_one();
+            }
+            None => {
+                self.inner.served_directly.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        city
+    }
+
+    /// A random city, straight from the pool when possible, otherwise one whose image still has to be fetched
+    ///
+    /// # Returns:
+    /// [AppResult]<[City]>
+    pub fn random_city(&self) -> AppResult<City> {
+        match self.pop() {
+            Some(city) => Ok(city),
+            None => self.inner.catalog.random().cloned(),
+        }
+    }
+
+    /// A random city the filter lets through, for a game at the given difficulty. The pool is picked at random,
+    /// so it only has one ready when the filter is wide, narrow filters mostly get a city whose image still has to be fetched.
+    /// The pool only holds images the way medium games show them, other difficulties never take from it.
+    /// Excluded cities are only picked when nothing else matches, see [random_matching_outside](crate::catalog::CityCatalog::random_matching_outside).
+    ///
+    /// # Returns:
+    /// [AppResult]<[City]> - [AppError::InvalidFilter](crate::error::AppError::InvalidFilter) when no city matches
+    pub fn random_city_matching(&self, filter: &LocationFilter, difficulty: Difficulty, excluded: &HashSet<i32>) -> AppResult<City> {
+        let pooled = if difficulty.uses_default_image() {
+            self.take(|city| filter.matches(city) && !excluded.contains(&city.id))
+        } else {
+            None
+        };
+
+        match pooled {
+            Some(city) => Ok(city),
+            None => self.inner.catalog.random_matching_outside(filter, excluded).cloned(),
+        }
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            ready: self.inner.ready.lock().unwrap().len(),
+            capacity: self.inner.capacity,
+            served_from_pool: self.inner.served_from_pool.load(Ordering::Relaxed),
+            served_directly: self.inner.served_directly.load(Ordering::Relaxed),
+            failed_fetches: self.inner.failed_fetches.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Runs forever, fetching one image at a time whenever the pool has room
+    async fn fill(inner: Arc<PoolInner>) {
+        let mut backoff = Duration::from_secs(1);
+
+        loop {
+            // A round taken between the check and the wait leaves a permit behind, so this can't miss one
+            if inner.ready.lock().unwrap().len() >= inner.capacity {
+                inner.taken.notified().await;
+                continue;
+            }
+
+            let city = match inner.catalog.random() {
+                Ok(city) => city.clone(),
+                // Nothing to prefetch, don't spin on an empty catalog
+                Err(_) => return,
+            };
+            let request = SatelliteRequest::new(city.latitude, city.longitude);
+
+            // The image itself is thrown away, what matters is that it is in the cache now
+            match tokio::time::timeout(inner.fetch_timeout, inner.imagery.satellite_image(&request)).await {
+                Ok(Ok(_)) => {
+                    let mut ready = inner.ready.lock().unwrap();
+                    ready.push_back(city);
+                    debug!("Round pool has {} of {} rounds ready", ready.len(), inner.capacity);
+                    backoff = Duration::from_secs(1);
+                }
+                failed => {
+                    inner.failed_fetches.fetch_add(1, Ordering::Relaxed);
+                    match failed {
+                        Ok(Err(err)) => warn!("Prefetching a round failed, retrying in {:?}: {:?}", backoff, err),
+                        _ => warn!("Prefetching a round timed out, retrying in {:?}", backoff),
+                    }
+
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::async_trait;
+
+    use super::*;
+    use crate::catalog::CityCatalog;
+    use crate::imagery::cache::CacheStats;
+    use crate::imagery::{ImageryProvider, ResultMapRequest};
+
+    /// Answers every request right away without any network, with or without pretending to have a cache
+    #[derive(Default)]
+    struct StubProvider {
+        cached: bool,
+        requests: AtomicU64,
+    }
+
+    #[async_trait]
+    impl ImageryProvider for StubProvider {
+        fn name(&self) -> &'static str {
+            "stub"
+        }
+
+        async fn satellite_image(&self, _request: &SatelliteRequest) -> AppResult<Vec<u8>> {
+            self.requests.fetch_add(1, Ordering::Relaxed);
+            Ok(vec![0; 10])
+        }
+
+        async fn result_map(&self, _request: &ResultMapRequest) -> AppResult<Vec<u8>> {
+            Ok(vec![0; 10])
+        }
+
+        fn cache_stats(&self) -> Option<CacheStats> {
+            self.cached.then(CacheStats::default)
+        }
+    }
+
+    fn city(id: i32, state: &str) -> City {
+        City {
+            id,
+            city: format!("City {}", id),
+            state: state.to_string(),
+            country_code: "US".to_string(),
+            population: 1000,
+            rank: id,
+            ..City::default()
+        }
+    }
+
+    fn catalog(cities: Vec<City>) -> Catalog {
+        Arc::new(CityCatalog::from_cities(cities))
+    }
+
+    /// Gives the filler the time to top the pool up
+    async fn filled(pool: &RoundPool) {
+        for _ in 0..200 {
+            if pool.stats().ready >= pool.stats().capacity {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        panic!("The pool never filled up: {:?}", pool.stats());
+    }
+
+    fn pooled_ids(pool: &RoundPool) -> Vec<i32> {
+        pool.inner.ready.lock().unwrap().iter().map(|city| city.id).collect()
+    }
+
+    #[tokio::test]
+    async fn the_pool_is_filled_through_the_provider() {
+        let provider = Arc::new(StubProvider { cached: true, ..StubProvider::default() });
+        let pool = RoundPool::start(provider.clone(), catalog(vec![city(1, "Idaho")]), 3, Duration::from_secs(1));
+
+        filled(&pool).await;
+
+        assert_eq!(pool.stats().ready, 3);
+        assert!(provider.requests.load(Ordering::Relaxed) >= 3);
+        assert_eq!(pool.pop().map(|city| city.id), Some(1));
+        assert_eq!(pool.stats().served_from_pool, 1);
+    }
+
+    #[tokio::test]
+    async fn medium_games_take_matching_cities_from_the_pool() {
+        let provider = Arc::new(StubProvider { cached: true, ..StubProvider::default() });
+        let pool = RoundPool::start(provider, catalog(vec![city(1, "Idaho")]), 2, Duration::from_secs(1));
+        filled(&pool).await;
+
+        let city = pool
+            .random_city_matching(&LocationFilter::default(), Difficulty::Medium, &HashSet::new())
+            .unwrap();
+
+        assert_eq!(city.id, 1);
+        assert_eq!(pool.stats().served_from_pool, 1);
+    }
+
+    #[tokio::test]
+    async fn other_difficulties_never_take_from_the_pool() {
+        let provider = Arc::new(StubProvider { cached: true, ..StubProvider::default() });
+        let pool = RoundPool::start(provider, catalog(vec![city(1, "Idaho")]), 2, Duration::from_secs(1));
+        filled(&pool).await;
+
+        for difficulty in [Difficulty::Easy, Difficulty::Hard, Difficulty::Expert] {
+            let city = pool
+                .random_city_matching(&LocationFilter::default(), difficulty, &HashSet::new())
+                .unwrap();
+            assert_eq!(city.id, 1);
+        }
+
+        let stats = pool.stats();
+        assert_eq!((stats.ready, stats.served_from_pool), (2, 0));
+    }
+
+    #[tokio::test]
+    async fn cities_the_filter_rejects_stay_in_the_pool() {
+        let provider = Arc::new(StubProvider { cached: true, ..StubProvider::default() });
+        let pool = RoundPool::start(
+            provider,
+            catalog(vec![city(1, "Idaho"), city(2, "Nevada")]),
+            1,
+            Duration::from_secs(1),
+        );
+        filled(&pool).await;
+        let ready = pooled_ids(&pool)[0];
+        let other = if ready == 1 { city(2, "Nevada") } else { city(1, "Idaho") };
+
+        let filter = LocationFilter {
+            state: Some(other.state.clone()),
+            ..LocationFilter::default()
+        };
+        let picked = pool.random_city_matching(&filter, Difficulty::Medium, &HashSet::new()).unwrap();
+
+        assert_eq!(picked.id, other.id);
+        assert_eq!(pooled_ids(&pool), vec![ready]);
+        let stats = pool.stats();
+        assert_eq!((stats.served_from_pool, stats.served_directly), (0, 1));
+
+        // Excluded cities are left in the pool as well
+        let excluded = HashSet::from([ready]);
+        pool.random_city_matching(&LocationFilter::default(), Difficulty::Medium, &excluded).unwrap();
+        assert_eq!(pooled_ids(&pool), vec![ready]);
+    }
+
+    #[tokio::test]
+    async fn the_pool_is_off_without_a_cache() {
+        let provider = Arc::new(StubProvider::default());
+        let pool = RoundPool::from_env(provider.clone(), catalog(vec![city(1, "Idaho")]));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        assert_eq!(pool.stats().capacity, 0);
+        assert_eq!(provider.requests.load(Ordering::Relaxed), 0);
+
+        let city = pool
+            .random_city_matching(&LocationFilter::default(), Difficulty::Medium, &HashSet::new())
+            .unwrap();
+        assert_eq!(city.id, 1);
+        let stats = pool.stats();
+        assert_eq!((stats.served_from_pool, stats.served_directly), (0, 1));
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde_json::Value;
+
+use crate::error::AppError;
+use crate::models::city::RawCity;
+use crate::AppResult;
+
+/// Fields of a city that can be read from a csv column
+const FIELDS: [&str; 10] = [
+    "id",
+    "city",
+    "state",
+    "country_code",
+    "latitude",
+    "longitude",
+    "population",
+    "rank",
+    "growth_from_2000_to_2013",
+    "alternate_names",
+];
+
+/// Alternate names are kept in a single column, separated by this
+const ALTERNATE_NAME_SEPARATOR: char = '|';
+
+/// Which column of a csv file holds which field of a city, by column header.
+/// Fields that aren't mapped are read from a column with the same name as the field, if there is one.
+///
+/// Written as `field=column` pairs separated by commas, e.g. `city=name,latitude=lat,longitude=lon`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ColumnMapping {
+    columns: HashMap<String, String>,
+}
+
+impl ColumnMapping {
+    /// The header of the column holding the given field
+    pub fn column_for<'a>(&'a self, field: &'a str) -> &'a str {
+        self.columns.get(field).map(String::as_str).unwrap_or(field)
+    }
+}
+
+impl FromStr for ColumnMapping {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut columns = HashMap::new();
+
+        for pair in s.split(',').filter(|pair| !pair.trim().is_empty()) {
+            let (field, column) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("Column mapping {:?} should look like field=column", pair))?;
+            let field = field.trim();
+
+            if !FIELDS.contains(&field) {
+                return Err(format!("Unknown city field {:?}, expected one of {}", field, FIELDS.join(", ")));
+            }
+
+            columns.insert(field.to_string(), column.trim().to_string());
+        }
+
+        Ok(Self { columns })
+    }
+}
+
+/// Reads cities from a csv file with a header row
+///
+/// # Arguments:
+/// * path: &[Path] - The csv file
+/// * mapping: &[ColumnMapping] - Which column holds which field
+/// * delimiter: u8 - What separates the fields, usually `,` or `;`
+///
+/// # Returns:
+/// [AppResult]<[Vec]<[RawCity]>>
+pub fn read(path: &Path, mapping: &ColumnMapping, delimiter: u8) -> AppResult<Vec<RawCity>> {
+    let file = std::fs::File::open(path)?;
+
+    parse(file, mapping, delimiter)
+}
+
+/// Reads cities from csv text with a header row, see [read]
+///
+/// # Returns:
+/// [AppResult]<[Vec]<[RawCity]>>
+pub fn parse(contents: impl Read, mapping: &ColumnMapping, delimiter: u8) -> AppResult<Vec<RawCity>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_reader(contents);
+
+    let headers = reader.headers().map_err(csv_error)?.clone();
+    let position: HashMap<&str, usize> = FIELDS
+        .iter()
+        .filter_map(|field| {
+            let column = mapping.column_for(field);
+            headers.iter().position(|header| header.trim() == column).map(|index| (*field, index))
+        })
+        .collect();
+
+    let mut cities = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(csv_error)?;
+
+        let text = |field: &str| {
+            position
+                .get(field)
+                .and_then(|&index| record.get(index))
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        };
+
+        cities.push(RawCity {
+            id: text("id").map(Value::from),
+            city: text("city"),
+            growth_from_2000_to_2013: text("growth_from_2000_to_2013").map(Value::from),
+            latitude: text("latitude").map(Value::from),
+            longitude: text("longitude").map(Value::from),
+            population: text("population").map(Value::from),
+            rank: text("rank").map(Value::from),
+            state: text("state"),
+            country_code: text("country_code"),
+            alternate_names: text("alternate_names")
+                .map(|names| names.split(ALTERNATE_NAME_SEPARATOR).map(str::to_string).collect()),
+        });
+    }
+
+    Ok(cities)
+}
+
+fn csv_error(err: csv::Error) -> AppError {
+    AppError::Any(anyhow::anyhow!("Could not read csv file: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::CityCatalog;
+    use crate::models::city::CityProblem;
+
+    fn parse_csv(contents: &str, mapping: &str) -> Vec<RawCity> {
+        parse(contents.as_bytes(), &mapping.parse().unwrap(), b',').unwrap()
+    }
+
+    #[test]
+    fn columns_can_be_mapped_to_fields() {
+        let cities = parse_csv(
+            "name,lat,lon,people,state,rank,alternate_names\n Boise City ,43.6,-116.2,214237,Idaho,1,Boise|BOI\n",
+            "city=name,latitude=lat,longitude=lon,population=people",
+        );
+
+        assert_eq!(cities.len(), 1);
+        assert_eq!(cities[0].city.as_deref(), Some("Boise City"));
+        assert_eq!(cities[0].latitude, Some(Value::from("43.6")));
+        assert_eq!(cities[0].population, Some(Value::from("214237")));
+        assert_eq!(cities[0].alternate_names, Some(vec!["Boise".to_string(), "BOI".to_string()]));
+        assert_eq!(cities[0].id, None);
+    }
+
+    #[test]
+    fn other_delimiters_are_read() {
+        let mapping = ColumnMapping::default();
+        let cities = parse("city;state\nNampa;Idaho\n".as_bytes(), &mapping, b';').unwrap();
+
+        assert_eq!(cities[0].state.as_deref(), Some("Idaho"));
+    }
+
+    #[test]
+    fn bad_mappings_are_rejected() {
+        assert!("town=name".parse::<ColumnMapping>().unwrap_err().contains("Unknown city field"));
+        assert!("city".parse::<ColumnMapping>().unwrap_err().contains("field=column"));
+        assert_eq!("".parse::<ColumnMapping>().unwrap(), ColumnMapping::default());
+    }
+
+    #[test]
+    fn columns_missing_from_the_header_leave_the_field_empty() {
+        // The mapping points at a column the file doesn't have
+        let cities = parse_csv(
+            "city,state,latitude,longitude,population,rank\nBoise City,Idaho,43.6,-116.2,214237,1\n",
+            "latitude=lat",
+        );
+        assert_eq!(cities[0].latitude, None);
+        assert_eq!(cities[0].longitude, Some(Value::from("-116.2")));
+
+        let catalog = CityCatalog::from_raw(cities);
+        assert_eq!(catalog.report().loaded, 0);
+        assert_eq!(catalog.report().issues[0].problem, CityProblem::MissingField { field: "latitude" });
+    }
+
+    #[test]
+    fn bad_rows_are_reported_by_validation() {
+        let cities = parse_csv(
+            "city,state,latitude,longitude,population,rank\n\
+             Boise City,Idaho,43.6,-116.2,214237,1\n\
+             Nampa,Idaho,north,-116.5,86518,2\n\
+             Meridian,Idaho\n",
+            "",
+        );
+        assert_eq!(cities.len(), 3);
+
+        let catalog = CityCatalog::from_raw(cities);
+        let report = catalog.report();
+        assert_eq!((report.entries, report.loaded), (3, 1));
+        assert!(report.issues.iter().any(|issue| issue.entry == 1
+            && issue.problem == CityProblem::InvalidField { field: "latitude", value: "north".to_string() }));
+        assert!(report
+            .issues
+            .iter()
+            .any(|issue| issue.entry == 2 && issue.problem == CityProblem::MissingField { field: "rank" }));
+    }
+
+    #[test]
+    fn duplicate_ids_keep_the_first_city() {
+        let cities = parse_csv(
+            "id,city,state,latitude,longitude,population,rank\n\
+             10,Boise City,Idaho,43.6,-116.2,214237,1\n\
+             10,Nampa,Idaho,43.5,-116.5,86518,2\n",
+            "",
+        );
+
+        let catalog = CityCatalog::from_raw(cities);
+        assert_eq!(catalog.len(), 1);
+        assert_eq!(catalog.find(10).unwrap().city, "Boise City");
+        assert_eq!(catalog.report().issues[0].problem, CityProblem::DuplicateId { id: 10 });
+    }
+}
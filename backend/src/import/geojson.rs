@@ -0,0 +1,169 @@
+use std::path::Path;
+
+use serde_json::{Map, Value};
+
+use crate::error::AppError;
+use crate::models::city::RawCity;
+use crate::AppResult;
+
+/// Reads cities from a GeoJSON FeatureCollection of points.
+///
+/// The name, admin region and country are looked up under a few common property names,
+/// so files from most sources work as they are:
+/// * name: `city`, `name`
+/// * admin region: `state`, `admin1`, `admin`, `region`
+/// * country: `country_code`, `iso_a2`, `country`
+/// * alternate names: `alternate_names`, either a list or separated by `|`
+///
+/// Features that aren't points come back without coordinates, validation reports and skips them.
+///
+/// # Returns:
+/// [AppResult]<[Vec]<[RawCity]>>
+pub fn read(path: &Path) -> AppResult<Vec<RawCity>> {
+    let contents = std::fs::read_to_string(path)?;
+
+    parse(&contents).map_err(|_| AppError::Any(anyhow::anyhow!("{} is not a GeoJSON FeatureCollection", path.display())))
+}
+
+/// Reads the features of a GeoJSON FeatureCollection, see [read]
+///
+/// # Returns:
+/// [AppResult]<[Vec]<[RawCity]>>
+pub fn parse(contents: &str) -> AppResult<Vec<RawCity>> {
+    let collection: Value = serde_json::from_str(contents)?;
+
+    let features = collection
+        .get("features")
+        .and_then(Value::as_array)
+        .ok_or_else(|| AppError::Any(anyhow::anyhow!("Not a GeoJSON FeatureCollection")))?;
+
+    Ok(features.iter().map(city_from_feature).collect())
+}
+
+fn city_from_feature(feature: &Value) -> RawCity {
+    let empty = Map::new();
+    let properties = feature
+        .get("properties")
+        .and_then(Value::as_object)
+        .unwrap_or(&empty);
+
+    let property = |names: &[&str]| {
+        names
+            .iter()
+            .filter_map(|name| properties.get(*name))
+            .find(|value| !value.is_null())
+            .cloned()
+    };
+    let text = |names: &[&str]| match property(names)? {
+        Value::String(text) => Some(text),
+        Value::Number(number) => Some(number.to_string()),
+        _ => None,
+    };
+
+    // GeoJSON positions are longitude first
+    let point = feature
+        .get("geometry")
+        .filter(|geometry| geometry.get("type").and_then(Value::as_str) == Some("Point"))
+        .and_then(|geometry| geometry.get("coordinates"))
+        .and_then(Value::as_array);
+    let coordinate = |index: usize| point.and_then(|position| position.get(index)).cloned();
+
+    let alternate_names = match property(&["alternate_names"]) {
+        Some(Value::Array(names)) => Some(
+            names
+                .iter()
+                .filter_map(|name| name.as_str().map(str::to_string))
+                .collect(),
+        ),
+        Some(Value::String(names)) => Some(names.split('|').map(str::to_string).collect()),
+        _ => None,
+    };
+
+    RawCity {
+        id: property(&["id"]).or_else(|| feature.get("id").cloned()),
+        city: text(&["city", "name"]),
+        growth_from_2000_to_2013: property(&["growth_from_2000_to_2013"]),
+        latitude: coordinate(1),
+        longitude: coordinate(0),
+        population: property(&["population"]),
+        rank: property(&["rank"]),
+        state: text(&["state", "admin1", "admin", "region"]),
+        country_code: text(&["country_code", "iso_a2", "country"]),
+        alternate_names,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::CityCatalog;
+    use crate::models::city::CityProblem;
+
+    fn parse_features(features: &str) -> Vec<RawCity> {
+        parse(&format!(r#"{{"type": "FeatureCollection", "features": [{}]}}"#, features)).unwrap()
+    }
+
+    #[test]
+    fn properties_are_found_under_common_names() {
+        let cities = parse_features(
+            r#"{"type": "Feature", "id": 12, "geometry": {"type": "Point", "coordinates": [-116.2, 43.6]},
+                "properties": {"name": "Boise City", "admin1": "Idaho", "iso_a2": "US", "population": 214237,
+                               "rank": 3, "alternate_names": "Boise|BOI"}}"#,
+        );
+
+        let city = &cities[0];
+        assert_eq!(city.id, Some(Value::from(12)));
+        assert_eq!(city.city.as_deref(), Some("Boise City"));
+        assert_eq!(city.state.as_deref(), Some("Idaho"));
+        assert_eq!(city.country_code.as_deref(), Some("US"));
+        assert_eq!((city.latitude.clone(), city.longitude.clone()), (Some(Value::from(43.6)), Some(Value::from(-116.2))));
+        assert_eq!(city.alternate_names, Some(vec!["Boise".to_string(), "BOI".to_string()]));
+    }
+
+    #[test]
+    fn anything_but_a_feature_collection_is_an_error() {
+        assert!(parse(r#"{"type": "Feature"}"#).is_err());
+        assert!(parse("not json").is_err());
+    }
+
+    #[test]
+    fn bad_features_are_reported_by_validation() {
+        let cities = parse_features(
+            r#"{"type": "Feature", "geometry": {"type": "LineString", "coordinates": [[0, 0], [1, 1]]},
+                "properties": {"city": "Road", "state": "Idaho", "population": 1, "rank": 1}},
+               {"type": "Feature", "geometry": {"type": "Point", "coordinates": [-116.2, 95.0]},
+                "properties": {"city": "Nowhere", "state": "Idaho", "population": 1, "rank": 2}},
+               {"type": "Feature", "geometry": {"type": "Point", "coordinates": [-116.5, 43.5]},
+                "properties": {"city": "Nampa", "state": "Idaho", "population": -1, "rank": 3}}"#,
+        );
+        assert_eq!(cities[0].latitude, None);
+
+        let catalog = CityCatalog::from_raw(cities);
+        let report = catalog.report();
+        assert_eq!((report.entries, report.loaded, report.skipped()), (3, 0, 3));
+        let problems: Vec<(usize, &CityProblem)> = report.issues.iter().map(|issue| (issue.entry, &issue.problem)).collect();
+        assert_eq!(
+            problems,
+            vec![
+                (0, &CityProblem::MissingField { field: "latitude" }),
+                (0, &CityProblem::MissingField { field: "longitude" }),
+                (1, &CityProblem::OutOfRange { field: "latitude", value: 95.0 }),
+                (2, &CityProblem::OutOfRange { field: "population", value: -1.0 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicate_ids_keep_the_first_city() {
+        let cities = parse_features(
+            r#"{"type": "Feature", "id": 1, "geometry": {"type": "Point", "coordinates": [-116.2, 43.6]},
+                "properties": {"city": "Boise City", "state": "Idaho", "population": 214237, "rank": 1}},
+               {"type": "Feature", "geometry": {"type": "Point", "coordinates": [-116.5, 43.5]},
+                "properties": {"id": 1, "city": "Nampa", "state": "Idaho", "population": 86518, "rank": 2}}"#,
+        );
+
+        let catalog = CityCatalog::from_raw(cities);
+        assert_eq!(catalog.len(), 1);
+        assert_eq!(catalog.report().issues[0].problem, CityProblem::DuplicateId { id: 1 });
+    }
+}
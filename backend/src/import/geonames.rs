@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::models::city::RawCity;
+use crate::AppResult;
+
+// Columns of the tab separated GeoNames dump, see "geoname" in https://download.geonames.org/export/dump/readme.txt
+const GEONAME_ID: usize = 0;
+const NAME: usize = 1;
+const ASCII_NAME: usize = 2;
+const ALTERNATE_NAMES: usize = 3;
+const LATITUDE: usize = 4;
+const LONGITUDE: usize = 5;
+const FEATURE_CLASS: usize = 6;
+const COUNTRY_CODE: usize = 8;
+const ADMIN1_CODE: usize = 10;
+const POPULATION: usize = 14;
+
+/// Reads a GeoNames `citiesNNNN.txt` dump. The geoname id becomes the city id, so reimporting
+/// a newer dump keeps pointing old rounds at the same cities.
+///
+/// GeoNames only has codes for admin regions. Given `admin1CodesASCII.txt` they are turned into names,
+/// otherwise the code is used, and cities without any admin region get their country code instead.
+///
+/// # Arguments:
+/// * path: &[Path] - The cities dump
+/// * admin_names: [Option]<&[Path]> - The `admin1CodesASCII.txt` file of the same dump
+///
+/// # Returns:
+/// [AppResult]<[Vec]<[RawCity]>>
+pub fn read(path: &Path, admin_names: Option<&Path>) -> AppResult<Vec<RawCity>> {
+    let admin_names = match admin_names {
+        Some(admin_path) => parse_admin_names(&std::fs::read_to_string(admin_path)?),
+        None => HashMap::new(),
+    };
+
+    Ok(parse(&std::fs::read_to_string(path)?, &admin_names))
+}
+
+/// Reads the lines of a GeoNames dump, see [read]
+///
+/// # Arguments:
+/// * contents: &str - The tab separated dump
+/// * admin_names: &[HashMap]<[String], [String]> - Names of admin regions by code, like `US.CA`
+///
+/// # Returns:
+/// [Vec]<[RawCity]>
+pub fn parse(contents: &str, admin_names: &HashMap<String, String>) -> Vec<RawCity> {
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(|line| line.split('\t').collect::<Vec<&str>>())
+        // Only populated places, in case a full country dump is imported
+        .filter(|columns| columns.get(FEATURE_CLASS).map_or(true, |class| class.is_empty() || *class == "P"))
+        .map(|columns| city_from_columns(&columns, admin_names))
+        .collect()
+}
+
+fn city_from_columns(columns: &[&str], admin_names: &HashMap<String, String>) -> RawCity {
+    // Missing columns are left empty, validation reports them
+    let column = |index: usize| {
+        columns
+            .get(index)
+            .map(|value| value.trim())
+            .filter(|value| !value.is_empty())
+    };
+
+    let name = column(NAME);
+    let country_code = column(COUNTRY_CODE);
+
+    let state = match (country_code, column(ADMIN1_CODE)) {
+        (Some(country), Some(admin)) => Some(
+            admin_names
+                .get(&format!("{}.{}", country, admin))
+                .cloned()
+                .unwrap_or_else(|| admin.to_string()),
+        ),
+        (country, None) => country.map(str::to_string),
+        (None, Some(admin)) => Some(admin.to_string()),
+    };
+
+    let mut alternate_names: Vec<String> = column(ALTERNATE_NAMES)
+        .map(|names| names.split(',').map(str::to_string).collect())
+        .unwrap_or_default();
+    if let Some(ascii_name) = column(ASCII_NAME) {
+        if Some(ascii_name) != name && !alternate_names.iter().any(|n| n == ascii_name) {
+            alternate_names.insert(0, ascii_name.to_string());
+        }
+    }
+    alternate_names.retain(|alternate| Some(alternate.as_str()) != name);
+
+    RawCity {
+        id: column(GEONAME_ID).map(|id| Value::from(id)),
+        city: name.map(str::to_string),
+        growth_from_2000_to_2013: None,
+        latitude: column(LATITUDE).map(Value::from),
+        longitude: column(LONGITUDE).map(Value::from),
+        population: column(POPULATION).map(Value::from),
+        rank: None,
+        state,
+        country_code: country_code.map(str::to_string),
+        alternate_names: Some(alternate_names),
+    }
+}
+
+/// Reads `admin1CodesASCII.txt`, lines like `US.CA<tab>California<tab>California<tab>5332921`
+fn parse_admin_names(contents: &str) -> HashMap<String, String> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut columns = line.split('\t');
+            let code = columns.next()?.trim();
+            let name = columns.next()?.trim();
+            (!code.is_empty() && !name.is_empty()).then(|| (code.to_string(), name.to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::CityCatalog;
+    use crate::models::city::CityProblem;
+
+    /// A line of the dump for a populated place, with the given columns filled in and the ones the importer doesn't read left empty
+    fn line(values: &[(usize, &str)]) -> String {
+        let mut columns = vec![""; 19];
+        columns[LATITUDE] = "47.6062";
+        columns[LONGITUDE] = "-122.33207";
+        columns[FEATURE_CLASS] = "P";
+        for &(index, value) in values {
+            columns[index] = value;
+        }
+        columns.join("\t")
+    }
+
+    fn place(id: &str, name: &str, country: &str, admin: &str) -> String {
+        line(&[(GEONAME_ID, id), (NAME, name), (ASCII_NAME, name), (COUNTRY_CODE, country), (ADMIN1_CODE, admin)])
+    }
+
+    fn admin_names() -> HashMap<String, String> {
+        parse_admin_names("US.WA\tWashington\tWashington\t5815762\nUS.OR\tOregon\tOregon\t5744886\n\n")
+    }
+
+    #[test]
+    fn admin_codes_become_names() {
+        let dump = [
+            place("5809844", "Seattle", "US", "WA"),
+            place("1", "Unnamed", "US", "ZZ"),
+            place("2", "Monaco", "MC", ""),
+        ]
+        .join("\n");
+        let cities = parse(&dump, &admin_names());
+
+        let states: Vec<Option<&str>> = cities.iter().map(|city| city.state.as_deref()).collect();
+        assert_eq!(states, vec![Some("Washington"), Some("ZZ"), Some("MC")]);
+        assert_eq!(cities[0].id, Some(Value::from("5809844")));
+        assert_eq!(cities[0].rank, None);
+    }
+
+    #[test]
+    fn the_ascii_name_is_kept_as_an_alternate_name() {
+        let dump = line(&[(NAME, "Zürich"), (ASCII_NAME, "Zurich"), (ALTERNATE_NAMES, "Zurigo,Zürich,Zurich")]);
+        let cities = parse(&dump, &HashMap::new());
+
+        assert_eq!(cities[0].alternate_names, Some(vec!["Zurigo".to_string(), "Zurich".to_string()]));
+    }
+
+    #[test]
+    fn comments_blank_lines_and_other_features_are_left_out() {
+        let dump = format!(
+            "# geonames\n\n{}\n{}\n",
+            line(&[(NAME, "Mount Rainier"), (FEATURE_CLASS, "T")]),
+            place("5", "Tacoma", "US", "WA"),
+        );
+        let cities = parse(&dump, &admin_names());
+
+        assert_eq!(cities.len(), 1);
+        assert_eq!(cities[0].city.as_deref(), Some("Tacoma"));
+    }
+
+    #[test]
+    fn short_rows_are_reported_by_validation() {
+        let dump = format!("6\tCut Off\n{}", line(&[(GEONAME_ID, "7"), (NAME, "Spokane"), (COUNTRY_CODE, "US"), (ADMIN1_CODE, "WA"), (POPULATION, "210721")]));
+        let mut cities = parse(&dump, &admin_names());
+        assert_eq!(cities.len(), 2);
+        assert_eq!(cities[0].latitude, None);
+
+        // Imports rank the cities before validating them
+        crate::import::assign_ranks(&mut cities);
+        let catalog = CityCatalog::from_raw(cities);
+        assert_eq!(catalog.report().loaded, 1);
+        assert!(catalog.report().issues.iter().all(|issue| issue.entry == 0 && issue.skipped));
+        assert!(catalog
+            .report()
+            .issues
+            .iter()
+            .any(|issue| issue.problem == CityProblem::MissingField { field: "latitude" }));
+    }
+
+    #[test]
+    fn duplicate_geoname_ids_keep_the_first_city() {
+        let dump = [
+            line(&[(GEONAME_ID, "8"), (NAME, "Portland"), (COUNTRY_CODE, "US"), (ADMIN1_CODE, "OR"), (POPULATION, "632309")]),
+            line(&[(GEONAME_ID, "8"), (NAME, "Salem"), (COUNTRY_CODE, "US"), (ADMIN1_CODE, "OR"), (POPULATION, "160614")]),
+        ]
+        .join("\n");
+        let mut cities = parse(&dump, &admin_names());
+        crate::import::assign_ranks(&mut cities);
+
+        let catalog = CityCatalog::from_raw(cities);
+        assert_eq!(catalog.find(8).unwrap().city, "Portland");
+        assert_eq!(catalog.report().issues[0].problem, CityProblem::DuplicateId { id: 8 });
+    }
+}
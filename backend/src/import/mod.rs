@@ -0,0 +1,297 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde_json::Value;
+
+use crate::catalog::CityCatalog;
+use crate::db::{new_pool, Store};
+use crate::error::AppError;
+use crate::models::city::{RawCity, ValidationReport};
+use crate::AppResult;
+
+pub mod csv_table;
+pub mod geojson;
+pub mod geonames;
+
+/// The kinds of files cities can be imported from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImportFormat {
+    /// A [GeoNames](https://download.geonames.org/export/dump/) `citiesNNNN.txt` dump
+    GeoNames,
+
+    /// Any csv file, with a [ColumnMapping](csv_table::ColumnMapping) saying which column holds what
+    Csv,
+
+    /// A GeoJSON FeatureCollection of points
+    GeoJson,
+}
+
+impl FromStr for ImportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "geonames" => Ok(ImportFormat::GeoNames),
+            "csv" => Ok(ImportFormat::Csv),
+            "geojson" => Ok(ImportFormat::GeoJson),
+            other => Err(format!("Unknown import format: {}", other)),
+        }
+    }
+}
+
+/// Everything an import needs to know, see [ImportOptions::from_args] for how to give them on the command line
+#[derive(Clone, Debug)]
+pub struct ImportOptions {
+    pub format: ImportFormat,
+    pub input: PathBuf,
+    pub output: PathBuf,
+
+    /// GeoNames `admin1CodesASCII.txt`, turns admin codes like `US.CA` into names like `California`
+    pub admin_names: Option<PathBuf>,
+
+    /// Which csv column holds which field
+    pub columns: csv_table::ColumnMapping,
+
+    /// Field delimiter of csv files
+    pub delimiter: u8,
+
+    /// Cities with fewer people than this are left out
+    pub min_population: u64,
+}
+
+const USAGE: &str = "Usage: import_cities <geonames|csv|geojson> <input> <output.json> \
+[--admin-names admin1CodesASCII.txt] [--columns field=column,...] [--delimiter ,] [--min-population 0]";
+
+impl ImportOptions {
+    /// Reads the options from command line arguments, without the name of the program
+    ///
+    /// # Returns:
+    /// [AppResult]<[Self](ImportOptions)>
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> AppResult<Self> {
+        let usage = |problem: String| AppError::Any(anyhow::anyhow!("{}\n{}", problem, USAGE));
+
+        let mut positional = Vec::new();
+        let mut admin_names = None;
+        let mut columns = csv_table::ColumnMapping::default();
+        let mut delimiter = b',';
+        let mut min_population = 0;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                positional.push(arg);
+                continue;
+            }
+
+            let value = args
+                .next()
+                .ok_or_else(|| usage(format!("{} needs a value", arg)))?;
+
+            match arg.as_str() {
+                "--admin-names" => admin_names = Some(PathBuf::from(value)),
+                "--columns" => columns = value.parse().map_err(usage)?,
+                "--delimiter" => {
+                    delimiter = match value.as_bytes() {
+                        [byte] => *byte,
+                        _ if value == "\\t" => b'\t',
+                        _ => return Err(usage(format!("Delimiter must be a single character, not {}", value))),
+                    }
+                }
+                "--min-population" => {
+                    min_population = value
+                        .parse()
+                        .map_err(|_| usage(format!("Minimum population must be a number, not {}", value)))?
+                }
+                other => return Err(usage(format!("Unknown option {}", other))),
+            }
+        }
+
+        let [format, input, output]: [String; 3] = positional
+            .try_into()
+            .map_err(|_| usage("Expected a format, an input file and an output file".to_string()))?;
+
+        Ok(Self {
+            format: format.parse().map_err(usage)?,
+            input: PathBuf::from(input),
+            output: PathBuf::from(output),
+            admin_names,
+            columns,
+            delimiter,
+            min_population,
+        })
+    }
+}
+
+/// Reads a city dataset and turns it into a catalog. Every entry goes through the same validation as
+/// the catalog file the server loads, so anything wrong with the dataset ends up in the catalogs report.
+///
+/// Cities without a rank are ranked by population, starting after the highest rank the dataset did have.
+///
+/// # Returns:
+/// [AppResult]<[CityCatalog]>
+pub fn import_cities(options: &ImportOptions) -> AppResult<CityCatalog> {
+    let mut raw_cities = match options.format {
+        ImportFormat::GeoNames => geonames::read(&options.input, options.admin_names.as_deref())?,
+        ImportFormat::Csv => csv_table::read(&options.input, &options.columns, options.delimiter)?,
+        ImportFormat::GeoJson => geojson::read(&options.input)?,
+    };
+
+    if options.min_population > 0 {
+        raw_cities.retain(|raw| population_of(raw).map_or(true, |population| population >= options.min_population as f64));
+    }
+
+    assign_ranks(&mut raw_cities);
+
+    Ok(CityCatalog::from_raw(raw_cities))
+}
+
+/// Writes the cities of a catalog to a json file the server can load with `CITY_FILE`
+///
+/// # Returns:
+/// [AppResult]<()>
+pub fn write_catalog(catalog: &CityCatalog, path: &Path) -> AppResult<()> {
+    let file = std::fs::File::create(path)?;
+    serde_json::to_writer_pretty(std::io::BufWriter::new(file), catalog.all())?;
+
+    Ok(())
+}
+
+/// Runs an import from the command line, printing the validation report when done
+///
+/// # Returns:
+/// [AppResult]<()>
+pub fn run(args: impl IntoIterator<Item = String>) -> AppResult<()> {
+    let options = ImportOptions::from_args(args)?;
+    let catalog = import_cities(&options)?;
+    let report = catalog.report();
+    print_issues(report);
+
+    write_catalog(&catalog, &options.output)?;
+
+    println!(
+        "Imported {} of {} cities into {}, {} skipped",
+        report.loaded,
+        report.entries,
+        options.output.display(),
+        report.skipped()
+    );
+
+    Ok(())
+}
+
+/// Seeds the `cities` table from a catalog file, the one set with `CITY_FILE` unless another one is given.
+/// Only cities that pass validation are written, cities already in the table are updated.
+///
+/// # .env variables
+/// * DATABASE_URL - The database to seed
+/// * CITY_FILE (optional) - Catalog file used when none is given, see [CityCatalog::from_env]
+///
+/// # Returns:
+/// [AppResult]<()>
+pub async fn seed(args: impl IntoIterator<Item = String>) -> AppResult<()> {
+    let mut args = args.into_iter();
+    let catalog = match (args.next(), args.next()) {
+        (Some(path), None) => CityCatalog::load(Path::new(&path))?,
+        (None, None) => CityCatalog::from_env()?,
+        _ => return Err(AppError::Any(anyhow::anyhow!("Usage: seed_cities [cities.json]"))),
+    };
+    print_issues(catalog.report());
+
+    let store = Store::with_pool(new_pool().await);
+    let seeded = store.seed_cities(catalog.all()).await?;
+
+    println!(
+        "Seeded {} cities, {} skipped, the table now holds {}",
+        seeded,
+        catalog.report().skipped(),
+        store.count_cities().await?
+    );
+
+    Ok(())
+}
+
+fn print_issues(report: &ValidationReport) {
+    for issue in &report.issues {
+        println!(
+            "Entry {} ({}): {}{}",
+            issue.entry,
+            issue.city.as_deref().unwrap_or("no name"),
+            issue.problem,
+            if issue.skipped { ", skipped" } else { "" }
+        );
+    }
+}
+
+fn population_of(raw: &RawCity) -> Option<f64> {
+    match raw.population.as_ref()? {
+        Value::Number(number) => number.as_f64(),
+        Value::String(text) => text.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Gives every city without a rank one, most populated first, continuing after the highest rank already taken
+fn assign_ranks(raw_cities: &mut [RawCity]) {
+    let highest_rank = raw_cities
+        .iter()
+        .filter_map(|raw| match raw.rank.as_ref()? {
+            Value::Number(number) => number.as_i64(),
+            Value::String(text) => text.trim().parse().ok(),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0);
+
+    let mut unranked: Vec<usize> = (0..raw_cities.len())
+        .filter(|&index| raw_cities[index].rank.is_none())
+        .collect();
+    unranked.sort_by(|&a, &b| {
+        let population = |index: usize| population_of(&raw_cities[index]).unwrap_or(-1.0);
+        population(b).total_cmp(&population(a))
+    });
+
+    for (position, index) in unranked.into_iter().enumerate() {
+        raw_cities[index].rank = Some(Value::from(highest_rank + position as i64 + 1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raw(rank: Option<i64>, population: &str) -> RawCity {
+        RawCity {
+            rank: rank.map(Value::from),
+            population: Some(Value::from(population)),
+            ..RawCity::default()
+        }
+    }
+
+    #[test]
+    fn unranked_cities_are_ranked_by_population_after_the_ranked_ones() {
+        let mut cities = vec![raw(None, "100"), raw(Some(4), "5"), raw(None, "9000"), raw(None, "lots")];
+        assign_ranks(&mut cities);
+
+        let ranks: Vec<Option<Value>> = cities.into_iter().map(|city| city.rank).collect();
+        assert_eq!(ranks, vec![Some(Value::from(6)), Some(Value::from(4)), Some(Value::from(5)), Some(Value::from(7))]);
+    }
+
+    #[test]
+    fn options_are_read_from_the_command_line() {
+        let args = "csv in.csv out.json --delimiter \\t --min-population 500 --columns city=name"
+            .split(' ')
+            .map(str::to_string);
+        let options = ImportOptions::from_args(args).unwrap();
+
+        assert_eq!(options.format, ImportFormat::Csv);
+        assert_eq!(options.delimiter, b'\t');
+        assert_eq!(options.min_population, 500);
+        assert_eq!(options.columns.column_for("city"), "name");
+
+        let bad = |args: &str| ImportOptions::from_args(args.split(' ').map(str::to_string)).is_err();
+        assert!(bad("shapefile in out"));
+        assert!(bad("csv in.csv"));
+        assert!(bad("csv in.csv out.json --delimiter ;;"));
+        assert!(bad("csv in.csv out.json --min-population"));
+    }
+}
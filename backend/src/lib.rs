@@ -12,23 +12,23 @@ use tracing::debug;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 
+pub mod catalog;
 pub mod db;
 pub mod error;
+pub mod geodesy;
 pub mod handlers;
+pub mod imagery;
+pub mod import;
 pub mod layers;
+pub mod map_files;
 mod models;
+pub mod regions;
 mod routes;
+pub mod scoring;
+pub mod spatial;
+pub mod state;
 mod template;
 
-#[allow(dead_code)]
-const EARTH_RADIUS_KM: f32 = 6371.0;
-#[allow(dead_code)]
-const KM_TO_MILES: f32 = 0.621371;
-
-const MAX_SCORE: i32 = 100;
-
-const SCORE_FACTOR: f32 = 0.5;
-
 /// Initializes logging for us, so we can see information about requests
 fn init_logging() {
     //From https://github.com/tokio-rs/axum/blob/main/examples/tracing-aka-logging
@@ -115,28 +115,41 @@ pub fn get_timestamp_after_8_hours() -> u64 {
 pub fn crop_image(img_str: String) -> Result<String, AppError> {
     let decoded = base64::decode(img_str)?;
 
-    let img = image::load_from_memory(&decoded).unwrap();
+    let cropped = crop_image_bytes(&decoded)?;
+
+    let return_string = encode(&cropped);
+
+    Ok(return_string)
+}
+
+/// Same as [crop_image], but works on the raw bytes of the image instead of a [base64](base64) string.
+/// The result is always encoded as a png.
+///
+/// # Arguments:
+/// * img_bytes: &[u8] - The image you want to crop, in any format the [image](image) crate can read.
+///
+/// # Returns:
+/// * [Result]<[Vec]<u8>, [AppError]>
+pub fn crop_image_bytes(img_bytes: &[u8]) -> Result<Vec<u8>, AppError> {
+    let img = image::load_from_memory(img_bytes)?;
 
     let pixels_to_cut = 20;
     // Get the dimensions of the original image
     let (width, height) = img.dimensions();
 
-    let new_height = height - pixels_to_cut;
+    let new_height = height.saturating_sub(pixels_to_cut);
 
     let new_img = img.crop_imm(0, 0, width, new_height);
 
     let mut encoded = Vec::new();
     let mut cursor = Cursor::new(&mut encoded);
-    new_img
-        .write_to(&mut cursor, ImageOutputFormat::Png)
-        .expect("Failed to encode image");
-
-    let return_string = encode(&encoded);
+    new_img.write_to(&mut cursor, ImageOutputFormat::Png)?;
 
-    Ok(return_string)
+    Ok(encoded)
 }
 
 /// This function calculates the distance between two points, each point has a corresponding latitude and longitude.
+/// The earth is taken to be a sphere, see [geodesy] for the ellipsoidal distance scores use.
 /// # Arguments:
 /// * lat1: f32 - [Latitude](https://en.wikipedia.org/wiki/Latitude) of fist location
 /// * lng1: f32 - [Longitude](https://en.wikipedia.org/wiki/Longitude) of fist location
@@ -146,24 +159,7 @@ pub fn crop_image(img_str: String) -> Result<String, AppError> {
 /// # Returns:
 /// [f32](std::f32)
 pub fn haversine_distance(lat1: f32, lng1: f32, lat2: f32, lng2: f32) -> f32 {
-    let d_lat = (lat2 - lat1).to_radians();
-    let d_lng = (lng2 - lng1).to_radians();
-
-    let a = (d_lat / 2.0).sin().powi(2)
-        + lat1.to_radians().cos() * lat2.to_radians().cos() * (d_lng / 2.0).sin().powi(2);
-    let c = 2.0 * a.sqrt().atan2((1.0 - a).sqrt());
-
-    EARTH_RADIUS_KM * c
-}
-
-
-/// Generates a score based off of the distance provided
-/// # Returns
-/// * f32
-///
-/// TODO: Scoring algorithm is subject to change
-pub fn get_score_from_distance(distance: f32) -> f32 {
-    MAX_SCORE as f32 - (distance * SCORE_FACTOR)
+    geodesy::haversine_km(lat1 as f64, lng1 as f64, lat2 as f64, lng2 as f64) as f32
 }
 
 pub type AppResult<T> = Result<T, AppError>;
@@ -0,0 +1,215 @@
+use serde_json::{json, Map, Value};
+
+use crate::catalog::CityCatalog;
+use crate::error::AppError;
+use crate::models::custom_map::{CustomMap, MapLocation, NewMapLocation};
+use crate::AppResult;
+
+use super::{text, MapFile};
+
+/// Reads a GeoJSON FeatureCollection of points.
+///
+/// Names are looked up under `name`, `title` and `city`, regions under `region` and `state`.
+/// A feature with a `city_id` of a catalog city becomes that city, the way [export] writes them.
+///
+/// # Returns:
+/// [AppResult]<[MapFile]>
+pub fn parse(contents: &str, catalog: &CityCatalog) -> AppResult<MapFile> {
+    let collection: Value = serde_json::from_str(contents)
+        .map_err(|err| AppError::InvalidMap(format!("The file is not valid JSON: {}", err)))?;
+
+    let features = collection
+        .get("features")
+        .and_then(Value::as_array)
+        .ok_or_else(|| AppError::InvalidMap("The file is not a GeoJSON FeatureCollection".to_string()))?;
+
+    let mut file = MapFile {
+        name: text(collection.get("name").and_then(Value::as_str)),
+        ..Default::default()
+    };
+
+    for (index, feature) in features.iter().enumerate() {
+        let empty = Map::new();
+        let properties = feature
+            .get("properties")
+            .and_then(Value::as_object)
+            .unwrap_or(&empty);
+        let property = |names: &[&str]| {
+            names
+                .iter()
+                .filter_map(|name| properties.get(*name))
+                .find_map(|value| text(value.as_str()))
+        };
+
+        let name = property(&["name", "title", "city"]);
+        let location = match catalog_city(properties, catalog) {
+            Ok(Some(location)) => Ok(location),
+            Ok(None) => point(feature).and_then(|(latitude, longitude)| {
+                NewMapLocation::at(name.clone(), property(&["region", "state"]), latitude, longitude)
+            }),
+            Err(message) => Err(message),
+        };
+
+        file.push(index + 1, name, location);
+    }
+
+    Ok(file)
+}
+
+/// The catalog city a feature links to, if it has a `city_id`
+fn catalog_city(
+    properties: &Map<String, Value>,
+    catalog: &CityCatalog,
+) -> Result<Option<NewMapLocation>, String> {
+    let value = match properties.get("city_id") {
+        None | Some(Value::Null) => return Ok(None),
+        Some(value) => value,
+    };
+    let city_id = match value {
+        Value::Number(number) => number.as_i64(),
+        Value::String(text) => text.trim().parse().ok(),
+        _ => None,
+    };
+
+    let city = city_id
+        .and_then(|id| i32::try_from(id).ok())
+        .and_then(|id| catalog.find(id))
+        .ok_or_else(|| format!("city_id {} is not a city we know", value))?;
+
+    Ok(Some(NewMapLocation::from_city(city)))
+}
+
+/// Latitude and longitude of a Point feature, GeoJSON positions are longitude first
+fn point(feature: &Value) -> Result<(f32, f32), String> {
+    let geometry = feature
+        .get("geometry")
+        .filter(|geometry| !geometry.is_null())
+        .ok_or_else(|| "The feature has no geometry".to_string())?;
+
+    match geometry.get("type").and_then(Value::as_str) {
+        Some("Point") => {}
+        Some(other) => return Err(format!("Only points can be imported, not a {}", other)),
+        None => return Err("The geometry has no type".to_string()),
+    }
+
+    let position = geometry
+        .get("coordinates")
+        .and_then(Value::as_array)
+        .filter(|position| position.len() >= 2)
+        .ok_or_else(|| "The point has no coordinates".to_string())?;
+    let coordinate = |field: &str, index: usize| {
+        position[index]
+            .as_f64()
+            .map(|value| value as f32)
+            .ok_or_else(|| format!("{} {} is not a number", field, position[index]))
+    };
+
+    Ok((coordinate("Latitude", 1)?, coordinate("Longitude", 0)?))
+}
+
+/// Writes the map as a FeatureCollection, with the map name and description on the collection
+pub fn export(map: &CustomMap, locations: &[MapLocation]) -> String {
+    let features: Vec<Value> = locations
+        .iter()
+        .map(|location| {
+            json!({
+                "type": "Feature",
+                "geometry": {
+                    "type": "Point",
+                    "coordinates": [location.longitude, location.latitude],
+                },
+                "properties": {
+                    "name": location.name,
+                    "region": location.region,
+                    "city_id": location.city_id,
+                },
+            })
+        })
+        .collect();
+
+    let collection = json!({
+        "type": "FeatureCollection",
+        "name": map.name,
+        "description": map.description,
+        "features": features,
+    });
+
+    serde_json::to_string_pretty(&collection).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_files::tests::catalog;
+
+    fn parse_features(features: &str) -> MapFile {
+        parse(&format!(r#"{{"type": "FeatureCollection", "name": "Test", "features": [{}]}}"#, features), &catalog()).unwrap()
+    }
+
+    #[test]
+    fn reads_a_point() {
+        let file = parse_features(
+            r#"{"type": "Feature", "geometry": {"type": "Point", "coordinates": [2.2945, 48.8584]},
+                "properties": {"title": "Eiffel Tower", "state": "Île-de-France"}}"#,
+        );
+
+        assert_eq!(file.name.as_deref(), Some("Test"));
+        assert!(file.errors.is_empty());
+        let location = &file.locations[0];
+        assert_eq!(location.name, "Eiffel Tower");
+        assert_eq!(location.region.as_deref(), Some("Île-de-France"));
+        assert_eq!((location.latitude, location.longitude), (48.8584, 2.2945));
+        assert_eq!(location.city_id, None);
+    }
+
+    #[test]
+    fn links_a_known_city_id() {
+        let file = parse_features(r#"{"type": "Feature", "geometry": null, "properties": {"city_id": "7"}}"#);
+
+        assert!(file.errors.is_empty());
+        assert_eq!(file.locations[0].city_id, Some(7));
+        assert_eq!(file.locations[0].name, "Boise");
+    }
+
+    #[test]
+    fn reports_bad_features_and_keeps_the_rest() {
+        let file = parse_features(
+            r#"{"type": "Feature", "geometry": {"type": "LineString", "coordinates": [[0, 0], [1, 1]]}, "properties": {"name": "Road"}},
+               {"type": "Feature", "geometry": {"type": "Point", "coordinates": [0]}},
+               {"type": "Feature", "geometry": {"type": "Point", "coordinates": ["x", 0]}},
+               {"type": "Feature", "geometry": {"type": "Point", "coordinates": [10, 95]}},
+               {"type": "Feature", "geometry": {"type": "Point", "coordinates": [190, 10]}},
+               {"type": "Feature", "properties": {"name": "Nowhere"}},
+               {"type": "Feature", "geometry": {"type": "Point", "coordinates": [1, 1]}, "properties": {"city_id": 99999}},
+               {"type": "Feature", "geometry": {"type": "Point", "coordinates": [1, 2]}}"#,
+        );
+
+        let messages: Vec<(usize, &str)> = file
+            .errors
+            .iter()
+            .map(|error| (error.feature, error.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (1, "Only points can be imported, not a LineString"),
+                (2, "The point has no coordinates"),
+                (3, "Longitude \"x\" is not a number"),
+                (4, "Latitude 95 is not between -90 and 90"),
+                (5, "Longitude 190 is not between -180 and 180"),
+                (6, "The feature has no geometry"),
+                (7, "city_id 99999 is not a city we know"),
+            ]
+        );
+        assert_eq!(file.errors[0].name.as_deref(), Some("Road"));
+
+        assert_eq!(file.locations.len(), 1);
+        assert_eq!((file.locations[0].latitude, file.locations[0].longitude), (2.0, 1.0));
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_feature_collections() {
+        assert!(parse("not json", &catalog()).is_err());
+        assert!(parse(r#"{"type": "Feature"}"#, &catalog()).is_err());
+    }
+}
@@ -0,0 +1,164 @@
+use crate::error::AppError;
+use crate::models::custom_map::{CustomMap, MapLocation, NewMapLocation};
+use crate::AppResult;
+
+use roxmltree::Node;
+
+use super::{child_text, coordinate, parse_xml, xml_escape, MapFile};
+
+/// Namespace of the elements [export] adds to the `extensions` of a waypoint
+const EXTENSION_NAMESPACE: &str = "urn:totally-not-geoguessr:map";
+
+/// Reads the waypoints of a GPX file, tracks and routes are left alone.
+/// GPX has no field for a region, it is read from a `region` element in the `extensions` of a waypoint, the way [export] writes it.
+/// The `desc` is free text in most GPX files and is never taken for a region.
+///
+/// # Returns:
+/// [AppResult]<[MapFile]>
+pub fn parse(contents: &str) -> AppResult<MapFile> {
+    let document = parse_xml(contents)?;
+    let root = document.root_element();
+    if root.tag_name().name() != "gpx" {
+        return Err(AppError::InvalidMap("The file is not a GPX file".to_string()));
+    }
+
+    let mut file = MapFile {
+        name: root
+            .children()
+            .find(|child| child.is_element() && child.tag_name().name() == "metadata")
+            .and_then(|metadata| child_text(metadata, "name")),
+        ..Default::default()
+    };
+
+    let waypoints = root
+        .children()
+        .filter(|child| child.is_element() && child.tag_name().name() == "wpt");
+
+    for (index, waypoint) in waypoints.enumerate() {
+        let name = child_text(waypoint, "name");
+        let location = coordinate("Latitude", waypoint.attribute("lat")).and_then(|latitude| {
+            let longitude = coordinate("Longitude", waypoint.attribute("lon"))?;
+            NewMapLocation::at(name.clone(), region(waypoint), latitude, longitude)
+        });
+
+        file.push(index + 1, name, location);
+    }
+
+    Ok(file)
+}
+
+/// The region written into the extensions of a waypoint
+fn region(waypoint: Node) -> Option<String> {
+    waypoint
+        .children()
+        .find(|child| child.is_element() && child.tag_name().name() == "extensions")
+        .and_then(|extensions| child_text(extensions, "region"))
+}
+
+/// Writes the map as a GPX 1.1 file with a waypoint for every location
+pub fn export(map: &CustomMap, locations: &[MapLocation]) -> String {
+    let mut gpx = String::new();
+
+    gpx.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    gpx.push_str(&format!(
+        "<gpx version=\"1.1\" creator=\"Totally Not GeoGuessr\" xmlns=\"http://www.topografix.com/GPX/1/1\" xmlns:map=\"{}\">\n",
+        EXTENSION_NAMESPACE
+    ));
+    gpx.push_str("  <metadata>\n");
+    gpx.push_str(&format!("    <name>{}</name>\n", xml_escape(&map.name)));
+    gpx.push_str(&format!("    <desc>{}</desc>\n", xml_escape(&map.description)));
+    gpx.push_str("  </metadata>\n");
+
+    for location in locations {
+        gpx.push_str(&format!(
+            "  <wpt lat=\"{}\" lon=\"{}\">\n",
+            location.latitude, location.longitude
+        ));
+        gpx.push_str(&format!("    <name>{}</name>\n", xml_escape(&location.name)));
+        if let Some(region) = &location.region {
+            gpx.push_str("    <extensions>\n");
+            gpx.push_str(&format!("      <map:region>{}</map:region>\n", xml_escape(region)));
+            gpx.push_str("    </extensions>\n");
+        }
+        gpx.push_str("  </wpt>\n");
+    }
+
+    gpx.push_str("</gpx>\n");
+
+    gpx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_waypoints(waypoints: &str) -> MapFile {
+        parse(&format!(
+            r#"<gpx version="1.1" xmlns="http://www.topografix.com/GPX/1/1"><metadata><name>Test</name></metadata>{}</gpx>"#,
+            waypoints
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn reads_a_waypoint() {
+        let file = parse_waypoints(
+            r#"<wpt lat="48.8584" lon="2.2945"><name>Eiffel Tower</name>
+                 <extensions><map:region xmlns:map="urn:totally-not-geoguessr:map">Île-de-France</map:region></extensions></wpt>"#,
+        );
+
+        assert_eq!(file.name.as_deref(), Some("Test"));
+        assert!(file.errors.is_empty());
+        let location = &file.locations[0];
+        assert_eq!(location.name, "Eiffel Tower");
+        assert_eq!(location.region.as_deref(), Some("Île-de-France"));
+        assert_eq!((location.latitude, location.longitude), (48.8584, 2.2945));
+    }
+
+    #[test]
+    fn never_takes_the_description_for_a_region() {
+        let file = parse_waypoints(r#"<wpt lat="1" lon="2"><name>Camp</name><desc>Nice spot by the river, water is 200m north</desc></wpt>"#);
+
+        assert_eq!(file.locations[0].region, None);
+    }
+
+    #[test]
+    fn reports_bad_waypoints_and_keeps_the_rest() {
+        let file = parse_waypoints(
+            r#"<wpt lon="2"><name>No latitude</name></wpt>
+               <wpt lat="1"/>
+               <wpt lat="95" lon="10"/>
+               <wpt lat="10" lon="190"/>
+               <wpt lat="north" lon="10"/>
+               <trk><name>Track</name><trkseg><trkpt lat="1" lon="1"/></trkseg></trk>
+               <wpt lat="2" lon="1"/>"#,
+        );
+
+        let messages: Vec<(usize, &str)> = file
+            .errors
+            .iter()
+            .map(|error| (error.feature, error.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (1, "Latitude is missing"),
+                (2, "Longitude is missing"),
+                (3, "Latitude 95 is not between -90 and 90"),
+                (4, "Longitude 190 is not between -180 and 180"),
+                (5, "Latitude \"north\" is not a number"),
+            ]
+        );
+        assert_eq!(file.errors[0].name.as_deref(), Some("No latitude"));
+
+        // Tracks are not waypoints, only the last waypoint is left
+        assert_eq!(file.locations.len(), 1);
+        assert_eq!((file.locations[0].latitude, file.locations[0].longitude), (2.0, 1.0));
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_gpx() {
+        assert!(parse("<gpx>").is_err());
+        assert!(parse("<kml></kml>").is_err());
+    }
+}
@@ -0,0 +1,185 @@
+use roxmltree::Node;
+
+use crate::error::AppError;
+use crate::models::custom_map::{CustomMap, MapLocation, NewMapLocation};
+use crate::AppResult;
+
+use super::{child_text, coordinate, parse_xml, xml_escape, MapFile};
+
+/// Elements a placemark can hold its geometry in, only points can be imported
+const GEOMETRIES: [&str; 7] = [
+    "Point",
+    "LineString",
+    "LinearRing",
+    "Polygon",
+    "MultiGeometry",
+    "Model",
+    "Track",
+];
+
+/// Reads the placemarks of a KML document, wherever they are in its folders.
+/// The region of a placemark is read from an `ExtendedData` entry called `region`, the way [export] writes it.
+///
+/// # Returns:
+/// [AppResult]<[MapFile]>
+pub fn parse(contents: &str) -> AppResult<MapFile> {
+    let document = parse_xml(contents)?;
+    let root = document.root_element();
+    if root.tag_name().name() != "kml" {
+        return Err(AppError::InvalidMap("The file is not a KML document".to_string()));
+    }
+
+    let mut file = MapFile {
+        name: root
+            .children()
+            .find(|child| matches!(child.tag_name().name(), "Document" | "Folder"))
+            .and_then(|container| child_text(container, "name")),
+        ..Default::default()
+    };
+
+    let placemarks = root
+        .descendants()
+        .filter(|node| node.is_element() && node.tag_name().name() == "Placemark");
+
+    for (index, placemark) in placemarks.enumerate() {
+        let name = child_text(placemark, "name");
+        let location = point(placemark).and_then(|(latitude, longitude)| {
+            NewMapLocation::at(name.clone(), region(placemark), latitude, longitude)
+        });
+
+        file.push(index + 1, name, location);
+    }
+
+    Ok(file)
+}
+
+/// Latitude and longitude of a placemark, KML coordinates are `longitude,latitude[,altitude]`
+fn point(placemark: Node) -> Result<(f32, f32), String> {
+    let geometry = placemark
+        .children()
+        .find(|child| child.is_element() && GEOMETRIES.contains(&child.tag_name().name()))
+        .ok_or_else(|| "The placemark has no geometry".to_string())?;
+
+    if geometry.tag_name().name() != "Point" {
+        return Err(format!("Only points can be imported, not a {}", geometry.tag_name().name()));
+    }
+
+    let coordinates = child_text(geometry, "coordinates").ok_or_else(|| "The point has no coordinates".to_string())?;
+    let mut parts = coordinates.split(',');
+    let longitude = coordinate("Longitude", parts.next())?;
+    let latitude = coordinate("Latitude", parts.next())?;
+
+    Ok((latitude, longitude))
+}
+
+fn region(placemark: Node) -> Option<String> {
+    placemark
+        .children()
+        .filter(|child| child.is_element() && child.tag_name().name() == "ExtendedData")
+        .flat_map(|data| data.children())
+        .find(|entry| entry.is_element() && entry.tag_name().name() == "Data" && entry.attribute("name") == Some("region"))
+        .and_then(|entry| child_text(entry, "value"))
+}
+
+/// Writes the map as a KML document with a placemark for every location
+pub fn export(map: &CustomMap, locations: &[MapLocation]) -> String {
+    let mut kml = String::new();
+
+    kml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    kml.push_str("<kml xmlns=\"http://www.opengis.net/kml/2.2\">\n");
+    kml.push_str("  <Document>\n");
+    kml.push_str(&format!("    <name>{}</name>\n", xml_escape(&map.name)));
+    kml.push_str(&format!("    <description>{}</description>\n", xml_escape(&map.description)));
+
+    for location in locations {
+        kml.push_str("    <Placemark>\n");
+        kml.push_str(&format!("      <name>{}</name>\n", xml_escape(&location.name)));
+        if let Some(region) = &location.region {
+            kml.push_str("      <ExtendedData>\n");
+            kml.push_str(&format!(
+                "        <Data name=\"region\"><value>{}</value></Data>\n",
+                xml_escape(region)
+            ));
+            kml.push_str("      </ExtendedData>\n");
+        }
+        kml.push_str(&format!(
+            "      <Point><coordinates>{},{}</coordinates></Point>\n",
+            location.longitude, location.latitude
+        ));
+        kml.push_str("    </Placemark>\n");
+    }
+
+    kml.push_str("  </Document>\n");
+    kml.push_str("</kml>\n");
+
+    kml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_placemarks(placemarks: &str) -> MapFile {
+        parse(&format!(
+            r#"<kml xmlns="http://www.opengis.net/kml/2.2"><Document><name>Test</name><Folder>{}</Folder></Document></kml>"#,
+            placemarks
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn reads_a_point() {
+        let file = parse_placemarks(
+            r#"<Placemark><name>Eiffel Tower</name>
+                 <ExtendedData><Data name="region"><value>Île-de-France</value></Data></ExtendedData>
+                 <Point><coordinates> 2.2945,48.8584,0 </coordinates></Point></Placemark>"#,
+        );
+
+        assert_eq!(file.name.as_deref(), Some("Test"));
+        assert!(file.errors.is_empty());
+        let location = &file.locations[0];
+        assert_eq!(location.name, "Eiffel Tower");
+        assert_eq!(location.region.as_deref(), Some("Île-de-France"));
+        assert_eq!((location.latitude, location.longitude), (48.8584, 2.2945));
+    }
+
+    #[test]
+    fn reports_bad_placemarks_and_keeps_the_rest() {
+        let file = parse_placemarks(
+            r#"<Placemark><name>Road</name><LineString><coordinates>0,0 1,1</coordinates></LineString></Placemark>
+               <Placemark><name>Empty</name></Placemark>
+               <Placemark><Point><coordinates>5</coordinates></Point></Placemark>
+               <Placemark><Point><coordinates>10,95</coordinates></Point></Placemark>
+               <Placemark><Point><coordinates>190,10</coordinates></Point></Placemark>
+               <Placemark><Point><coordinates>a,b</coordinates></Point></Placemark>
+               <Placemark><Point><coordinates>1,2</coordinates></Point></Placemark>"#,
+        );
+
+        let messages: Vec<(usize, &str)> = file
+            .errors
+            .iter()
+            .map(|error| (error.feature, error.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (1, "Only points can be imported, not a LineString"),
+                (2, "The placemark has no geometry"),
+                (3, "Latitude is missing"),
+                (4, "Latitude 95 is not between -90 and 90"),
+                (5, "Longitude 190 is not between -180 and 180"),
+                (6, "Longitude \"a\" is not a number"),
+            ]
+        );
+        assert_eq!(file.errors[0].name.as_deref(), Some("Road"));
+
+        assert_eq!(file.locations.len(), 1);
+        assert_eq!((file.locations[0].latitude, file.locations[0].longitude), (2.0, 1.0));
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_kml() {
+        assert!(parse("<kml>").is_err());
+        assert!(parse("<gpx></gpx>").is_err());
+    }
+}
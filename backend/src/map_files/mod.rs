@@ -0,0 +1,238 @@
+use std::str::FromStr;
+
+use roxmltree::{Document, Node};
+use serde_derive::Serialize;
+
+use crate::catalog::CityCatalog;
+use crate::error::AppError;
+use crate::models::custom_map::{CustomMap, MapLocation, NewMapLocation};
+use crate::AppResult;
+
+pub mod geojson;
+pub mod gpx;
+pub mod kml;
+
+/// The kinds of files a [custom map](CustomMap) can be uploaded from and downloaded as
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MapFileFormat {
+    /// A GeoJSON FeatureCollection of points
+    GeoJson,
+
+    /// A KML document of placemarks, like the ones Google My Maps and Google Earth export
+    Kml,
+
+    /// The waypoints of a GPX file
+    Gpx,
+}
+
+impl FromStr for MapFileFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "geojson" | "json" => Ok(MapFileFormat::GeoJson),
+            "kml" => Ok(MapFileFormat::Kml),
+            "gpx" => Ok(MapFileFormat::Gpx),
+            other => Err(format!("Unknown map file format: {}", other)),
+        }
+    }
+}
+
+impl MapFileFormat {
+    /// Guesses the format from the extension of an uploaded file
+    pub fn from_file_name(file_name: &str) -> Option<Self> {
+        let (_, extension) = file_name.rsplit_once('.')?;
+        extension.parse().ok()
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            MapFileFormat::GeoJson => "geojson",
+            MapFileFormat::Kml => "kml",
+            MapFileFormat::Gpx => "gpx",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            MapFileFormat::GeoJson => "application/geo+json",
+            MapFileFormat::Kml => "application/vnd.google-earth.kml+xml",
+            MapFileFormat::Gpx => "application/gpx+xml",
+        }
+    }
+}
+
+/// Why one feature of an uploaded file was left out, the rest of the file is still imported
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct FeatureError {
+    /// Position of the feature in the file, starting at 1
+    pub feature: usize,
+
+    /// Name of the feature, if it has one
+    pub name: Option<String>,
+
+    pub message: String,
+}
+
+/// Everything read out of an uploaded map file
+#[derive(Debug, Default)]
+pub struct MapFile {
+    /// Name the file gives itself, used for maps created from a file
+    pub name: Option<String>,
+
+    /// The features that passed validation, in file order
+    pub locations: Vec<NewMapLocation>,
+
+    /// The features that didn't
+    pub errors: Vec<FeatureError>,
+}
+
+impl MapFile {
+    /// Adds a feature to the file, or the reason it can't be added
+    fn push(&mut self, feature: usize, name: Option<String>, location: Result<NewMapLocation, String>) {
+        match location {
+            Ok(location) => self.locations.push(location),
+            Err(message) => self.errors.push(FeatureError { feature, name, message }),
+        }
+    }
+}
+
+/// Reads an uploaded map file. Every feature is checked on its own, bad ones end up in
+/// [errors](MapFile::errors) and don't stop the others from being imported.
+///
+/// # Arguments:
+/// * catalog: [CityCatalog] - GeoJSON features with a `city_id` are linked to the catalog city
+///
+/// # Returns:
+/// [AppResult]<[MapFile]> - [AppError::InvalidMap](crate::error::AppError::InvalidMap) if the file can't be read at all
+pub fn parse(format: MapFileFormat, contents: &str, catalog: &CityCatalog) -> AppResult<MapFile> {
+    match format {
+        MapFileFormat::GeoJson => geojson::parse(contents, catalog),
+        MapFileFormat::Kml => kml::parse(contents),
+        MapFileFormat::Gpx => gpx::parse(contents),
+    }
+}
+
+/// Writes a map and its locations as a file that [parse] reads back
+pub fn export(format: MapFileFormat, map: &CustomMap, locations: &[MapLocation]) -> String {
+    match format {
+        MapFileFormat::GeoJson => geojson::export(map, locations),
+        MapFileFormat::Kml => kml::export(map, locations),
+        MapFileFormat::Gpx => gpx::export(map, locations),
+    }
+}
+
+/// Text of an element or property, with surrounding whitespace removed and empty text treated as missing
+fn text(value: Option<&str>) -> Option<String> {
+    value
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+}
+
+/// Parses one coordinate out of a file
+fn coordinate(field: &str, value: Option<&str>) -> Result<f32, String> {
+    let value = value.map(str::trim).ok_or_else(|| format!("{} is missing", field))?;
+
+    value
+        .parse()
+        .map_err(|_| format!("{} {:?} is not a number", field, value))
+}
+
+/// Escapes text for XML elements and attributes
+fn xml_escape(text: &str) -> String {
+    html_escape::encode_double_quoted_attribute(text).into_owned()
+}
+
+/// Parses a KML or GPX file
+fn parse_xml(contents: &str) -> AppResult<Document<'_>> {
+    Document::parse(contents).map_err(|err| AppError::InvalidMap(format!("The file is not valid XML: {}", err)))
+}
+
+/// Text of the first child element with the given name, ignoring namespaces
+fn child_text(node: Node, name: &str) -> Option<String> {
+    node.children()
+        .find(|child| child.is_element() && child.tag_name().name() == name)
+        .and_then(|child| text(child.text()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::city::City;
+    use crate::models::custom_map::Visibility;
+
+    /// A catalog with a single city, id 7
+    pub(super) fn catalog() -> CityCatalog {
+        CityCatalog::from_cities(vec![City {
+            id: 7,
+            city: "Boise".to_string(),
+            state: "Idaho".to_string(),
+            country_code: "US".to_string(),
+            latitude: 43.6135,
+            longitude: -116.2035,
+            population: 214_237,
+            rank: 7,
+            ..City::default()
+        }])
+    }
+
+    /// A map with a catalog city, a place with a region and one without, with names that need escaping
+    pub(super) fn sample_map() -> (CustomMap, Vec<MapLocation>) {
+        let map = CustomMap::new(1, "Rivers & <Lakes>".to_string(), "\"Wet\" places".to_string(), Visibility::Public);
+        let location = |id: i32, city_id: Option<i32>, name: &str, region: Option<&str>, latitude: f32, longitude: f32| MapLocation {
+            id,
+            map_id: map.id,
+            city_id,
+            name: name.to_string(),
+            region: region.map(str::to_string),
+            latitude,
+            longitude,
+        };
+
+        let locations = vec![
+            location(1, Some(7), "Boise", Some("Idaho"), 43.6135, -116.2035),
+            location(2, None, "Lake <Titicaca>", Some("Puno & La Paz"), -15.8402, -69.3354),
+            location(3, None, "Null Island", None, 0.0, 0.0),
+        ];
+
+        (map, locations)
+    }
+
+    #[test]
+    fn formats_are_read_from_names_and_extensions() {
+        assert_eq!("GeoJSON".parse(), Ok(MapFileFormat::GeoJson));
+        assert_eq!("json".parse(), Ok(MapFileFormat::GeoJson));
+        assert_eq!(MapFileFormat::from_file_name("trip.backup.gpx"), Some(MapFileFormat::Gpx));
+        assert_eq!(MapFileFormat::from_file_name("places.KML"), Some(MapFileFormat::Kml));
+        assert_eq!(MapFileFormat::from_file_name("places"), None);
+        assert!("shp".parse::<MapFileFormat>().is_err());
+    }
+
+    #[test]
+    fn every_format_reads_back_what_it_exports() {
+        let catalog = catalog();
+        let (map, locations) = sample_map();
+
+        for format in [MapFileFormat::GeoJson, MapFileFormat::Kml, MapFileFormat::Gpx] {
+            let file = parse(format, &export(format, &map, &locations), &catalog).unwrap();
+
+            assert_eq!(file.name.as_deref(), Some(map.name.as_str()), "{:?}", format);
+            assert!(file.errors.is_empty(), "{:?}: {:?}", format, file.errors);
+            assert_eq!(file.locations.len(), locations.len(), "{:?}", format);
+
+            for (read, written) in file.locations.iter().zip(&locations) {
+                assert_eq!(read.name, written.name, "{:?}", format);
+                assert_eq!(read.region, written.region, "{:?}", format);
+                assert_eq!(read.latitude, written.latitude, "{:?}", format);
+                assert_eq!(read.longitude, written.longitude, "{:?}", format);
+            }
+        }
+
+        // Only GeoJSON keeps the link to the catalog city
+        let file = parse(MapFileFormat::GeoJson, &export(MapFileFormat::GeoJson, &map, &locations), &catalog).unwrap();
+        let city_ids: Vec<Option<i32>> = file.locations.iter().map(|location| location.city_id).collect();
+        assert_eq!(city_ids, vec![Some(7), None, None]);
+    }
+}
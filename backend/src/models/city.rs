@@ -1,9 +1,9 @@
-use crate::error::AppError;
-use crate::models::page::CityPage;
-use rand::Rng;
+use std::fmt::{Display, Formatter};
+
 use serde_derive::{Deserialize, Serialize};
-use std::fs::File;
-use std::io::Read;
+use serde_json::Value;
+use sqlx::postgres::PgRow;
+use sqlx::{FromRow, Row};
 
 /// This struct contains all information regarding a City, including
 ///     The city name,
@@ -13,129 +13,493 @@ use std::io::Read;
 ///     The rank, from 1 to 1000, in population,
 ///     The State in which the city resides in
 ///
-///
+/// Cities are stored in the `cities` table and looked up through the [CityCatalog](crate::catalog::CityCatalog),
+/// which reads them all once at startup.
+/// Cities read from a file start out as a [RawCity] and only become a City once they pass validation.
 #[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
 pub struct City {
+    /// Identifies the city, this is what rounds and guesses store. For the bundled cities it is the same as the rank
+    pub id: i32,
+
     /// Name of the City
     pub city: String,
 
-    /// Growth in population from the year 2000 to 2013
-    pub growth_from_2000_to_2013: String,
+    /// Growth in population from the year 2000 to 2013, in percent. Not every city has one
+    pub growth_from_2000_to_2013: Option<f32>,
 
-    /// Latitude of the city
+    /// Latitude of the city, between -90 and 90
     pub latitude: f32,
 
-    /// Longitude of the city
+    /// Longitude of the city, between -180 and 180
     pub longitude: f32,
 
     /// Population of the city
-    pub population: String,
+    pub population: u64,
 
     /// Rank of the city, between 1 and 1000, 1 being the most populated city, 1000 being 1000th most populated city
-    pub rank: String,
+    pub rank: i32,
 
     /// Name of the State which the City resides in.
+    /// Outside the US this is whatever the first level admin region is called there, a province, region, county...
     pub state: String,
-}
 
-impl City {
-    /// Returns a random City, ranked from 1 to 1000,
-    /// The file ```cities.json``` must be present in the path: ``` resources/city_json/cities.json```
-    ///
-    /// # Returns:
-    /// [Self](City)
-    ///
-    ///
-    /// # Examples
-    /// ```
-    /// let city: City = City::get_random_city();
-    ///
-    /// println!("City name and populations: {}, {}", city.city, city.population);
-    ///
-    /// ```
-    pub async fn get_random_city() -> Self {
-        //let city_file_name = std::env::var("CITY_FILE").expect("Could not find city file.");
-        let mut city_file =
-            File::open("resources/city_json/cities.json").expect("Could not open city file.");
-        let mut city_string = String::new();
+    /// [ISO 3166-1 alpha-2](https://en.wikipedia.org/wiki/ISO_3166-1_alpha-2) code of the country the city is in
+    pub country_code: String,
 
-        city_file
-            .read_to_string(&mut city_string)
-            .expect("Could not read file.");
+    /// Other names the city is known by, like the name in the local language
+    pub alternate_names: Vec<String>,
+}
 
-        let city_vec: Vec<City> =
-            serde_json::from_str(&city_string).expect("Couldn't parse JSON city file.");
+// Postgres has no unsigned integers, the population is stored as a BIGINT
+impl<'r> FromRow<'r, PgRow> for City {
+    fn from_row(row: &'r PgRow) -> Result<Self, sqlx::Error> {
+        let population: i64 = row.try_get("population")?;
 
-        let mut rng = rand::thread_rng();
-        let rand_city_num: u32 = rng.gen_range(1..=1000);
+        Ok(City {
+            id: row.try_get("id")?,
+            city: row.try_get("city")?,
+            growth_from_2000_to_2013: row.try_get("growth_from_2000_to_2013")?,
+            latitude: row.try_get("latitude")?,
+            longitude: row.try_get("longitude")?,
+            population: population.max(0) as u64,
+            rank: row.try_get("rank")?,
+            state: row.try_get("state")?,
+            country_code: row.try_get("country_code")?,
+            alternate_names: row.try_get("alternate_names")?,
+        })
+    }
+}
+
+/// A city exactly as it was read from a json file, or put together by one of the [importers](crate::import),
+/// before anything is checked. Numbers may be written as numbers or as strings, like they are in `cities.json`.
+///
+/// Files without country codes are taken to be US cities, like the bundled one.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct RawCity {
+    pub id: Option<Value>,
+    pub city: Option<String>,
+    pub growth_from_2000_to_2013: Option<Value>,
+    pub latitude: Option<Value>,
+    pub longitude: Option<Value>,
+    pub population: Option<Value>,
+    pub rank: Option<Value>,
+    pub state: Option<String>,
+    pub country_code: Option<String>,
+    pub alternate_names: Option<Vec<String>>,
+}
 
-        let chosen_city: City = city_vec
-            .iter()
-            .find(|&c| c.rank.parse::<u32>().unwrap() == rand_city_num)
-            .cloned()
-            .unwrap();
+/// Something wrong with one entry of a city file
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CityProblem {
+    MissingField { field: &'static str },
+    InvalidField { field: &'static str, value: String },
+    OutOfRange { field: &'static str, value: f64 },
+    DuplicateId { id: i32 },
+    DuplicateCity { city: String, state: String, country_code: String },
+}
 
-        chosen_city
+impl Display for CityProblem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CityProblem::MissingField { field } => write!(f, "{} is missing", field),
+            CityProblem::InvalidField { field, value } => write!(f, "{} {:?} can't be read", field, value),
+            CityProblem::OutOfRange { field, value } => write!(f, "{} {} is out of range", field, value),
+            CityProblem::DuplicateId { id } => write!(f, "id {} is already taken", id),
+            CityProblem::DuplicateCity {
+                city,
+                state,
+                country_code,
+            } => write!(f, "{}, {}, {} is already in the file", city, state, country_code),
+        }
     }
+}
 
-    /// Takes a city rank, as a string, and fetches the corresponding city from the json file
-    ///
-    /// # Arguments
-    /// * `rank` - The city rank, in string form.
-    ///
-    /// # Returns
-    /// Result<[Self](City), [AppError](AppError)>
-    ///
-    /// # Examples:
-    /// ```
-    /// let rank: String = String::from("234");
-    /// let city: City = City::get_city_with_rank(rank).unwrap();
-    /// println!("City name and populations: {}, {}", city.city, city.population);
-    /// ```
-    pub async fn get_city_with_rank(rank: String) -> Result<Self, AppError> {
-        let mut city_file =
-            File::open("resources/city_json/cities.json").expect("Could not open city file.");
-        let mut city_string = String::new();
+/// A problem found with one entry, and whether the entry was left out because of it
+#[derive(Serialize, Clone, Debug)]
+pub struct CityIssue {
+    /// Position of the entry in the file, starting at 0
+    pub entry: usize,
+
+    /// Name of the city, if the entry had one
+    pub city: Option<String>,
+
+    pub problem: CityProblem,
+
+    /// Entries that couldn't be used at all are skipped, the rest are loaded anyway
+    pub skipped: bool,
+}
 
-        city_file
-            .read_to_string(&mut city_string)
-            .expect("Could not read file.");
+/// Everything that was wrong with a city file, so bad data shows up once at startup instead of in the middle of a request
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct ValidationReport {
+    /// How many entries were read
+    pub entries: usize,
 
-        let city_vec: Vec<City> =
-            serde_json::from_str(&city_string).expect("Couldn't parse JSON city file.");
+    /// How many of those made it into the catalog
+    pub loaded: usize,
 
-        let chosen_city: City = city_vec.iter().find(|&c| c.rank == rank).cloned().unwrap();
+    pub issues: Vec<CityIssue>,
+}
+
+impl ValidationReport {
+    pub fn is_clean(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    pub fn skipped(&self) -> usize {
+        self.entries - self.loaded
+    }
 
-        Ok(chosen_city)
+    /// Records a problem with an entry
+    pub fn add(&mut self, entry: usize, city: Option<&str>, problem: CityProblem, skipped: bool) {
+        self.issues.push(CityIssue {
+            entry,
+            city: city.map(str::to_string),
+            problem,
+            skipped,
+        });
     }
+}
 
-    /// Takes a string, representing the rank of the City desired
+impl RawCity {
+    /// Checks every field of the entry and turns it into a [City].
+    /// Anything wrong is written to the report, entries missing something the game can't do without come back as none.
+    ///
+    /// Only the entry itself is checked here, duplicates are found by the [CityCatalog](crate::catalog::CityCatalog).
     ///
     /// # Arguments:
-    /// * rank: String -> Represents the rank of the city, needs to be between "1" and "1000", negative numbers
-    ///     or numbers greater than "1000" will Return an AppError
+    /// * entry: usize - Position of the entry in the file, for the report
+    /// * report: &mut [ValidationReport] - Where problems are written to
     ///
     /// # Returns:
-    /// Result<[CityPage](CityPage), [AppError](AppError)>
-    ///
-    /// # Examples:
-    /// ```rust
-    /// let city_rank: String = String::from("954");
-    /// let city_page =  City::get_city_in_pagte_from_rank(city_rank);
-    ///
-    /// match city_page {
-    ///     Ok(page) => {
-    ///         println!("City: {}", page.city.city);
-    ///         page
-    ///     }
-    ///     Err(err) => {
-    ///         println!("Error! {}", err.to_string());
-    ///     }
-    /// }
-    /// ```
-    pub async fn get_city_in_page_from_rank(rank: String) -> Result<CityPage, AppError> {
-        let city = City::get_city_with_rank(rank).await?;
-        let city_page = CityPage { city };
-        Ok(city_page)
+    /// [Option]<[City]>
+    pub fn validate(self, entry: usize, report: &mut ValidationReport) -> Option<City> {
+        let name = self.city.clone().filter(|name| !name.trim().is_empty());
+        let mut problems = Vec::new();
+
+        let mut required_number = |field: &'static str, value: Option<Value>| -> Option<f64> {
+            match value {
+                None | Some(Value::Null) => {
+                    problems.push(CityProblem::MissingField { field });
+                    None
+                }
+                Some(value) => {
+                    let number = number_from(&value);
+                    if number.is_none() {
+                        problems.push(CityProblem::InvalidField {
+                            field,
+                            value: text_of(&value),
+                        });
+                    }
+                    number
+                }
+            }
+        };
+
+        let rank = required_number("rank", self.rank);
+        let population = required_number("population", self.population);
+        let latitude = required_number("latitude", self.latitude);
+        let longitude = required_number("longitude", self.longitude);
+
+        // Files that don't have ids use the rank, like the bundled one
+        let id = match self.id {
+            None | Some(Value::Null) => rank,
+            Some(value) => {
+                let id = number_from(&value);
+                if id.is_none() {
+                    problems.push(CityProblem::InvalidField {
+                        field: "id",
+                        value: text_of(&value),
+                    });
+                }
+                id
+            }
+        };
+
+        if name.is_none() {
+            problems.push(CityProblem::MissingField { field: "city" });
+        }
+        let state = self.state.filter(|state| !state.trim().is_empty());
+        if state.is_none() {
+            problems.push(CityProblem::MissingField { field: "state" });
+        }
+
+        let whole = |field: &'static str, value: Option<f64>, min: f64, max: f64, problems: &mut Vec<CityProblem>| {
+            let value = value?;
+            if value.fract() != 0.0 || value < min || value > max {
+                problems.push(CityProblem::OutOfRange { field, value });
+                return None;
+            }
+            Some(value)
+        };
+        let id = whole("id", id, i32::MIN as f64, i32::MAX as f64, &mut problems);
+        let rank = whole("rank", rank, 1.0, i32::MAX as f64, &mut problems);
+        let population = whole("population", population, 0.0, u64::MAX as f64, &mut problems);
+
+        let in_range = |field: &'static str, value: Option<f64>, limit: f64, problems: &mut Vec<CityProblem>| {
+            let value = value?;
+            if !(-limit..=limit).contains(&value) {
+                problems.push(CityProblem::OutOfRange { field, value });
+                return None;
+            }
+            Some(value)
+        };
+        let latitude = in_range("latitude", latitude, 90.0, &mut problems);
+        let longitude = in_range("longitude", longitude, 180.0, &mut problems);
+
+        let country_code = match self.country_code {
+            None => Some("US".to_string()),
+            Some(code) => {
+                let code = code.trim().to_uppercase();
+                if code.len() == 2 && code.chars().all(|c| c.is_ascii_alphabetic()) {
+                    Some(code)
+                } else {
+                    problems.push(CityProblem::InvalidField {
+                        field: "country_code",
+                        value: code,
+                    });
+                    None
+                }
+            }
+        };
+
+        // Growth is nice to have, an unreadable one is reported but doesn't cost the city its place
+        let growth = match self.growth_from_2000_to_2013 {
+            None | Some(Value::Null) => None,
+            Some(Value::String(growth)) if growth.trim().is_empty() => None,
+            Some(value) => {
+                let growth = match &value {
+                    Value::String(growth) => growth.trim().trim_end_matches('%').trim().parse::<f32>().ok(),
+                    Value::Number(growth) => growth.as_f64().map(|growth| growth as f32),
+                    _ => None,
+                };
+                if growth.is_none() {
+                    report.add(
+                        entry,
+                        name.as_deref(),
+                        CityProblem::InvalidField {
+                            field: "growth_from_2000_to_2013",
+                            value: text_of(&value),
+                        },
+                        false,
+                    );
+                }
+                growth
+            }
+        };
+
+        let skipped = !problems.is_empty();
+        for problem in problems {
+            report.add(entry, name.as_deref(), problem, skipped);
+        }
+
+        Some(City {
+            id: id? as i32,
+            city: name?,
+            growth_from_2000_to_2013: growth,
+            latitude: latitude? as f32,
+            longitude: longitude? as f32,
+            population: population? as u64,
+            rank: rank? as i32,
+            state: state?,
+            country_code: country_code?,
+            alternate_names: self
+                .alternate_names
+                .unwrap_or_default()
+                .into_iter()
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.is_empty())
+                .collect(),
+        })
+    }
+}
+
+/// The value the way it was written in the file, without the quotes json puts around strings
+fn text_of(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Reads a number that may have been written as a json number or as a string
+fn number_from(value: &Value) -> Option<f64> {
+    let number = match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(text) => text.trim().parse().ok(),
+        _ => None,
+    }?;
+
+    number.is_finite().then_some(number)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn raw() -> RawCity {
+        RawCity {
+            id: None,
+            city: Some("Boise City".to_string()),
+            growth_from_2000_to_2013: Some(json!("9.5%")),
+            latitude: Some(json!(43.6187102)),
+            longitude: Some(json!("-116.2146068")),
+            population: Some(json!("214237")),
+            rank: Some(json!(99)),
+            state: Some("Idaho".to_string()),
+            country_code: None,
+            alternate_names: Some(vec![" Boise ".to_string(), "".to_string()]),
+        }
+    }
+
+    fn problems(report: &ValidationReport) -> Vec<&CityProblem> {
+        report.issues.iter().map(|issue| &issue.problem).collect()
+    }
+
+    #[test]
+    fn a_complete_entry_becomes_a_city() {
+        let mut report = ValidationReport::default();
+        let city = raw().validate(0, &mut report).unwrap();
+
+        assert!(report.is_clean());
+        assert_eq!(city.id, 99, "files without ids use the rank");
+        assert_eq!(city.population, 214_237);
+        assert_eq!(city.longitude, -116.2146068_f64 as f32);
+        assert_eq!(city.growth_from_2000_to_2013, Some(9.5));
+        assert_eq!(city.country_code, "US");
+        assert_eq!(city.alternate_names, vec!["Boise"]);
+    }
+
+    #[test]
+    fn missing_fields_skip_the_entry() {
+        let mut report = ValidationReport::default();
+        let entry = RawCity {
+            city: Some("  ".to_string()),
+            latitude: None,
+            population: Some(Value::Null),
+            state: None,
+            ..raw()
+        };
+
+        assert!(entry.validate(3, &mut report).is_none());
+        assert_eq!(
+            problems(&report),
+            vec![
+                &CityProblem::MissingField { field: "population" },
+                &CityProblem::MissingField { field: "latitude" },
+                &CityProblem::MissingField { field: "city" },
+                &CityProblem::MissingField { field: "state" },
+            ]
+        );
+        assert!(report.issues.iter().all(|issue| issue.skipped && issue.entry == 3 && issue.city.is_none()));
+    }
+
+    #[test]
+    fn unreadable_numbers_skip_the_entry() {
+        let mut report = ValidationReport::default();
+        let entry = RawCity {
+            id: Some(json!("abc")),
+            rank: Some(json!([1])),
+            ..raw()
+        };
+
+        assert!(entry.validate(0, &mut report).is_none());
+        assert_eq!(
+            problems(&report),
+            vec![
+                &CityProblem::InvalidField { field: "rank", value: "[1]".to_string() },
+                &CityProblem::InvalidField { field: "id", value: "abc".to_string() },
+            ]
+        );
+    }
+
+    #[test]
+    fn out_of_range_coordinates_skip_the_entry() {
+        let mut report = ValidationReport::default();
+        let entry = RawCity {
+            latitude: Some(json!(90.5)),
+            longitude: Some(json!(-180.5)),
+            ..raw()
+        };
+
+        assert!(entry.validate(0, &mut report).is_none());
+        assert_eq!(
+            problems(&report),
+            vec![
+                &CityProblem::OutOfRange { field: "latitude", value: 90.5 },
+                &CityProblem::OutOfRange { field: "longitude", value: -180.5 },
+            ]
+        );
+
+        // The poles and the antimeridian themselves are fine
+        let mut report = ValidationReport::default();
+        let edge = RawCity {
+            latitude: Some(json!(-90)),
+            longitude: Some(json!(180)),
+            ..raw()
+        };
+        assert!(edge.validate(0, &mut report).is_some());
+        assert!(report.is_clean());
+    }
+
+    #[test]
+    fn negative_and_fractional_numbers_are_out_of_range() {
+        let mut report = ValidationReport::default();
+        let entry = RawCity {
+            population: Some(json!(-5)),
+            rank: Some(json!(2.5)),
+            ..raw()
+        };
+
+        assert!(entry.validate(0, &mut report).is_none());
+        // The id falls back on the rank, so it is out of range as well
+        assert_eq!(
+            problems(&report),
+            vec![
+                &CityProblem::OutOfRange { field: "id", value: 2.5 },
+                &CityProblem::OutOfRange { field: "rank", value: 2.5 },
+                &CityProblem::OutOfRange { field: "population", value: -5.0 },
+            ]
+        );
+    }
+
+    #[test]
+    fn a_bad_country_code_skips_the_entry() {
+        let mut report = ValidationReport::default();
+        let entry = RawCity {
+            country_code: Some("USA".to_string()),
+            ..raw()
+        };
+
+        assert!(entry.validate(0, &mut report).is_none());
+        assert_eq!(
+            problems(&report),
+            vec![&CityProblem::InvalidField { field: "country_code", value: "USA".to_string() }]
+        );
+
+        let mut report = ValidationReport::default();
+        let lowercase = RawCity {
+            country_code: Some(" ca ".to_string()),
+            ..raw()
+        };
+        assert_eq!(lowercase.validate(0, &mut report).unwrap().country_code, "CA");
+    }
+
+    #[test]
+    fn unreadable_growth_is_reported_but_the_city_is_kept() {
+        let mut report = ValidationReport::default();
+        let entry = RawCity {
+            growth_from_2000_to_2013: Some(json!("lots")),
+            ..raw()
+        };
+
+        let city = entry.validate(1, &mut report).unwrap();
+        assert_eq!(city.growth_from_2000_to_2013, None);
+        assert_eq!(report.issues.len(), 1);
+        assert!(!report.issues[0].skipped);
+        assert_eq!(report.issues[0].city.as_deref(), Some("Boise City"));
     }
 }
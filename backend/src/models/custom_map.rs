@@ -0,0 +1,285 @@
+use chrono::{DateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::catalog::CityCatalog;
+use crate::error::AppError;
+use crate::models::city::City;
+use crate::AppResult;
+
+/// Longest name a map can have, in characters
+pub const MAX_NAME_LENGTH: usize = 100;
+
+/// Longest description a map can have, in characters
+const MAX_DESCRIPTION_LENGTH: usize = 1000;
+
+/// Most locations a single map can hold
+pub const MAX_LOCATIONS_PER_MAP: i64 = 5000;
+
+/// Who gets to see and play a custom map, stored as text in the database
+#[derive(Serialize, Deserialize, sqlx::Type, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum Visibility {
+    /// Only the owner
+    #[default]
+    Private,
+
+    /// Anybody with the link, but it isn't listed anywhere
+    Unlisted,
+
+    /// Anybody, and it is listed on the maps page
+    Public,
+}
+
+impl std::str::FromStr for Visibility {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "private" => Ok(Visibility::Private),
+            "unlisted" => Ok(Visibility::Unlisted),
+            "public" => Ok(Visibility::Public),
+            other => Err(format!("Unknown visibility: {}", other)),
+        }
+    }
+}
+
+/// A named set of locations made by a user, like "Pacific Northwest" or "College towns".
+/// Games can be played on a map instead of the whole catalog, and every map has a leaderboard of its own.
+#[derive(Serialize, Deserialize, sqlx::FromRow, Debug, Clone)]
+pub struct CustomMap {
+    pub id: Uuid,
+
+    /// The user who made the map, only they can change it
+    pub owner_id: i32,
+
+    pub name: String,
+    pub description: String,
+    pub visibility: Visibility,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl CustomMap {
+    pub fn new(owner_id: i32, name: String, description: String, visibility: Visibility) -> Self {
+        let now = Utc::now();
+
+        Self {
+            id: Uuid::new_v4(),
+            owner_id,
+            name,
+            description,
+            visibility,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// Makes sure the given user may see and play the map.
+    /// Private maps of other users are reported as not found, so map ids can't be probed.
+    ///
+    /// # Arguments:
+    /// * user_id: [Option]<i32> - The user looking at the map, none when not logged in
+    ///
+    /// # Returns:
+    /// * [AppResult]<()>
+    pub fn check_visible(&self, user_id: Option<i32>) -> AppResult<()> {
+        if self.visibility == Visibility::Private && user_id != Some(self.owner_id) {
+            return Err(AppError::MapNotFound);
+        }
+
+        Ok(())
+    }
+
+    /// Makes sure the map belongs to the given user, anybody else may not change it
+    ///
+    /// # Returns:
+    /// * [AppResult]<()>
+    pub fn check_owner(&self, user_id: i32) -> AppResult<()> {
+        self.check_visible(Some(user_id))?;
+
+        if self.owner_id != user_id {
+            return Err(AppError::InvalidMap("Only the owner can change this map".to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+/// One place on a custom map. Either a city from the catalog, whose name and coordinates are copied
+/// onto the location when it is added, or any point the owner typed in.
+#[derive(Serialize, Deserialize, sqlx::FromRow, Debug, Clone, PartialEq)]
+pub struct MapLocation {
+    pub id: i32,
+    pub map_id: Uuid,
+
+    /// [Id](City::id) of the catalog city, none for locations added by coordinates
+    pub city_id: Option<i32>,
+
+    pub name: String,
+
+    /// State, province or whatever region the place is in, if the owner gave one
+    pub region: Option<String>,
+
+    pub latitude: f32,
+    pub longitude: f32,
+}
+
+/// A location that is about to be added to a map
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewMapLocation {
+    pub city_id: Option<i32>,
+    pub name: String,
+    pub region: Option<String>,
+    pub latitude: f32,
+    pub longitude: f32,
+}
+
+impl NewMapLocation {
+    /// A location for a city from the catalog
+    pub fn from_city(city: &City) -> Self {
+        Self {
+            city_id: Some(city.id),
+            name: city.city.clone(),
+            region: Some(city.state.clone()),
+            latitude: city.latitude,
+            longitude: city.longitude,
+        }
+    }
+
+    /// A location at the given coordinates, named after them when it has no name of its own
+    ///
+    /// # Returns:
+    /// [Result]<[NewMapLocation], [String]> - Or what is wrong with the location
+    pub fn at(name: Option<String>, region: Option<String>, latitude: f32, longitude: f32) -> Result<Self, String> {
+        if !(-90.0..=90.0).contains(&latitude) {
+            return Err(format!("Latitude {} is not between -90 and 90", latitude));
+        }
+        if !(-180.0..=180.0).contains(&longitude) {
+            return Err(format!("Longitude {} is not between -180 and 180", longitude));
+        }
+
+        let name = name.unwrap_or_else(|| format!("{:.4}, {:.4}", latitude, longitude));
+        if name.chars().count() > MAX_NAME_LENGTH {
+            return Err(format!("Location names can be at most {} characters", MAX_NAME_LENGTH));
+        }
+
+        Ok(Self {
+            city_id: None,
+            name,
+            region,
+            latitude,
+            longitude,
+        })
+    }
+}
+
+/// The form for creating a map or changing its name, description and visibility
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct MapForm {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub visibility: Option<String>,
+}
+
+impl MapForm {
+    /// Checks the form
+    ///
+    /// # Returns:
+    /// [AppResult]<([String], [String], [Visibility])> - The trimmed name and description, and the visibility
+    pub fn validate(self) -> AppResult<(String, String, Visibility)> {
+        let name = self.name.trim().to_string();
+        if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+            return Err(AppError::InvalidMap(format!(
+                "The name has to be between 1 and {} characters",
+                MAX_NAME_LENGTH
+            )));
+        }
+
+        let description = self.description.trim().to_string();
+        if description.chars().count() > MAX_DESCRIPTION_LENGTH {
+            return Err(AppError::InvalidMap(format!(
+                "The description can be at most {} characters",
+                MAX_DESCRIPTION_LENGTH
+            )));
+        }
+
+        let visibility = match self.visibility.as_deref().map(str::trim) {
+            None | Some("") => Visibility::default(),
+            Some(visibility) => visibility.parse().map_err(AppError::InvalidMap)?,
+        };
+
+        Ok((name, description, visibility))
+    }
+}
+
+/// The form for adding a location to a map, either `city_id` on its own, or `lat` and `lng` with a `name`
+/// and optionally a `region`. Every field is text so the two halves of the form can be left empty.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct MapLocationForm {
+    pub city_id: Option<String>,
+    pub name: Option<String>,
+    pub region: Option<String>,
+    pub lat: Option<String>,
+    pub lng: Option<String>,
+}
+
+impl MapLocationForm {
+    /// Checks the form, looking catalog cities up by their id
+    ///
+    /// # Returns:
+    /// [AppResult]<[NewMapLocation]>
+    pub fn validate(self, catalog: &CityCatalog) -> AppResult<NewMapLocation> {
+        let text = |value: Option<String>| {
+            value
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+
+        if let Some(city_id) = text(self.city_id) {
+            let city_id: i32 = city_id
+                .parse()
+                .map_err(|_| AppError::InvalidMap(format!("{:?} is not a city id", city_id)))?;
+
+            return Ok(NewMapLocation::from_city(catalog.get(city_id)?));
+        }
+
+        let coordinate = |field: &str, value: Option<String>| -> AppResult<f32> {
+            let value = text(value).ok_or_else(|| AppError::InvalidMap(format!("{} is missing", field)))?;
+
+            value
+                .parse()
+                .map_err(|_| AppError::InvalidMap(format!("{} {:?} is not a number", field, value)))
+        };
+
+        let latitude = coordinate("Latitude", self.lat)?;
+        let longitude = coordinate("Longitude", self.lng)?;
+
+        NewMapLocation::at(text(self.name), text(self.region), latitude, longitude).map_err(AppError::InvalidMap)
+    }
+}
+
+/// Query string of the map page, the owner can search the catalog for cities to add
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct MapSearchQuery {
+    pub search: Option<String>,
+}
+
+/// Everything the page of a single map needs
+#[derive(Serialize, Debug, Clone)]
+pub struct MapPage {
+    pub map: CustomMap,
+
+    /// The places of the map, only for its owner. Everybody else would be looking at the answers
+    pub locations: Vec<MapLocation>,
+    pub num_locations: usize,
+
+    /// Whether the user looking at the map may change it
+    pub is_owner: bool,
+
+    /// Catalog cities matching the search box, for the owner to add
+    pub search_results: Vec<City>,
+    pub search: String,
+}
@@ -0,0 +1,196 @@
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use rand::seq::index::sample;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::catalog::CityCatalog;
+use crate::models::game::ROUNDS_PER_GAME;
+
+/// Number of cities the daily challenge picks from, the highest ranked ones in the catalog.
+/// This is fixed rather than the size of the catalog, so adding cities that don't rank among them never changes a past day.
+const NUM_CITIES: usize = 1000;
+
+/// The date of todays daily challenge, days roll over at midnight UTC
+pub fn today() -> NaiveDate {
+    Utc::now().date_naive()
+}
+
+/// Gets the ids of the cities in the daily challenge for the given date, in the order they are played.
+///
+/// The cities come from an RNG seeded with the date, so every player gets the same ones.
+/// The RNG picks places in the [rank order](CityCatalog::nth_ranked) of the catalog rather than ids,
+/// which only line up with the ranks for the bundled cities.
+/// Setting a `DAILY_SEED` in the .env file mixes a secret into the seed, so upcoming days can't be worked out ahead of time.
+///
+/// # Arguments:
+/// * date: [NaiveDate] - The day of the challenge
+/// * count: usize - How many cities the challenge has
+/// * catalog: &[CityCatalog] - The cities to pick from
+///
+/// # Returns:
+/// [Vec]<i32>
+///
+/// # .env variables
+/// * DAILY_SEED (optional)
+pub fn daily_city_ids(date: NaiveDate, count: usize, catalog: &CityCatalog) -> Vec<i32> {
+    let secret: u64 = std::env::var("DAILY_SEED")
+        .ok()
+        .and_then(|seed| seed.parse().ok())
+        .unwrap_or_default();
+
+    let seed = (date.num_days_from_ce() as u64) ^ secret.rotate_left(32);
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+
+    let num_cities = NUM_CITIES.min(catalog.len());
+
+    sample(&mut rng, num_cities, count.min(num_cities))
+        .into_iter()
+        .filter_map(|position| catalog.nth_ranked(position))
+        .map(|city| city.id)
+        .collect()
+}
+
+/// One finished daily challenge on the daily leaderboard
+#[derive(Serialize, Deserialize, sqlx::FromRow, Debug, Clone)]
+pub struct DailyLeaderBoardRow {
+    pub rank: i64,
+    pub user_id: i32,
+    pub total_score: i32,
+    pub finished_at: DateTime<Utc>,
+}
+
+/// One past day in the daily challenge archive
+#[derive(Serialize, Deserialize, sqlx::FromRow, Debug, Clone)]
+pub struct DailyArchiveEntry {
+    pub daily_date: NaiveDate,
+    pub num_players: i64,
+    pub best_score: i32,
+}
+
+/// The leaderboard of a single day. The cities are only filled in once the day is over,
+/// nobody gets to see the answers while the challenge can still be played.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DailyLeaderBoard {
+    pub date: NaiveDate,
+    pub is_today: bool,
+    pub rows: Vec<DailyLeaderBoardRow>,
+    pub cities: Vec<String>,
+}
+
+impl IntoResponse for DailyLeaderBoard {
+    fn into_response(self) -> Response {
+        Json(self).into_response()
+    }
+}
+
+impl DailyLeaderBoard {
+    /// Builds the leaderboard of a day, revealing the days cities if it is already over
+    ///
+    /// # Returns:
+    /// [Self](DailyLeaderBoard)
+    pub fn new(date: NaiveDate, rows: Vec<DailyLeaderBoardRow>, catalog: &CityCatalog) -> Self {
+        let is_today = date >= today();
+
+        let cities = if is_today {
+            Vec::new()
+        } else {
+            daily_city_ids(date, ROUNDS_PER_GAME as usize, catalog)
+                .into_iter()
+                .filter_map(|id| catalog.find(id))
+                .map(|c| format!("{}, {}", c.city, c.state))
+                .collect()
+        };
+
+        Self {
+            date,
+            is_today,
+            rows,
+            cities,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::models::city::City;
+
+    /// Cities ranked 1 to `count`, with ids that have nothing to do with their rank
+    fn ranked_catalog(count: i32) -> CityCatalog {
+        CityCatalog::from_cities(
+            (1..=count)
+                .map(|rank| City {
+                    id: 100_000 + (rank * 7919) % 10_007,
+                    city: format!("City {}", rank),
+                    state: "State".to_string(),
+                    country_code: "US".to_string(),
+                    population: 10_000_000 / rank as u64,
+                    rank,
+                    ..City::default()
+                })
+                .collect(),
+        )
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2023, 10, day).unwrap()
+    }
+
+    #[test]
+    fn the_same_day_always_gets_the_same_cities() {
+        let catalog = ranked_catalog(1200);
+
+        assert_eq!(daily_city_ids(date(1), 5, &catalog), daily_city_ids(date(1), 5, &catalog));
+        assert_eq!(daily_city_ids(date(1), 5, &catalog).len(), 5);
+    }
+
+    #[test]
+    fn other_days_get_other_cities() {
+        let catalog = ranked_catalog(1200);
+        let picks: HashSet<Vec<i32>> = (1..=31).map(|day| daily_city_ids(date(day), 5, &catalog)).collect();
+
+        assert_eq!(picks.len(), 31);
+    }
+
+    #[test]
+    fn a_day_never_repeats_a_city() {
+        let catalog = ranked_catalog(1200);
+
+        for day in 1..=31 {
+            let ids = daily_city_ids(date(day), 5, &catalog);
+            assert_eq!(ids.iter().collect::<HashSet<_>>().len(), ids.len(), "{:?}", ids);
+        }
+
+        // Even when the challenge asks for every city there is
+        let small = ranked_catalog(5);
+        let mut ids = daily_city_ids(date(1), 10, &small);
+        ids.sort_unstable();
+        let mut all: Vec<i32> = small.all().iter().map(|city| city.id).collect();
+        all.sort_unstable();
+        assert_eq!(ids, all);
+    }
+
+    #[test]
+    fn only_the_highest_ranked_cities_come_up() {
+        let catalog = ranked_catalog(1200);
+
+        for day in 1..=31 {
+            for id in daily_city_ids(date(day), 5, &catalog) {
+                let city = catalog.find(id).unwrap();
+                assert!(city.rank as usize <= NUM_CITIES, "{} is ranked {}", city.city, city.rank);
+            }
+        }
+    }
+
+    #[test]
+    fn cities_ranked_below_the_pool_never_change_a_day() {
+        let before = daily_city_ids(date(3), 5, &ranked_catalog(1000));
+
+        assert_eq!(daily_city_ids(date(3), 5, &ranked_catalog(1500)), before);
+    }
+}
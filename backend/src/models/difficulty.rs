@@ -0,0 +1,245 @@
+use std::str::FromStr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::imagery::{MapType, SatelliteRequest};
+
+/// How hard a game is, picked when the game is started and stored as text in the database.
+/// Every tier is a [preset](DifficultyPreset) of which cities come up, how the picture looks and how much a guess is worth.
+///
+/// Medium is the game as it has always been played, the other tiers have a leaderboard of their own.
+#[derive(Serialize, Deserialize, sqlx::Type, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum Difficulty {
+    /// Big cities, zoomed out, with roads and labels
+    Easy,
+
+    /// Any city at the usual zoom
+    #[default]
+    Medium,
+
+    /// Smaller cities, zoomed in
+    Hard,
+
+    /// Small towns, zoomed in so far only a few blocks are visible
+    Expert,
+}
+
+/// Everything a difficulty tier changes about a game
+#[derive(Serialize, Clone, Copy, Debug)]
+pub struct DifficultyPreset {
+    pub difficulty: Difficulty,
+    pub name: &'static str,
+
+    /// Only cities with at least this many people come up
+    pub min_population: Option<u64>,
+
+    /// Only cities with at most this many people come up
+    pub max_population: Option<u64>,
+
+    /// Zoom level of the round image, higher shows less around the city
+    pub zoom: u8,
+
+    pub map_type: MapType,
+
+    /// Every score of the game is multiplied by this
+    pub multiplier: f32,
+}
+
+const PRESETS: [DifficultyPreset; 4] = [
+    DifficultyPreset {
+        difficulty: Difficulty::Easy,
+        name: "Easy",
+        min_population: Some(250_000),
+        max_population: None,
+        zoom: 12,
+        map_type: MapType::Hybrid,
+        multiplier: 0.5,
+    },
+    DifficultyPreset {
+        difficulty: Difficulty::Medium,
+        name: "Medium",
+        min_population: None,
+        max_population: None,
+        zoom: 14,
+        map_type: MapType::Satellite,
+        multiplier: 1.0,
+    },
+    DifficultyPreset {
+        difficulty: Difficulty::Hard,
+        name: "Hard",
+        min_population: None,
+        max_population: Some(150_000),
+        zoom: 16,
+        map_type: MapType::Satellite,
+        multiplier: 1.5,
+    },
+    DifficultyPreset {
+        difficulty: Difficulty::Expert,
+        name: "Expert",
+        min_population: None,
+        max_population: Some(75_000),
+        zoom: 17,
+        map_type: MapType::Satellite,
+        multiplier: 2.0,
+    },
+];
+
+impl Difficulty {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Medium => "medium",
+            Difficulty::Hard => "hard",
+            Difficulty::Expert => "expert",
+        }
+    }
+
+    pub fn preset(self) -> &'static DifficultyPreset {
+        &PRESETS[self as usize]
+    }
+
+    /// Every tier, easiest first
+    pub fn presets() -> &'static [DifficultyPreset] {
+        &PRESETS
+    }
+
+    /// The round image of a city at this difficulty
+    pub fn satellite_request(self, lat: f32, lng: f32) -> SatelliteRequest {
+        let preset = self.preset();
+
+        SatelliteRequest {
+            zoom: preset.zoom,
+            map_type: preset.map_type,
+            ..SatelliteRequest::new(lat, lng)
+        }
+    }
+
+    /// Whether round images of this difficulty look like the ones the [RoundPool](crate::imagery::prefetch::RoundPool) prefetches
+    pub fn uses_default_image(self) -> bool {
+        let default = SatelliteRequest::new(0.0, 0.0);
+        let preset = self.preset();
+
+        preset.zoom == default.zoom && preset.map_type == default.map_type
+    }
+
+    /// Applies the multiplier of the tier to the score of a guess
+    pub fn apply(self, score: i32) -> i32 {
+        (score as f32 * self.preset().multiplier).round() as i32
+    }
+}
+
+impl FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "easy" => Ok(Difficulty::Easy),
+            "medium" => Ok(Difficulty::Medium),
+            "hard" => Ok(Difficulty::Hard),
+            "expert" => Ok(Difficulty::Expert),
+            other => Err(format!("Unknown difficulty: {}", other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::city::City;
+    use crate::models::filter::LocationFilter;
+
+    const ALL: [Difficulty; 4] = [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard, Difficulty::Expert];
+
+    /// Whether a city of the given size comes up at the difficulty
+    fn comes_up(difficulty: Difficulty, population: u64) -> bool {
+        let preset = difficulty.preset();
+        let city = City {
+            population,
+            ..City::default()
+        };
+
+        LocationFilter::default()
+            .within_population(preset.min_population, preset.max_population)
+            .matches(&city)
+    }
+
+    #[test]
+    fn every_tier_has_its_own_preset() {
+        for difficulty in ALL {
+            assert_eq!(difficulty.preset().difficulty, difficulty);
+        }
+        let names: Vec<&str> = Difficulty::presets().iter().map(|preset| preset.name).collect();
+        assert_eq!(names, vec!["Easy", "Medium", "Hard", "Expert"]);
+    }
+
+    #[test]
+    fn scores_are_multiplied_and_rounded() {
+        assert_eq!(Difficulty::Easy.apply(100), 50);
+        assert_eq!(Difficulty::Easy.apply(45), 23, "22.5 rounds away from zero");
+        assert_eq!(Difficulty::Easy.apply(1), 1);
+        assert_eq!(Difficulty::Medium.apply(87), 87);
+        assert_eq!(Difficulty::Hard.apply(33), 50, "49.5 rounds up");
+        assert_eq!(Difficulty::Hard.apply(100), 150);
+        assert_eq!(Difficulty::Expert.apply(37), 74);
+
+        for difficulty in ALL {
+            assert_eq!(difficulty.apply(0), 0);
+        }
+    }
+
+    #[test]
+    fn every_spelling_of_a_tier_is_read() {
+        for difficulty in ALL {
+            assert_eq!(difficulty.as_str().parse::<Difficulty>(), Ok(difficulty));
+            assert_eq!(difficulty.preset().name.parse::<Difficulty>(), Ok(difficulty));
+        }
+        assert_eq!(" EXPERT ".parse::<Difficulty>(), Ok(Difficulty::Expert));
+        assert_eq!("hArD".parse::<Difficulty>(), Ok(Difficulty::Hard));
+
+        assert!("impossible".parse::<Difficulty>().unwrap_err().contains("impossible"));
+        assert!("".parse::<Difficulty>().is_err());
+    }
+
+    #[test]
+    fn easy_only_has_big_cities() {
+        assert!(!comes_up(Difficulty::Easy, 249_999));
+        assert!(comes_up(Difficulty::Easy, 250_000));
+        assert!(comes_up(Difficulty::Easy, 8_000_000));
+    }
+
+    #[test]
+    fn medium_has_every_city() {
+        assert!(comes_up(Difficulty::Medium, 0));
+        assert!(comes_up(Difficulty::Medium, 8_000_000));
+    }
+
+    #[test]
+    fn hard_and_expert_only_have_smaller_cities() {
+        assert!(comes_up(Difficulty::Hard, 150_000));
+        assert!(!comes_up(Difficulty::Hard, 150_001));
+        assert!(comes_up(Difficulty::Expert, 75_000));
+        assert!(!comes_up(Difficulty::Expert, 75_001));
+        assert!(comes_up(Difficulty::Expert, 100));
+    }
+
+    #[test]
+    fn the_band_narrows_a_filter_that_has_one() {
+        let preset = Difficulty::Hard.preset();
+        let filter = LocationFilter {
+            min_population: Some(100_000),
+            max_population: Some(500_000),
+            ..LocationFilter::default()
+        }
+        .within_population(preset.min_population, preset.max_population);
+
+        assert_eq!((filter.min_population, filter.max_population), (Some(100_000), Some(150_000)));
+    }
+
+    #[test]
+    fn only_medium_looks_like_the_prefetched_images() {
+        let default: Vec<Difficulty> = ALL.into_iter().filter(|difficulty| difficulty.uses_default_image()).collect();
+        assert_eq!(default, vec![Difficulty::Medium]);
+    }
+}
@@ -0,0 +1,469 @@
+use serde_derive::{Deserialize, Serialize};
+
+use crate::error::AppError;
+use crate::haversine_distance;
+use crate::models::city::City;
+use crate::regions::KnownRegions;
+use crate::AppResult;
+
+/// Half the circumference of the earth, no two places are further apart than this
+const MAX_RADIUS_KM: f32 = 20_038.0;
+
+/// Restricts which cities a game picks its rounds from. Every part is optional, a city has to match all parts
+/// that are set, and a filter without any parts matches every city.
+///
+/// The filter is stored on the game, games with different filters are scored on separate leaderboards,
+/// see [key](LocationFilter::key).
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct LocationFilter {
+    /// [ISO 3166-1 alpha-2](https://en.wikipedia.org/wiki/ISO_3166-1_alpha-2) code of the country, always upper case
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub country_code: Option<String>,
+
+    /// Name of the state, or whatever the first level admin region is called in the country
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_population: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_population: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub area: Option<Area>,
+
+    /// Lowest rank allowed, 1 being the most populated city
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_rank: Option<i32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_rank: Option<i32>,
+
+    /// Only cities in one of these regions, set for games in a region mode so every round can be judged.
+    /// Worked out from the boundaries whenever the game picks a city, never stored and not part of the key
+    #[serde(skip)]
+    pub regions: Option<KnownRegions>,
+}
+
+/// Part of the map the cities have to be in
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Area {
+    /// Everything between two latitudes and two longitudes.
+    /// A box whose west edge is east of its east edge crosses the antimeridian.
+    BoundingBox {
+        south: f32,
+        west: f32,
+        north: f32,
+        east: f32,
+    },
+
+    /// Everything within `radius_km` of a point
+    Radius {
+        latitude: f32,
+        longitude: f32,
+        radius_km: f32,
+    },
+}
+
+impl Area {
+    pub fn contains(&self, latitude: f32, longitude: f32) -> bool {
+        match *self {
+            Area::BoundingBox {
+                south,
+                west,
+                north,
+                east,
+            } => {
+                let in_longitude = if west <= east {
+                    (west..=east).contains(&longitude)
+                } else {
+                    longitude >= west || longitude <= east
+                };
+
+                (south..=north).contains(&latitude) && in_longitude
+            }
+            Area::Radius {
+                latitude: center_lat,
+                longitude: center_lng,
+                radius_km,
+            } => haversine_distance(center_lat, center_lng, latitude, longitude) <= radius_km,
+        }
+    }
+}
+
+impl LocationFilter {
+    /// Whether the filter lets every city through
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Whether the city is one the filter allows
+    pub fn matches(&self, city: &City) -> bool {
+        self.country_code.as_ref().map_or(true, |code| city.country_code == *code)
+            && self.state.as_ref().map_or(true, |state| city.state.eq_ignore_ascii_case(state))
+            && self.min_population.map_or(true, |min| city.population >= min)
+            && self.max_population.map_or(true, |max| city.population <= max)
+            && self.min_rank.map_or(true, |min| city.rank >= min)
+            && self.max_rank.map_or(true, |max| city.rank <= max)
+            && self.regions.as_ref().map_or(true, |regions| regions.contains(city))
+            && self
+                .area
+                .as_ref()
+                .map_or(true, |area| area.contains(city.latitude, city.longitude))
+    }
+
+    /// Identifies the filter on the leaderboard. Two filters letting the same cities through the same way
+    /// always get the same key, the empty filter has an empty key.
+    ///
+    /// # Returns:
+    /// [String] - Like `country=US&state=california&population=100000-&rank=1-100`
+    pub fn key(&self) -> String {
+        let mut parts = Vec::new();
+
+        if let Some(code) = &self.country_code {
+            parts.push(format!("country={}", code));
+        }
+        if let Some(state) = &self.state {
+            parts.push(format!("state={}", state.to_lowercase()));
+        }
+        if self.min_population.is_some() || self.max_population.is_some() {
+            parts.push(format!("population={}", range_text(self.min_population, self.max_population)));
+        }
+        match &self.area {
+            Some(Area::BoundingBox {
+                south,
+                west,
+                north,
+                east,
+            }) => parts.push(format!("bbox={:.4},{:.4},{:.4},{:.4}", south, west, north, east)),
+            Some(Area::Radius {
+                latitude,
+                longitude,
+                radius_km,
+            }) => parts.push(format!("radius={:.4},{:.4},{:.1}", latitude, longitude, radius_km)),
+            None => {}
+        }
+        if self.min_rank.is_some() || self.max_rank.is_some() {
+            parts.push(format!("rank={}", range_text(self.min_rank, self.max_rank)));
+        }
+
+        parts.join("&")
+    }
+
+    /// The filter narrowed down to cities between the given populations as well, on top of any band it already has
+    pub fn within_population(&self, min: Option<u64>, max: Option<u64>) -> Self {
+        Self {
+            min_population: self.min_population.max(min),
+            max_population: match (self.max_population, max) {
+                (Some(own), Some(max)) => Some(own.min(max)),
+                (own, max) => own.or(max),
+            },
+            ..self.clone()
+        }
+    }
+
+    /// Short description of the filter for the game and leaderboard pages
+    pub fn describe(&self) -> String {
+        if self.is_empty() {
+            return "Every city".to_string();
+        }
+
+        let mut parts = Vec::new();
+
+        match (&self.state, &self.country_code) {
+            (Some(state), Some(code)) => parts.push(format!("in {}, {}", state, code)),
+            (Some(state), None) => parts.push(format!("in {}", state)),
+            (None, Some(code)) => parts.push(format!("in {}", code)),
+            (None, None) => {}
+        }
+        match (self.min_population, self.max_population) {
+            (Some(min), Some(max)) => parts.push(format!("with {} to {} people", min, max)),
+            (Some(min), None) => parts.push(format!("with at least {} people", min)),
+            (None, Some(max)) => parts.push(format!("with at most {} people", max)),
+            (None, None) => {}
+        }
+        match &self.area {
+            Some(Area::BoundingBox {
+                south,
+                west,
+                north,
+                east,
+            }) => parts.push(format!("between {}, {} and {}, {}", south, west, north, east)),
+            Some(Area::Radius {
+                latitude,
+                longitude,
+                radius_km,
+            }) => parts.push(format!("within {} km of {}, {}", radius_km, latitude, longitude)),
+            None => {}
+        }
+        match (self.min_rank, self.max_rank) {
+            (Some(min), Some(max)) => parts.push(format!("ranked {} to {}", min, max)),
+            (Some(min), None) => parts.push(format!("ranked {} or lower", min)),
+            (None, Some(max)) => parts.push(format!("in the top {}", max)),
+            (None, None) => {}
+        }
+
+        format!("Cities {}", parts.join(", "))
+    }
+}
+
+fn range_text<T: ToString>(min: Option<T>, max: Option<T>) -> String {
+    format!(
+        "{}-{}",
+        min.map(|min| min.to_string()).unwrap_or_default(),
+        max.map(|max| max.to_string()).unwrap_or_default()
+    )
+}
+
+/// A location filter the way it comes in from a form or query string. Every field is text, empty fields are
+/// left out, so the settings form can be submitted with only some of them filled in.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct LocationFilterForm {
+    pub country: Option<String>,
+    pub state: Option<String>,
+    pub min_population: Option<String>,
+    pub max_population: Option<String>,
+    pub south: Option<String>,
+    pub west: Option<String>,
+    pub north: Option<String>,
+    pub east: Option<String>,
+    pub center_lat: Option<String>,
+    pub center_lng: Option<String>,
+    pub radius_km: Option<String>,
+    pub min_rank: Option<String>,
+    pub max_rank: Option<String>,
+}
+
+impl LocationFilterForm {
+    /// Checks every field and builds the filter out of them.
+    /// Only the filter itself is checked here, whether any city matches it is up to the [CityCatalog](crate::catalog::CityCatalog).
+    ///
+    /// # Returns:
+    /// [AppResult]<[LocationFilter]> - [AppError::InvalidFilter] saying what is wrong with the first bad field
+    pub fn validate(self) -> AppResult<LocationFilter> {
+        let country_code = text(self.country)
+            .map(|code| {
+                let code = code.to_uppercase();
+                if code.len() == 2 && code.chars().all(|c| c.is_ascii_alphabetic()) {
+                    Ok(code)
+                } else {
+                    Err(invalid(format!("{} is not a two letter country code", code)))
+                }
+            })
+            .transpose()?;
+        let state = text(self.state);
+
+        let min_population = number::<u64>("min_population", self.min_population)?;
+        let max_population = number::<u64>("max_population", self.max_population)?;
+        check_order("population", min_population, max_population)?;
+
+        let min_rank = number::<i32>("min_rank", self.min_rank)?;
+        let max_rank = number::<i32>("max_rank", self.max_rank)?;
+        if min_rank.into_iter().chain(max_rank).any(|rank| rank < 1) {
+            return Err(invalid("Ranks start at 1".to_string()));
+        }
+        check_order("rank", min_rank, max_rank)?;
+
+        let bounding_box = [
+            number::<f32>("south", self.south)?,
+            number::<f32>("west", self.west)?,
+            number::<f32>("north", self.north)?,
+            number::<f32>("east", self.east)?,
+        ];
+        let radius = [
+            number::<f32>("center_lat", self.center_lat)?,
+            number::<f32>("center_lng", self.center_lng)?,
+            number::<f32>("radius_km", self.radius_km)?,
+        ];
+
+        let area = match (bounding_box, radius) {
+            ([None, None, None, None], [None, None, None]) => None,
+            ([Some(south), Some(west), Some(north), Some(east)], [None, None, None]) => {
+                check_latitude(south)?;
+                check_latitude(north)?;
+                check_longitude(west)?;
+                check_longitude(east)?;
+                if south > north {
+                    return Err(invalid("The south edge has to be below the north edge".to_string()));
+                }
+                Some(Area::BoundingBox {
+                    south,
+                    west,
+                    north,
+                    east,
+                })
+            }
+            ([None, None, None, None], [Some(latitude), Some(longitude), Some(radius_km)]) => {
+                check_latitude(latitude)?;
+                check_longitude(longitude)?;
+                if !(radius_km > 0.0 && radius_km <= MAX_RADIUS_KM) {
+                    return Err(invalid(format!("The radius has to be between 0 and {} km", MAX_RADIUS_KM)));
+                }
+                Some(Area::Radius {
+                    latitude,
+                    longitude,
+                    radius_km,
+                })
+            }
+            ([None, None, None, None], _) => {
+                return Err(invalid("A radius needs center_lat, center_lng and radius_km".to_string()))
+            }
+            (_, [None, None, None]) => {
+                return Err(invalid("A bounding box needs south, west, north and east".to_string()))
+            }
+            _ => return Err(invalid("Pick either a bounding box or a radius, not both".to_string())),
+        };
+
+        Ok(LocationFilter {
+            country_code,
+            state,
+            min_population,
+            max_population,
+            area,
+            min_rank,
+            max_rank,
+            regions: None,
+        })
+    }
+}
+
+fn invalid(message: String) -> AppError {
+    AppError::InvalidFilter(message)
+}
+
+fn text(value: Option<String>) -> Option<String> {
+    value
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+fn number<T: std::str::FromStr>(field: &str, value: Option<String>) -> AppResult<Option<T>> {
+    text(value)
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|_| invalid(format!("{} {:?} is not a valid number", field, value)))
+        })
+        .transpose()
+}
+
+fn check_order<T: PartialOrd + std::fmt::Display>(field: &str, min: Option<T>, max: Option<T>) -> AppResult<()> {
+    match (min, max) {
+        (Some(min), Some(max)) if min > max => Err(invalid(format!(
+            "The minimum {} {} is above the maximum {}",
+            field, min, max
+        ))),
+        _ => Ok(()),
+    }
+}
+
+fn check_latitude(latitude: f32) -> AppResult<()> {
+    if !(-90.0..=90.0).contains(&latitude) {
+        return Err(invalid(format!("Latitude {} is not between -90 and 90", latitude)));
+    }
+    Ok(())
+}
+
+fn check_longitude(longitude: f32) -> AppResult<()> {
+    if !(-180.0..=180.0).contains(&longitude) {
+        return Err(invalid(format!("Longitude {} is not between -180 and 180", longitude)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn form(fields: &[(&str, &str)]) -> LocationFilterForm {
+        let fields: serde_json::Map<String, serde_json::Value> = fields
+            .iter()
+            .map(|(field, value)| (field.to_string(), serde_json::Value::from(*value)))
+            .collect();
+        serde_json::from_value(serde_json::Value::Object(fields)).unwrap()
+    }
+
+    fn error(fields: &[(&str, &str)]) -> String {
+        match form(fields).validate() {
+            Err(AppError::InvalidFilter(message)) => message,
+            other => panic!("expected an invalid filter, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn an_empty_form_is_an_empty_filter() {
+        let filter = LocationFilterForm::default().validate().unwrap();
+        assert!(filter.is_empty());
+        assert_eq!(filter.key(), "");
+
+        // Fields submitted without anything filled in are left out
+        let blank = form(&[("country", ""), ("state", "  "), ("min_population", ""), ("south", " ")]);
+        assert!(blank.validate().unwrap().is_empty());
+    }
+
+    #[test]
+    fn the_key_ignores_field_order_and_case() {
+        let one = form(&[
+            ("country", "us"),
+            ("state", "California"),
+            ("max_rank", "100"),
+            ("min_population", "100000"),
+        ]);
+        let other = form(&[
+            ("min_population", " 100000 "),
+            ("max_rank", "100"),
+            ("state", "CALIFORNIA"),
+            ("country", "US"),
+        ]);
+
+        let key = one.validate().unwrap().key();
+        assert_eq!(key, "country=US&state=california&population=100000-&rank=-100");
+        assert_eq!(other.validate().unwrap().key(), key);
+    }
+
+    #[test]
+    fn the_key_tells_areas_apart() {
+        let bbox = form(&[("south", "40"), ("west", "-80"), ("north", "45"), ("east", "-70")]);
+        let radius = form(&[("center_lat", "40"), ("center_lng", "-80"), ("radius_km", "50")]);
+
+        assert_eq!(bbox.validate().unwrap().key(), "bbox=40.0000,-80.0000,45.0000,-70.0000");
+        assert_eq!(radius.validate().unwrap().key(), "radius=40.0000,-80.0000,50.0");
+    }
+
+    #[test]
+    fn ranges_have_to_be_in_order() {
+        assert!(error(&[("min_population", "5000"), ("max_population", "100")]).contains("above the maximum"));
+        assert!(error(&[("min_rank", "50"), ("max_rank", "10")]).contains("above the maximum"));
+        assert!(error(&[("south", "45"), ("west", "-80"), ("north", "40"), ("east", "-70")]).contains("south edge"));
+
+        // A minimum equal to the maximum is fine
+        assert!(form(&[("min_rank", "10"), ("max_rank", "10")]).validate().is_ok());
+    }
+
+    #[test]
+    fn negative_numbers_are_rejected() {
+        assert!(error(&[("min_population", "-5")]).contains("not a valid number"));
+        assert!(error(&[("max_rank", "0")]).contains("Ranks start at 1"));
+        assert!(error(&[("min_rank", "-3")]).contains("Ranks start at 1"));
+        assert!(error(&[("center_lat", "40"), ("center_lng", "-80"), ("radius_km", "-1")]).contains("radius"));
+    }
+
+    #[test]
+    fn other_bad_fields_are_rejected() {
+        assert!(error(&[("country", "USA")]).contains("two letter country code"));
+        assert!(error(&[("south", "-91"), ("west", "0"), ("north", "0"), ("east", "1")]).contains("Latitude"));
+        assert!(error(&[("south", "40"), ("west", "-80")]).contains("bounding box needs"));
+        assert!(error(&[("radius_km", "10")]).contains("radius needs"));
+        assert!(error(&[
+            ("south", "40"),
+            ("west", "-80"),
+            ("north", "45"),
+            ("east", "-70"),
+            ("radius_km", "10"),
+        ])
+        .contains("not both"));
+    }
+}
@@ -0,0 +1,347 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde_derive::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::catalog::CityCatalog;
+use crate::error::AppError;
+use crate::models::daily::daily_city_ids;
+use crate::models::difficulty::Difficulty;
+use crate::models::filter::{LocationFilter, LocationFilterForm};
+use crate::models::guess_mode::GuessMode;
+use crate::models::hint::HintKind;
+use crate::models::location_source::LocationSource;
+use crate::models::preferences::DistanceUnit;
+use crate::models::round::Round;
+use crate::regions::BoundarySet;
+use crate::scoring::ScoringKind;
+use crate::AppResult;
+
+/// Number of locations a player has to guess before a game is over
+pub const ROUNDS_PER_GAME: i32 = 5;
+
+/// The different ways the game can be played, stored as text in the database
+#[derive(Serialize, Deserialize, sqlx::Type, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum GameMode {
+    /// Random cities, scored by how far off the guess was
+    #[default]
+    Classic,
+
+    /// The same cities for everybody on a given day, playable once per user
+    Daily,
+}
+
+impl GameMode {
+    /// The scoring strategy new games of this mode are played with.
+    /// The daily challenge is always scored the same way, so everyone on its leaderboard is comparable.
+    pub fn scoring(self) -> ScoringKind {
+        match self {
+            GameMode::Classic => ScoringKind::from_env(),
+            GameMode::Daily => ScoringKind::ExponentialDecay,
+        }
+    }
+
+    /// Whether finished games of this mode count towards the main leaderboard.
+    /// The daily challenge has a leaderboard of its own.
+    pub fn is_ranked(self) -> bool {
+        match self {
+            GameMode::Classic => true,
+            GameMode::Daily => false,
+        }
+    }
+}
+
+/// A game is a fixed number of rounds played by one user, only the total of a finished game
+/// makes it onto the leaderboard.
+#[derive(Serialize, Deserialize, sqlx::FromRow, Debug, Clone)]
+pub struct Game {
+    pub id: Uuid,
+
+    /// The user playing this game
+    pub user_id: i32,
+
+    /// How many rounds this game has in total
+    pub num_rounds: i32,
+
+    pub mode: GameMode,
+
+    /// How every round of this game is scored
+    pub scoring: ScoringKind,
+
+    /// Running total of the scores of every answered round
+    pub total_score: i32,
+
+    pub created_at: DateTime<Utc>,
+
+    /// Set once the last round has been answered
+    pub finished_at: Option<DateTime<Utc>>,
+
+    /// The day of the challenge, only set for daily challenge games
+    pub daily_date: Option<NaiveDate>,
+
+    /// Which cities the rounds are picked from, empty for games played with every city
+    #[sqlx(json)]
+    pub location_filter: LocationFilter,
+
+    /// [Key](LocationFilter::key) of the filter, games are grouped by it on the leaderboard
+    pub filter_key: String,
+
+    /// Which cities come up on top of the filter, how the pictures look and what the scores are multiplied by
+    pub difficulty: Difficulty,
+
+    /// The [custom map](crate::models::custom_map::CustomMap) the game is played on, none for games using the catalog
+    pub map_id: Option<Uuid>,
+
+    /// Whether rounds are scored by distance, or by the country or state the guess is in
+    pub guess_mode: GuessMode,
+
+    /// Whether rounds are catalog cities or random points on land
+    pub location_source: LocationSource,
+}
+
+impl Game {
+    /// Starts a new game for the given user with [ROUNDS_PER_GAME] rounds, scored the way the mode says
+    pub fn new(user_id: i32, mode: GameMode) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            user_id,
+            num_rounds: ROUNDS_PER_GAME,
+            mode,
+            scoring: mode.scoring(),
+            total_score: 0,
+            created_at: Utc::now(),
+            finished_at: None,
+            daily_date: None,
+            location_filter: LocationFilter::default(),
+            filter_key: String::new(),
+            difficulty: Difficulty::default(),
+            map_id: None,
+            guess_mode: GuessMode::default(),
+            location_source: LocationSource::default(),
+        }
+    }
+
+    /// Plays this game on the locations of a custom map instead of the catalog
+    pub fn on_map(self, map_id: Uuid) -> Self {
+        Self {
+            map_id: Some(map_id),
+            ..self
+        }
+    }
+
+    /// Plays this game at the given difficulty
+    pub fn with_difficulty(self, difficulty: Difficulty) -> Self {
+        Self { difficulty, ..self }
+    }
+
+    /// Plays this game in the given guess mode
+    pub fn with_guess_mode(self, guess_mode: GuessMode) -> Self {
+        Self { guess_mode, ..self }
+    }
+
+    /// Picks the locations of this game from the given source
+    pub fn with_location_source(self, location_source: LocationSource) -> Self {
+        Self {
+            location_source,
+            ..self
+        }
+    }
+
+    /// The cities this game picks from, its location filter narrowed down to the population band of its difficulty.
+    /// In a region mode only the cities in a region of the boundaries are left
+    pub fn city_filter(&self, boundaries: &BoundarySet) -> LocationFilter {
+        let preset = self.difficulty.preset();
+
+        LocationFilter {
+            regions: boundaries.known_regions(self.guess_mode),
+            ..self
+                .location_filter
+                .within_population(preset.min_population, preset.max_population)
+        }
+    }
+
+    /// Only picks cities the filter lets through for this game
+    pub fn with_filter(self, location_filter: LocationFilter) -> Self {
+        Self {
+            filter_key: location_filter.key(),
+            location_filter,
+            ..self
+        }
+    }
+
+    /// Starts the daily challenge of the given date for a user
+    pub fn new_daily(user_id: i32, date: NaiveDate) -> Self {
+        Self {
+            daily_date: Some(date),
+            ..Self::new(user_id, GameMode::Daily)
+        }
+    }
+
+    /// The city a round of this game has to be played with, if the game decides that up front.
+    /// Games that pick a random city for every round return none.
+    pub fn city_id_for_round(&self, round_number: i32, catalog: &CityCatalog) -> Option<i32> {
+        let date = self.daily_date?;
+        let index = usize::try_from(round_number - 1).ok()?;
+
+        daily_city_ids(date, self.num_rounds as usize, catalog).get(index).copied()
+    }
+
+    /// Whether the game counts towards the main leaderboard once finished.
+    /// Filtered games, games at another difficulty than medium, games guessing regions and games on random land
+    /// are only compared with games played with
+    /// the same settings, see [Store::get_settings_leaderboard](crate::db::Store::get_settings_leaderboard),
+    /// and games on a custom map with other games on that map, see [Store::get_map_leaderboard](crate::db::Store::get_map_leaderboard).
+    /// Every [scoring strategy](ScoringKind) has leaderboards of its own, a game out of 5000 points would outweigh dozens out of 100.
+    pub fn is_ranked(&self) -> bool {
+        self.mode.is_ranked()
+            && self.location_filter.is_empty()
+            && self.difficulty == Difficulty::Medium
+            && self.map_id.is_none()
+            && self.guess_mode == GuessMode::Point
+            && self.location_source == LocationSource::Cities
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished_at.is_some()
+    }
+
+    /// Makes sure the game belongs to the given user, other users games are reported as not found
+    ///
+    /// # Returns:
+    /// * [AppResult]<()>
+    pub fn check_owner(&self, user_id: i32) -> AppResult<()> {
+        if self.user_id != user_id {
+            return Err(AppError::GameNotFound);
+        }
+
+        Ok(())
+    }
+}
+
+/// The settings a classic game can be started with, as they come in from a form or query string
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct GameSettingsForm {
+    pub difficulty: Option<String>,
+    pub guess_mode: Option<String>,
+    pub location_source: Option<String>,
+
+    #[serde(flatten)]
+    pub filter: LocationFilterForm,
+}
+
+impl GameSettingsForm {
+    /// Checks the settings, a missing difficulty is medium, a missing guess mode is [GuessMode::Point]
+    /// and a missing location source is [LocationSource::Cities].
+    /// Random land has no population or name to filter by, so it can only be played without a filter.
+    ///
+    /// # Returns:
+    /// [AppResult]<[GameSettings]>
+    pub fn validate(self) -> AppResult<GameSettings> {
+        let difficulty = match self.difficulty.as_deref().map(str::trim) {
+            None | Some("") => Difficulty::default(),
+            Some(difficulty) => difficulty.parse().map_err(AppError::InvalidDifficulty)?,
+        };
+        let guess_mode = match self.guess_mode.as_deref().map(str::trim) {
+            None | Some("") => GuessMode::default(),
+            Some(guess_mode) => guess_mode.parse().map_err(AppError::InvalidGuessMode)?,
+        };
+        let location_source = match self.location_source.as_deref().map(str::trim) {
+            None | Some("") => LocationSource::default(),
+            Some(location_source) => location_source.parse().map_err(AppError::InvalidLocationSource)?,
+        };
+
+        let filter = self.filter.validate()?;
+        if location_source.is_land() && !filter.is_empty() {
+            return Err(AppError::InvalidLocationSource(
+                "Locations on land can't be filtered, leave the filter empty".to_string(),
+            ));
+        }
+
+        Ok(GameSettings {
+            filter,
+            difficulty,
+            guess_mode,
+            location_source,
+        })
+    }
+}
+
+/// The checked settings of a classic game, every combination has a leaderboard of its own
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct GameSettings {
+    pub filter: LocationFilter,
+    pub difficulty: Difficulty,
+    pub guess_mode: GuessMode,
+    pub location_source: LocationSource,
+}
+
+impl GameSettings {
+    /// Whether these are the settings of the main leaderboard
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Short description of the settings for the leaderboard page
+    pub fn describe(&self) -> String {
+        let mut description = match self.location_source {
+            LocationSource::Cities => format!("{}, {}", self.filter.describe(), self.difficulty.preset().name),
+            source => format!("{}, {}", source.option().name, self.difficulty.preset().name),
+        };
+        if self.guess_mode.is_region() {
+            description.push_str(&format!(", guessing the {}", self.guess_mode.as_str()));
+        }
+
+        description
+    }
+}
+
+/// One line of the end of game summary
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RoundSummary {
+    pub round_number: i32,
+    pub city: String,
+    pub state: String,
+    /// Distance of the guess in the unit of the summary, rounded to 3 decimal places like on the results page
+    pub distance: String,
+    pub score: i32,
+    pub hints: Vec<HintKind>,
+}
+
+/// Everything the summary page needs to show a finished game
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GameSummary {
+    pub game: Game,
+    pub rounds: Vec<RoundSummary>,
+    pub distance_unit: DistanceUnit,
+}
+
+impl GameSummary {
+    /// Builds the summary of a game out of its rounds, looking up the city of every answered round in the catalog
+    /// and showing the distances in the given unit
+    ///
+    /// # Returns:
+    /// * [AppResult]<[Self](GameSummary)>
+    pub fn new(game: Game, rounds: Vec<Round>, catalog: &CityCatalog, distance_unit: DistanceUnit) -> AppResult<Self> {
+        let mut summaries = Vec::with_capacity(rounds.len());
+
+        for round in rounds.iter().filter(|r| r.answered_at.is_some()) {
+            let city = round.target(catalog)?;
+
+            summaries.push(RoundSummary {
+                round_number: round.round_number,
+                city: city.city.clone(),
+                state: city.state.clone(),
+                distance: distance_unit.format(round.distance.unwrap_or_default(), 3),
+                score: round.score.unwrap_or_default(),
+                hints: round.hints.clone(),
+            });
+        }
+
+        Ok(Self {
+            game,
+            rounds: summaries,
+            distance_unit,
+        })
+    }
+}
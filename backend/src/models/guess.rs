@@ -0,0 +1,48 @@
+use chrono::{DateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::models::difficulty::Difficulty;
+use crate::models::game::GameMode;
+use crate::scoring::ScoringKind;
+
+/// A single scored guess, kept forever so players can look back at how they did
+#[derive(Serialize, Deserialize, sqlx::FromRow, Debug, Clone)]
+pub struct Guess {
+    pub id: i32,
+
+    /// The user who made the guess
+    pub user_id: i32,
+
+    /// The round the guess was made in, if it still exists
+    pub round_id: Option<Uuid>,
+
+    /// Id of the city the user had to guess, see [Round::city_id](crate::models::round::Round::city_id)
+    pub city_id: i32,
+
+    /// The place the user had to guess when it wasn't a catalog city, copied from the round,
+    /// see [Round::target_name](crate::models::round::Round::target_name)
+    pub target_name: Option<String>,
+    pub target_region: Option<String>,
+    pub target_lat: Option<f32>,
+    pub target_lng: Option<f32>,
+
+    pub guess_lat: f32,
+    pub guess_lng: f32,
+
+    /// Distance in kilometers between the guess and the city on the WGS84 ellipsoid, see [distance_km](crate::geodesy::distance_km).
+    /// Guesses scored before that were measured on a sphere
+    pub distance: f32,
+
+    pub score: i32,
+
+    /// The strategy the score was computed with, none for guesses scored before strategies were recorded
+    pub scoring: Option<ScoringKind>,
+
+    /// Difficulty of the game the guess was made in, its [multiplier](Difficulty::apply) is part of the score
+    pub difficulty: Difficulty,
+
+    pub game_mode: GameMode,
+
+    pub created_at: DateTime<Utc>,
+}
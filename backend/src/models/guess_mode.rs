@@ -0,0 +1,83 @@
+use std::str::FromStr;
+
+use serde_derive::{Deserialize, Serialize};
+
+/// What the player has to get right in a round, picked when the game is started and stored as text in the database.
+/// Every mode is clicked on the same map, only the way the click is judged differs.
+#[derive(Serialize, Deserialize, sqlx::Type, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum GuessMode {
+    /// The exact spot, scored by how far off the guess was
+    #[default]
+    Point,
+
+    /// The country the city is in, judged against the [country boundaries](crate::regions::BoundarySet)
+    Country,
+
+    /// The US state the city is in, judged against the state boundaries
+    State,
+}
+
+/// How a guess mode is shown on the settings pages
+#[derive(Serialize, Clone, Copy, Debug)]
+pub struct GuessModeOption {
+    pub mode: GuessMode,
+    pub name: &'static str,
+    pub description: &'static str,
+}
+
+const OPTIONS: [GuessModeOption; 3] = [
+    GuessModeOption {
+        mode: GuessMode::Point,
+        name: "Pinpoint",
+        description: "click the exact spot, the closer the more points",
+    },
+    GuessModeOption {
+        mode: GuessMode::Country,
+        name: "Country",
+        description: "click anywhere in the right country, neighbouring countries get partial credit",
+    },
+    GuessModeOption {
+        mode: GuessMode::State,
+        name: "State",
+        description: "click anywhere in the right US state, neighbouring states get partial credit",
+    },
+];
+
+impl GuessMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            GuessMode::Point => "point",
+            GuessMode::Country => "country",
+            GuessMode::State => "state",
+        }
+    }
+
+    /// Every mode, in the order the settings pages list them
+    pub fn options() -> &'static [GuessModeOption] {
+        &OPTIONS
+    }
+
+    pub fn option(self) -> &'static GuessModeOption {
+        &OPTIONS[self as usize]
+    }
+
+    /// Whether guesses are judged by the region they fall in rather than their distance
+    pub fn is_region(self) -> bool {
+        self != GuessMode::Point
+    }
+}
+
+impl FromStr for GuessMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "point" => Ok(GuessMode::Point),
+            "country" => Ok(GuessMode::Country),
+            "state" => Ok(GuessMode::State),
+            other => Err(format!("Unknown guess mode: {}", other)),
+        }
+    }
+}
@@ -0,0 +1,398 @@
+use std::str::FromStr;
+
+use once_cell::sync::Lazy;
+use serde_derive::{Deserialize, Serialize};
+use sqlx::postgres::{PgHasArrayType, PgTypeInfo};
+
+use crate::catalog::CityCatalog;
+use crate::geodesy;
+use crate::models::city::City;
+use crate::models::game::Game;
+use crate::models::round::Round;
+use crate::AppResult;
+
+/// How many zoom levels the [zoom out](HintKind::ZoomOut) hint takes off the round image
+pub const ZOOM_OUT_LEVELS: u8 = 3;
+
+/// The compass hint points from the most populated of this many cities around the place
+const COMPASS_CANDIDATES: usize = 10;
+
+/// Help a player can ask for during a round, every hint costs part of the score of that round.
+/// The hints taken are stored on the round as text.
+#[derive(Serialize, Deserialize, sqlx::Type, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum HintKind {
+    /// Names the state, or whatever the region is called outside the US
+    State,
+
+    /// Tells roughly how many people live there, and how that changed from 2000 to 2013
+    Population,
+
+    /// Shows the round image from further up
+    ZoomOut,
+
+    /// Tells which way the place is from a well known city nearby
+    Compass,
+}
+
+// Rounds keep their hints in a TEXT[] column
+impl PgHasArrayType for HintKind {
+    fn array_type_info() -> PgTypeInfo {
+        <&str as PgHasArrayType>::array_type_info()
+    }
+}
+
+/// How a hint is offered on the round page
+#[derive(Serialize, Clone, Copy, Debug)]
+pub struct HintOption {
+    pub kind: HintKind,
+    pub name: &'static str,
+    pub description: &'static str,
+}
+
+const OPTIONS: [HintOption; 4] = [
+    HintOption {
+        kind: HintKind::State,
+        name: "State",
+        description: "the state or region the place is in",
+    },
+    HintOption {
+        kind: HintKind::Population,
+        name: "Population",
+        description: "roughly how many people live there",
+    },
+    HintOption {
+        kind: HintKind::ZoomOut,
+        name: "Zoom out",
+        description: "the picture from further up",
+    },
+    HintOption {
+        kind: HintKind::Compass,
+        name: "Compass",
+        description: "which way the place is from a big city nearby",
+    },
+];
+
+/// Part of the best score every hint costs, in percent
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HintPenalties {
+    pub state: i32,
+    pub population: i32,
+    pub zoom_out: i32,
+    pub compass: i32,
+}
+
+impl HintPenalties {
+    /// Reads the penalties from the .env file, a missing penalty or one that isn't between 0 and 100 gets its default
+    ///
+    /// # .env variables
+    /// * HINT_PENALTY_STATE (optional) - Percent of the best score the state hint costs, defaults to 25
+    /// * HINT_PENALTY_POPULATION (optional) - Percent the population hint costs, defaults to 10
+    /// * HINT_PENALTY_ZOOM_OUT (optional) - Percent the zoom out hint costs, defaults to 15
+    /// * HINT_PENALTY_COMPASS (optional) - Percent the compass hint costs, defaults to 20
+    pub fn from_env() -> Self {
+        let penalty = |variable: &str, default: i32| {
+            std::env::var(variable)
+                .ok()
+                .and_then(|penalty| penalty.parse::<i32>().ok())
+                .filter(|penalty| (0..=100).contains(penalty))
+                .unwrap_or(default)
+        };
+
+        Self {
+            state: penalty("HINT_PENALTY_STATE", 25),
+            population: penalty("HINT_PENALTY_POPULATION", 10),
+            zoom_out: penalty("HINT_PENALTY_ZOOM_OUT", 15),
+            compass: penalty("HINT_PENALTY_COMPASS", 20),
+        }
+    }
+
+    pub fn of(&self, kind: HintKind) -> i32 {
+        match kind {
+            HintKind::State => self.state,
+            HintKind::Population => self.population,
+            HintKind::ZoomOut => self.zoom_out,
+            HintKind::Compass => self.compass,
+        }
+    }
+}
+
+/// Read once, the first time a hint is shown or taken
+pub static HINT_PENALTIES: Lazy<HintPenalties> = Lazy::new(HintPenalties::from_env);
+
+impl HintKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HintKind::State => "state",
+            HintKind::Population => "population",
+            HintKind::ZoomOut => "zoom_out",
+            HintKind::Compass => "compass",
+        }
+    }
+
+    /// Every hint, in the order the round page lists them
+    pub fn options() -> &'static [HintOption] {
+        &OPTIONS
+    }
+
+    pub fn option(self) -> &'static HintOption {
+        &OPTIONS[self as usize]
+    }
+
+    /// Percent of the best score taking this hint costs right now, see [HintPenalties::from_env]
+    pub fn penalty_percent(self) -> i32 {
+        HINT_PENALTIES.of(self)
+    }
+
+    /// What the hint tells about the place to guess
+    ///
+    /// # Arguments:
+    /// * game: &[Game] - The game of the round. The state and compass hints would give the answer away in the region modes,
+    ///   the compass names the state and country of a city nearby, which is usually the one the place is in
+    /// * target: &[City] - The place to guess
+    /// * catalog: &[CityCatalog] - Where the compass hint finds the city to point from
+    ///
+    /// # Returns:
+    /// [Option]<[String]> - None when the hint can't be given for this place, like the population of a spot on land
+    pub fn reveal(self, game: &Game, target: &City, catalog: &CityCatalog) -> Option<String> {
+        match self {
+            HintKind::State if game.guess_mode.is_region() || target.state.is_empty() => None,
+            HintKind::State => Some(format!("It is in {}.", target.state)),
+            HintKind::Population if target.population == 0 => None,
+            HintKind::Population => Some(describe_population(target)),
+            HintKind::ZoomOut => Some(format!("The picture is {} zoom levels further out.", ZOOM_OUT_LEVELS)),
+            HintKind::Compass if game.guess_mode.is_region() => None,
+            HintKind::Compass => {
+                let reference = compass_reference(target, catalog)?;
+                let bearing = geodesy::initial_bearing(
+                    reference.latitude as f64,
+                    reference.longitude as f64,
+                    target.latitude as f64,
+                    target.longitude as f64,
+                );
+
+                Some(format!(
+                    "It is {} of {}{}.",
+                    compass_direction(bearing),
+                    reference.city,
+                    if reference.state.is_empty() { String::new() } else { format!(", {}", reference.state) }
+                ))
+            }
+        }
+    }
+}
+
+impl FromStr for HintKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "state" => Ok(HintKind::State),
+            "population" => Ok(HintKind::Population),
+            "zoom_out" => Ok(HintKind::ZoomOut),
+            "compass" => Ok(HintKind::Compass),
+            other => Err(format!("Unknown hint: {}", other)),
+        }
+    }
+}
+
+/// The hint picked on the round page
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct HintForm {
+    pub kind: String,
+}
+
+/// A hint as shown on the round page, either already taken with what it told, or still on offer
+#[derive(Serialize, Clone, Debug)]
+pub struct HintPage {
+    pub kind: HintKind,
+    pub name: &'static str,
+    pub description: &'static str,
+
+    /// Percent of the best score the hint costs
+    pub penalty_percent: i32,
+    pub taken: bool,
+
+    /// What the hint told, only there once it is taken
+    pub text: Option<String>,
+}
+
+impl HintPage {
+    /// Every hint that can be given for the round, the ones taken so far with what they told
+    ///
+    /// # Returns:
+    /// [AppResult]<[Vec]<[HintPage]>> - [AppError::CityNotFound](crate::error::AppError::CityNotFound) when the city of the round is gone
+    pub fn for_round(round: &Round, game: &Game, catalog: &CityCatalog) -> AppResult<Vec<Self>> {
+        let target = round.target(catalog)?;
+
+        Ok(HintKind::options()
+            .iter()
+            .filter_map(|option| {
+                let text = option.kind.reveal(game, &target, catalog)?;
+                let taken = round.has_hint(option.kind);
+
+                Some(Self {
+                    kind: option.kind,
+                    name: option.name,
+                    description: option.description,
+                    penalty_percent: option.kind.penalty_percent(),
+                    taken,
+                    text: taken.then_some(text),
+                })
+            })
+            .collect())
+    }
+}
+
+/// Upper bounds of the population ranges the population hint gives
+const POPULATION_RANGES: [u64; 9] = [
+    1_000, 10_000, 50_000, 100_000, 250_000, 500_000, 1_000_000, 5_000_000, 10_000_000,
+];
+
+fn describe_population(city: &City) -> String {
+    let upper = POPULATION_RANGES.iter().position(|&bound| city.population < bound);
+    let range = match upper {
+        Some(0) => format!("Fewer than {} people live there", with_separators(POPULATION_RANGES[0])),
+        Some(index) => format!(
+            "Between {} and {} people live there",
+            with_separators(POPULATION_RANGES[index - 1]),
+            with_separators(POPULATION_RANGES[index])
+        ),
+        None => format!("More than {} people live there", with_separators(POPULATION_RANGES[8])),
+    };
+
+    match city.growth_from_2000_to_2013 {
+        Some(growth) if growth >= 0.0 => format!("{}, {:.1}% more than in 2000.", range, growth),
+        Some(growth) => format!("{}, {:.1}% fewer than in 2000.", range, -growth),
+        None => format!("{}.", range),
+    }
+}
+
+/// 1000000 as 1,000,000
+fn with_separators(number: u64) -> String {
+    let digits = number.to_string();
+    let groups: Vec<&str> = digits
+        .as_bytes()
+        .rchunks(3)
+        .rev()
+        .map(|group| std::str::from_utf8(group).unwrap_or_default())
+        .collect();
+
+    groups.join(",")
+}
+
+/// The most populated city around the target, the place itself doesn't count
+fn compass_reference<'a>(target: &City, catalog: &'a CityCatalog) -> Option<&'a City> {
+    catalog
+        .k_nearest(target.latitude, target.longitude, COMPASS_CANDIDATES + 1)
+        .into_iter()
+        .filter(|(city, distance)| city.id != target.id && *distance > 1.0)
+        .map(|(city, _)| city)
+        .max_by_key(|city| city.population)
+}
+
+/// One of the eight compass directions for a bearing in degrees clockwise from north
+fn compass_direction(bearing: f64) -> &'static str {
+    const DIRECTIONS: [&str; 8] = [
+        "north", "north-east", "east", "south-east", "south", "south-west", "west", "north-west",
+    ];
+
+    DIRECTIONS[((bearing + 22.5) / 45.0) as usize % 8]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::game::GameMode;
+    use crate::models::guess_mode::GuessMode;
+
+    fn city(id: i32, name: &str, population: u64, latitude: f32, longitude: f32) -> City {
+        City {
+            id,
+            city: name.to_string(),
+            state: "Texas".to_string(),
+            country_code: "US".to_string(),
+            latitude,
+            longitude,
+            population,
+            rank: id,
+            ..City::default()
+        }
+    }
+
+    fn with_population(population: u64, growth: Option<f32>) -> City {
+        City {
+            growth_from_2000_to_2013: growth,
+            ..city(1, "Somewhere", population, 0.0, 0.0)
+        }
+    }
+
+    #[test]
+    fn compass_directions_change_halfway_between_points() {
+        assert_eq!(compass_direction(0.0), "north");
+        assert_eq!(compass_direction(22.49), "north");
+        assert_eq!(compass_direction(22.5), "north-east");
+        assert_eq!(compass_direction(67.49), "north-east");
+        assert_eq!(compass_direction(67.5), "east");
+        assert_eq!(compass_direction(180.0), "south");
+        assert_eq!(compass_direction(292.5), "north-west");
+        assert_eq!(compass_direction(337.49), "north-west");
+        assert_eq!(compass_direction(337.5), "north");
+        assert_eq!(compass_direction(359.99), "north");
+    }
+
+    #[test]
+    fn population_ranges_include_their_lower_bound() {
+        assert!(describe_population(&with_population(999, None)).starts_with("Fewer than 1,000 people"));
+        assert!(describe_population(&with_population(1_000, None)).starts_with("Between 1,000 and 10,000 people"));
+        assert!(describe_population(&with_population(9_999, None)).starts_with("Between 1,000 and 10,000 people"));
+        assert!(describe_population(&with_population(9_999_999, None)).starts_with("Between 5,000,000 and 10,000,000 people"));
+        assert!(describe_population(&with_population(10_000_000, None)).starts_with("More than 10,000,000 people"));
+    }
+
+    #[test]
+    fn population_growth_reads_as_more_or_fewer() {
+        assert_eq!(
+            describe_population(&with_population(2_000, Some(12.34))),
+            "Between 1,000 and 10,000 people live there, 12.3% more than in 2000."
+        );
+        assert_eq!(
+            describe_population(&with_population(2_000, Some(-4.0))),
+            "Between 1,000 and 10,000 people live there, 4.0% fewer than in 2000."
+        );
+        assert_eq!(describe_population(&with_population(2_000, None)), "Between 1,000 and 10,000 people live there.");
+    }
+
+    #[test]
+    fn separators_every_three_digits() {
+        assert_eq!(with_separators(999), "999");
+        assert_eq!(with_separators(1_000), "1,000");
+        assert_eq!(with_separators(10_000_000), "10,000,000");
+    }
+
+    #[test]
+    fn region_modes_get_no_hint_naming_a_region() {
+        let houston = city(1, "Houston", 2_000_000, 29.76, -95.37);
+        let pasadena = city(2, "Pasadena", 150_000, 29.74, -95.21);
+        let catalog = CityCatalog::from_cities(vec![houston.clone(), pasadena.clone()]);
+
+        let point = Game::new(1, GameMode::Classic);
+        assert_eq!(
+            HintKind::Compass.reveal(&point, &pasadena, &catalog).as_deref(),
+            Some("It is east of Houston, Texas.")
+        );
+        assert_eq!(HintKind::State.reveal(&point, &pasadena, &catalog).as_deref(), Some("It is in Texas."));
+
+        for guess_mode in [GuessMode::Country, GuessMode::State] {
+            let game = Game {
+                guess_mode,
+                ..Game::new(1, GameMode::Classic)
+            };
+
+            assert_eq!(HintKind::Compass.reveal(&game, &pasadena, &catalog), None);
+            assert_eq!(HintKind::State.reveal(&game, &pasadena, &catalog), None);
+            assert!(HintKind::Population.reveal(&game, &pasadena, &catalog).is_some());
+        }
+    }
+}
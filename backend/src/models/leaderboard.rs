@@ -5,6 +5,7 @@ use serde_derive::{Deserialize, Serialize};
 use crate::db::Store;
 use crate::error::AppError;
 use crate::models::user::{LeaderBoardRow, User, UserRankInfo};
+use crate::scoring::ScoringKind;
 
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct LeaderBoard {
@@ -25,9 +26,9 @@ impl LeaderBoard {
     }
 
 
-    /// Fetches top num amount of users from the database
-    pub async fn populate_top_num_users(num_users: i32, database: Store) -> Result<Self, AppError> {
-        let user_list = database.get_top_num_users(num_users).await?;
+    /// Fetches top num amount of users from the database, off the leaderboard of the given scoring strategy
+    pub async fn populate_top_num_users(num_users: i32, scoring: ScoringKind, database: Store) -> Result<Self, AppError> {
+        let user_list = database.get_top_num_users(num_users, scoring).await?;
         Ok(
             Self {
                 user_list
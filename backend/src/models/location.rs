@@ -1,8 +1,10 @@
 use serde_derive::{Deserialize, Serialize};
+use uuid::Uuid;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Location {
     pub lat: f32,
     pub lng: f32,
-    pub city_id: String, // Will need to turn into u32, since it is only deserialized as a string
+    /// The opaque round this guess is for, the city itself is only known to the server
+    pub round_id: Uuid,
 }
@@ -0,0 +1,82 @@
+use std::str::FromStr;
+
+use serde_derive::{Deserialize, Serialize};
+
+/// Where the locations of a game come from, picked when the game is started and stored as text in the database
+#[derive(Serialize, Deserialize, sqlx::Type, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum LocationSource {
+    /// The center of a city from the catalog
+    #[default]
+    Cities,
+
+    /// Any point on land, picked evenly over the [land](crate::regions::land::Land)
+    Land,
+
+    /// Any point on land near people, around a catalog city picked by its population
+    PopulatedLand,
+}
+
+/// How a location source is shown on the settings pages
+#[derive(Serialize, Clone, Copy, Debug)]
+pub struct LocationSourceOption {
+    pub source: LocationSource,
+    pub name: &'static str,
+    pub description: &'static str,
+}
+
+const OPTIONS: [LocationSourceOption; 3] = [
+    LocationSourceOption {
+        source: LocationSource::Cities,
+        name: "City centers",
+        description: "the middle of a city",
+    },
+    LocationSourceOption {
+        source: LocationSource::Land,
+        name: "Anywhere on land",
+        description: "any spot on land, mostly fields, forests and deserts",
+    },
+    LocationSourceOption {
+        source: LocationSource::PopulatedLand,
+        name: "Near people",
+        description: "any spot on land, more often where lots of people live",
+    },
+];
+
+impl LocationSource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LocationSource::Cities => "cities",
+            LocationSource::Land => "land",
+            LocationSource::PopulatedLand => "populated_land",
+        }
+    }
+
+    /// Every source, in the order the settings pages list them
+    pub fn options() -> &'static [LocationSourceOption] {
+        &OPTIONS
+    }
+
+    pub fn option(self) -> &'static LocationSourceOption {
+        &OPTIONS[self as usize]
+    }
+
+    /// Whether rounds are random points on land rather than catalog cities
+    pub fn is_land(self) -> bool {
+        self != LocationSource::Cities
+    }
+}
+
+impl FromStr for LocationSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "cities" => Ok(LocationSource::Cities),
+            "land" => Ok(LocationSource::Land),
+            "populated_land" => Ok(LocationSource::PopulatedLand),
+            other => Err(format!("Unknown location source: {}", other)),
+        }
+    }
+}
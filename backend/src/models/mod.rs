@@ -1,7 +1,18 @@
 pub mod city;
-pub mod city_with_image;
+pub mod custom_map;
+pub mod difficulty;
+pub mod filter;
+pub mod guess_mode;
+pub mod hint;
 pub mod location;
+pub mod location_source;
 pub mod maps;
 pub mod page;
+pub mod preferences;
 pub mod user;
 pub mod leaderboard;
+pub mod round;
+pub mod game;
+pub mod guess;
+pub mod stats;
+pub mod daily;
@@ -1,19 +1,62 @@
+use crate::catalog::CityCatalog;
 use crate::error::AppError;
-use crate::haversine_distance;
+use crate::geodesy;
+use crate::imagery::MapType;
 use crate::models::city::City;
-use crate::models::city_with_image::CityAndImage;
+use crate::models::hint::HintKind;
 use crate::models::maps::InteractiveMap;
+use crate::models::preferences::DistanceUnit;
+use crate::models::round::Round;
 use axum::response::{IntoResponse, Response};
 use axum::Json;
 use serde_derive::{Deserialize, Serialize};
 use tera::ast::ExprVal::Float;
 use tracing::info;
+use uuid::Uuid;
 use crate::models::user::UserRankInfo;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PagePackage {
-    pub city_image: CityAndImage,
+    /// Where the browser loads the satellite image of the round from, see [round_image](crate::handlers::round_image)
+    pub image_url: String,
     pub map: InteractiveMap,
+    /// The round the user is guessing for, this is all the browser gets to send back
+    pub round_id: Uuid,
+}
+
+impl PagePackage {
+    pub fn new(round_id: Uuid, map_type: MapType) -> Self {
+        Self {
+            image_url: format!("/images/rounds/{}", round_id),
+            map: InteractiveMap::new(map_type),
+            round_id,
+        }
+    }
+
+    /// Points the image at the zoomed out picture once the [zoom out](HintKind::ZoomOut) hint is taken,
+    /// browsers keep round images for a day so the new picture needs an address of its own
+    pub fn with_hints(mut self, round: &Round) -> Self {
+        if round.has_hint(HintKind::ZoomOut) {
+            self.image_url.push_str("?zoomed_out=true");
+        }
+
+        self
+    }
+}
+
+/// Pages that can also be fetched as json pick the format with a `?format=json` query
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ResponseFormat {
+    #[default]
+    Html,
+    Json,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct FormatQuery {
+    #[serde(default)]
+    pub format: ResponseFormat,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -21,9 +64,120 @@ pub struct CityPage {
     pub city: City,
 }
 
+/// Guesses closer than this to a city are said to be near it, further out they only get the distance to it
+pub const NEAR_GUESS_KM: f32 = 25.0;
+
+/// Where the user's guess landed, as the closest city in the [catalog](CityCatalog)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NearestCityPage {
+    pub city: City,
+
+    /// Distance from the guess to the city in the users unit, rounded to one decimal
+    pub distance: String,
+    pub distance_unit: DistanceUnit,
+
+    /// Whether the guess was within [NEAR_GUESS_KM] of the city
+    pub near: bool,
+}
+
+impl NearestCityPage {
+    /// Looks up the city closest to the guess, none when the catalog is empty
+    pub fn new(catalog: &CityCatalog, lat: f32, lng: f32, distance_unit: DistanceUnit) -> Option<Self> {
+        catalog.nearest(lat, lng).map(|(city, distance)| NearestCityPage {
+            city: city.clone(),
+            distance: distance_unit.format(distance, 1),
+            distance_unit,
+            near: distance <= NEAR_GUESS_KM,
+        })
+    }
+}
+
+impl IntoResponse for NearestCityPage {
+    fn into_response(self) -> Response {
+        Json(self).into_response()
+    }
+}
+
+/// Most cities [NearbyCitiesPage] lists, and how many it lists when the query doesn't say
+pub const MAX_NEARBY_CITIES: usize = 100;
+pub const DEFAULT_NEARBY_CITIES: usize = 10;
+
+/// Query string of the cities near here page. Without a radius the closest cities are listed however far they are
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NearbyQuery {
+    pub lat: f32,
+    pub lng: f32,
+    pub radius_km: Option<f32>,
+    pub limit: Option<usize>,
+}
+
+/// A city near the spot a [NearbyCitiesPage] was asked for
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NearbyCity {
+    pub city: City,
+
+    /// Distance from the spot to the city, in the unit of the page
+    pub distance: f32,
+}
+
+/// The cities closest to a spot on the map, closest first
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NearbyCitiesPage {
+    pub lat: f32,
+    pub lng: f32,
+    pub distance_unit: DistanceUnit,
+    pub cities: Vec<NearbyCity>,
+}
+
+impl NearbyCitiesPage {
+    /// Looks up the cities for the query, within its radius when it has one.
+    /// The radius is always in kilometers, the distances of the cities found are in the given unit.
+    ///
+    /// # Returns:
+    /// [Result]<[Self](NearbyCitiesPage), [AppError]> - [AppError::InvalidFilter] for coordinates or a radius out of range
+    pub fn new(catalog: &CityCatalog, query: &NearbyQuery, distance_unit: DistanceUnit) -> Result<Self, AppError> {
+        if !(-90.0..=90.0).contains(&query.lat) || !(-180.0..=180.0).contains(&query.lng) {
+            return Err(AppError::InvalidFilter(
+                "The latitude has to be between -90 and 90 and the longitude between -180 and 180".to_string(),
+            ));
+        }
+
+        let limit = query.limit.unwrap_or(DEFAULT_NEARBY_CITIES).min(MAX_NEARBY_CITIES);
+        let found = match query.radius_km {
+            Some(radius_km) if radius_km.is_nan() || radius_km <= 0.0 => {
+                return Err(AppError::InvalidFilter("The radius has to be more than 0 km".to_string()));
+            }
+            Some(radius_km) => catalog.within_radius(query.lat, query.lng, radius_km),
+            None => catalog.k_nearest(query.lat, query.lng, limit),
+        };
+
+        Ok(NearbyCitiesPage {
+            lat: query.lat,
+            lng: query.lng,
+            distance_unit,
+            cities: found
+                .into_iter()
+                .take(limit)
+                .map(|(city, distance)| NearbyCity {
+                    city: city.clone(),
+                    distance: distance_unit.convert_km(distance),
+                })
+                .collect(),
+        })
+    }
+}
+
+impl IntoResponse for NearbyCitiesPage {
+    fn into_response(self) -> Response {
+        Json(self).into_response()
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DistancePage {
+    /// Distance from the guess to the city in the users unit, rounded to 3 decimal places
     pub distance: String,
+    pub distance_unit: DistanceUnit,
 }
 
 impl IntoResponse for DistancePage {
@@ -33,14 +187,15 @@ impl IntoResponse for DistancePage {
 }
 
 impl DistancePage {
-    pub fn new(city: City, lat: f32, lng: f32) -> Result<Self, AppError> {
-        let distance_unrounded: f32 = haversine_distance(city.latitude, city.longitude, lat, lng);
-        let distance_rounded_string = format!("{:.3}", distance_unrounded);
+    pub fn new(city: City, lat: f32, lng: f32, distance_unit: DistanceUnit) -> Result<Self, AppError> {
+        let distance_unrounded = geodesy::distance_km(city.latitude as f64, city.longitude as f64, lat as f64, lng as f64);
+        let distance_rounded_string = distance_unit.format(distance_unrounded as f32, 3);
 
-        info!("Distance rounded: {}", distance_rounded_string);
+        info!("Distance rounded: {} {}", distance_rounded_string, distance_unit.as_str());
 
         Ok(DistancePage {
             distance: distance_rounded_string,
+            distance_unit,
         })
     }
 
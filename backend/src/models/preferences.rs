@@ -0,0 +1,156 @@
+use std::str::FromStr;
+
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::error::AppError;
+use crate::geodesy::KM_TO_MILES;
+use crate::imagery::MapType;
+use crate::models::difficulty::Difficulty;
+use crate::models::guess_mode::GuessMode;
+use crate::AppResult;
+
+/// What distances are shown in, stored as text in the database.
+/// Distances are always measured and stored in kilometers, they are only converted when they are shown.
+#[derive(Serialize, Deserialize, sqlx::Type, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum DistanceUnit {
+    #[default]
+    Kilometers,
+    Miles,
+}
+
+/// How a distance unit is shown on the pages
+#[derive(Serialize, Clone, Copy, Debug)]
+pub struct DistanceUnitOption {
+    pub unit: DistanceUnit,
+    pub name: &'static str,
+
+    /// Written after a distance, like "12.5 miles off"
+    pub plural: &'static str,
+    pub abbreviation: &'static str,
+}
+
+const OPTIONS: [DistanceUnitOption; 2] = [
+    DistanceUnitOption {
+        unit: DistanceUnit::Kilometers,
+        name: "Kilometers",
+        plural: "kilometers",
+        abbreviation: "km",
+    },
+    DistanceUnitOption {
+        unit: DistanceUnit::Miles,
+        name: "Miles",
+        plural: "miles",
+        abbreviation: "mi",
+    },
+];
+
+impl DistanceUnit {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DistanceUnit::Kilometers => "kilometers",
+            DistanceUnit::Miles => "miles",
+        }
+    }
+
+    /// Every unit, in the order the settings page lists them
+    pub fn options() -> &'static [DistanceUnitOption] {
+        &OPTIONS
+    }
+
+    pub fn option(self) -> &'static DistanceUnitOption {
+        &OPTIONS[self as usize]
+    }
+
+    /// Converts a distance in kilometers to this unit
+    pub fn convert_km(self, km: f32) -> f32 {
+        match self {
+            DistanceUnit::Kilometers => km,
+            DistanceUnit::Miles => (km as f64 * KM_TO_MILES) as f32,
+        }
+    }
+
+    /// Converts a distance in kilometers to this unit, rounded to the given number of decimals for showing it
+    pub fn format(self, km: f32, decimals: usize) -> String {
+        format!("{:.*}", decimals, self.convert_km(km))
+    }
+}
+
+impl FromStr for DistanceUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "kilometers" | "km" => Ok(DistanceUnit::Kilometers),
+            "miles" | "mi" => Ok(DistanceUnit::Miles),
+            other => Err(format!("Unknown distance unit: {}", other)),
+        }
+    }
+}
+
+/// How a user likes the game to look and start, stored in the `user_preferences` table.
+/// Users who never saved their preferences get the [defaults](UserPreferences::defaults).
+#[derive(Serialize, Deserialize, sqlx::FromRow, Clone, Copy, Debug, PartialEq)]
+pub struct UserPreferences {
+    pub user_id: i32,
+
+    /// What every distance the user sees is shown in
+    pub distance_unit: DistanceUnit,
+
+    /// The map guesses are clicked on and the results are drawn on
+    pub map_type: MapType,
+
+    /// Preselected when starting a game, and used for games started without picking any settings
+    pub difficulty: Difficulty,
+
+    /// Preselected when starting a game, and used for games started without picking any settings
+    pub guess_mode: GuessMode,
+}
+
+impl UserPreferences {
+    /// What a user gets until they save their own preferences, the game as it has always been played
+    pub fn defaults(user_id: i32) -> Self {
+        Self {
+            user_id,
+            distance_unit: DistanceUnit::default(),
+            map_type: MapType::Roadmap,
+            difficulty: Difficulty::default(),
+            guess_mode: GuessMode::default(),
+        }
+    }
+}
+
+impl IntoResponse for UserPreferences {
+    fn into_response(self) -> Response {
+        Json(self).into_response()
+    }
+}
+
+/// The settings page as it is posted, every field is checked by [validate](PreferencesForm::validate)
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct PreferencesForm {
+    pub distance_unit: String,
+    pub map_type: String,
+    pub difficulty: String,
+    pub guess_mode: String,
+}
+
+impl PreferencesForm {
+    /// Turns the form into the preferences of the user
+    ///
+    /// # Returns:
+    /// [AppResult]<[UserPreferences]> - [AppError::InvalidPreferences] when a field isn't one of its options
+    pub fn validate(&self, user_id: i32) -> AppResult<UserPreferences> {
+        Ok(UserPreferences {
+            user_id,
+            distance_unit: self.distance_unit.parse().map_err(AppError::InvalidPreferences)?,
+            map_type: self.map_type.parse().map_err(AppError::InvalidPreferences)?,
+            difficulty: self.difficulty.parse().map_err(AppError::InvalidPreferences)?,
+            guess_mode: self.guess_mode.parse().map_err(AppError::InvalidPreferences)?,
+        })
+    }
+}
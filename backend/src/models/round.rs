@@ -0,0 +1,253 @@
+use chrono::{DateTime, Duration, Utc};
+use serde_derive::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::catalog::CityCatalog;
+use crate::error::AppError;
+use crate::models::city::City;
+use crate::models::custom_map::MapLocation;
+use crate::models::game::Game;
+use crate::models::hint::HintKind;
+use crate::scoring::ScoringKind;
+use crate::AppResult;
+
+/// How long a user has to submit a guess once a round has been issued
+const ROUND_DURATION_MINUTES: i64 = 30;
+
+/// New rounds keep at least this far away from the places earlier rounds of the same game were at, in kilometers
+pub const EXCLUSION_RADIUS_KM: f32 = 100.0;
+
+/// A single guessing round, issued to one user when they load the game page.
+///
+/// The answer (`city_id`, or the `target` of a custom location) only ever lives on the server, the browser is given nothing but the
+/// opaque round `id`, which it sends back along with its guess.
+#[derive(Serialize, Deserialize, sqlx::FromRow, Debug, Clone)]
+pub struct Round {
+    /// Opaque id handed to the browser
+    pub id: Uuid,
+
+    /// The game this round is part of
+    pub game_id: Uuid,
+
+    /// Position of this round in its game, starting at 1
+    pub round_number: i32,
+
+    /// The user this round was issued to, nobody else may answer it
+    pub user_id: i32,
+
+    /// [Id](crate::models::city::City::id) of the city the user has to guess, never serialized.
+    /// For the bundled cities the id is the same as the rank, imported cities have ids of their own.
+    /// Rounds played on a custom map location that isn't a catalog city, or on a random spot on land, have 0 here and a target instead
+    #[serde(skip_serializing)]
+    pub city_id: i32,
+
+    /// The place to guess when it isn't a catalog city, copied from the [MapLocation] the round was issued for
+    /// or picked from the land
+    #[serde(skip_serializing)]
+    pub target_name: Option<String>,
+    #[serde(skip_serializing)]
+    pub target_region: Option<String>,
+    #[serde(skip_serializing)]
+    pub target_lat: Option<f32>,
+    #[serde(skip_serializing)]
+    pub target_lng: Option<f32>,
+
+    pub created_at: DateTime<Utc>,
+
+    /// After this point the round can no longer be answered
+    pub expires_at: DateTime<Utc>,
+
+    /// Set once a guess has been scored for this round
+    pub answered_at: Option<DateTime<Utc>>,
+
+    /// The guess made for this round, set together with `answered_at`
+    pub guess_lat: Option<f32>,
+    pub guess_lng: Option<f32>,
+
+    /// Distance in kilometers between the guess and the city, see [distance_km](crate::geodesy::distance_km)
+    pub distance: Option<f32>,
+
+    pub score: Option<i32>,
+
+    /// Code of the country or state the guess fell in, only set for games [guessing regions](crate::models::guess_mode::GuessMode::is_region)
+    pub guessed_region: Option<String>,
+
+    /// The strategy the score is computed with, copied from the game when the round is issued.
+    /// Only rounds scored before strategies were recorded have none.
+    pub scoring: Option<ScoringKind>,
+
+    /// Hints taken for this round, in the order they were taken
+    pub hints: Vec<HintKind>,
+
+    /// Percent of the best score the hints cost, added up when each hint is taken
+    /// so changing the [penalties](crate::models::hint::HintPenalties) later doesn't change it
+    pub hint_penalty: i32,
+}
+
+impl Round {
+    /// Creates a brand new, unanswered round of a game
+    ///
+    /// # Arguments:
+    /// * game: &[Game] - The game this round belongs to, the round is issued to the owner of the game
+    /// * round_number: i32 - Position of the round in the game, starting at 1
+    /// * city_id: i32 - Id of the city the user has to guess
+    ///
+    /// # Returns:
+    /// [Self](Round)
+    pub fn new(game: &Game, round_number: i32, city_id: i32) -> Self {
+        let created_at = Utc::now();
+
+        Self {
+            id: Uuid::new_v4(),
+            game_id: game.id,
+            round_number,
+            user_id: game.user_id,
+            city_id,
+            created_at,
+            expires_at: created_at + Duration::minutes(ROUND_DURATION_MINUTES),
+            answered_at: None,
+            guess_lat: None,
+            guess_lng: None,
+            distance: None,
+            score: None,
+            guessed_region: None,
+            scoring: Some(game.scoring),
+            hints: Vec::new(),
+            hint_penalty: 0,
+            target_name: None,
+            target_region: None,
+            target_lat: None,
+            target_lng: None,
+        }
+    }
+
+    /// Creates a round of a game played on a custom map. Locations that are catalog cities are played
+    /// like any other city, the rest are copied onto the round.
+    pub fn at_location(game: &Game, round_number: i32, location: &MapLocation) -> Self {
+        match location.city_id {
+            Some(city_id) => Self::new(game, round_number, city_id),
+            None => Self::at_point(
+                game,
+                round_number,
+                location.name.clone(),
+                location.region.clone(),
+                location.latitude,
+                location.longitude,
+            ),
+        }
+    }
+
+    /// Creates a round played at any point rather than a catalog city, like a random spot on land
+    pub fn at_point(
+        game: &Game,
+        round_number: i32,
+        name: String,
+        region: Option<String>,
+        latitude: f32,
+        longitude: f32,
+    ) -> Self {
+        Self {
+            target_name: Some(name),
+            target_region: region,
+            target_lat: Some(latitude),
+            target_lng: Some(longitude),
+            ..Self::new(game, round_number, 0)
+        }
+    }
+
+    /// The place the user has to guess, from the round itself for custom locations, otherwise from the catalog
+    ///
+    /// # Returns:
+    /// * [AppResult]<[City]> - [AppError::CityNotFound] when the city is no longer in the catalog
+    pub fn target(&self, catalog: &CityCatalog) -> AppResult<City> {
+        match (self.target_lat, self.target_lng) {
+            (Some(latitude), Some(longitude)) => Ok(City {
+                city: self.target_name.clone().unwrap_or_default(),
+                state: self.target_region.clone().unwrap_or_default(),
+                latitude,
+                longitude,
+                ..City::default()
+            }),
+            _ => catalog.get(self.city_id).cloned(),
+        }
+    }
+
+    pub fn has_hint(&self, kind: HintKind) -> bool {
+        self.hints.contains(&kind)
+    }
+
+    /// Points the hints taken cost, out of the best score of the round. Never more than the best score
+    pub fn hint_penalty_points(&self, max_score: i32) -> i32 {
+        (max_score as f32 * self.hint_penalty.min(100) as f32 / 100.0).round() as i32
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at < Utc::now()
+    }
+
+    /// Makes sure the round was issued to the given user, other users rounds are reported as not found
+    ///
+    /// # Returns:
+    /// * [AppResult]<()>
+    pub fn check_owner(&self, user_id: i32) -> AppResult<()> {
+        if self.user_id != user_id {
+            return Err(AppError::RoundNotFound);
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the given user is allowed to submit a guess for this round right now
+    ///
+    /// A round belonging to somebody else is reported as not found, so round ids can't be probed.
+    ///
+    /// # Returns:
+    /// * [AppResult]<()>
+    pub fn check_guessable(&self, user_id: i32) -> AppResult<()> {
+        self.check_owner(user_id)?;
+
+        if self.answered_at.is_some() {
+            return Err(AppError::RoundAlreadyAnswered);
+        }
+
+        if self.is_expired() {
+            return Err(AppError::RoundExpired);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::game::GameMode;
+
+    fn with_penalty(hint_penalty: i32) -> Round {
+        Round {
+            hint_penalty,
+            ..Round::new(&Game::new(1, GameMode::Classic), 1, 1)
+        }
+    }
+
+    #[test]
+    fn hint_penalty_is_a_share_of_the_best_score() {
+        assert_eq!(with_penalty(0).hint_penalty_points(5000), 0);
+        assert_eq!(with_penalty(25).hint_penalty_points(5000), 1250);
+        assert_eq!(with_penalty(45).hint_penalty_points(5000), 2250);
+    }
+
+    #[test]
+    fn hint_penalty_rounds_to_the_nearest_point() {
+        assert_eq!(with_penalty(15).hint_penalty_points(10), 2);
+        assert_eq!(with_penalty(14).hint_penalty_points(10), 1);
+        assert_eq!(with_penalty(33).hint_penalty_points(100), 33);
+    }
+
+    #[test]
+    fn hint_penalty_never_takes_more_than_the_best_score() {
+        assert_eq!(with_penalty(100).hint_penalty_points(5000), 5000);
+        assert_eq!(with_penalty(170).hint_penalty_points(5000), 5000);
+        assert_eq!(with_penalty(170).hint_penalty_points(100), 100);
+    }
+}
@@ -0,0 +1,379 @@
+use std::collections::{BTreeMap, HashMap};
+
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::catalog::CityCatalog;
+use crate::models::guess::Guess;
+use crate::models::preferences::DistanceUnit;
+use crate::models::user::LeaderBoardRow;
+
+/// Number of buckets the score histogram is split into, each covering an equal share of the max score
+const HISTOGRAM_BUCKETS: usize = 10;
+
+/// What guesses are counted under when the city they were made for is no longer in the catalog
+const UNKNOWN_STATE: &str = "Unknown";
+
+/// What guesses are counted under when the place they were made for, like a custom map location or a spot on land, has no region
+const NO_REGION: &str = "No region";
+
+/// A guess worth pointing out on the profile page, like the best or worst one
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NotableGuess {
+    pub city: String,
+    pub state: String,
+    pub distance: f32,
+    pub score: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+/// How many guesses scored within a range of percentages of the max score
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HistogramBucket {
+    /// Lowest percentage of the max score in this bucket, inclusive
+    pub from_percent: u32,
+    /// Highest percentage of the max score in this bucket, exclusive except for the last bucket
+    pub to_percent: u32,
+    pub count: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StateCount {
+    pub state: String,
+    pub count: usize,
+}
+
+/// How a user did on one day
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TrendPoint {
+    pub date: NaiveDate,
+    pub num_guesses: usize,
+    pub average_distance: f32,
+    /// Average score as a percentage of the max score, so days played with different scoring compare
+    pub average_score_percent: f32,
+}
+
+/// Everything we know about how a user has been doing, computed from their guess history
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UserStats {
+    pub user_id: i32,
+
+    /// What every distance in the stats is in, see [in_unit](UserStats::in_unit)
+    pub distance_unit: DistanceUnit,
+
+    /// The users row on the leaderboard, if they have finished a game yet
+    pub rank: Option<LeaderBoardRow>,
+
+    pub num_guesses: usize,
+    pub average_distance: Option<f32>,
+    pub median_distance: Option<f32>,
+    pub best_guess: Option<NotableGuess>,
+    pub worst_guess: Option<NotableGuess>,
+    pub score_histogram: Vec<HistogramBucket>,
+
+    /// Number of guesses per state of the city that had to be guessed, most guessed state first.
+    /// Places that aren't catalog cities are counted under their region
+    pub guesses_per_state: Vec<StateCount>,
+
+    /// One point per day the user played, oldest first
+    pub trend: Vec<TrendPoint>,
+}
+
+impl IntoResponse for UserStats {
+    fn into_response(self) -> Response {
+        Json(self).into_response()
+    }
+}
+
+impl UserStats {
+    /// Computes a users stats from their guess history
+    ///
+    /// # Arguments:
+    /// * user_id: i32 - The user the guesses belong to
+    /// * rank: [Option]<[LeaderBoardRow]> - The users leaderboard row, if they have one
+    /// * guesses: &[[Guess]] - Every guess the user has made
+    /// * catalog: &[CityCatalog] - Used to find the names and states of the cities the guesses were made for,
+    ///   places that aren't catalog cities are stored on the guess
+    ///
+    /// # Returns:
+    /// [Self](UserStats)
+    pub fn new(user_id: i32, rank: Option<LeaderBoardRow>, guesses: &[Guess], catalog: &CityCatalog) -> Self {
+        let notable = |guess: &Guess| {
+            let (city, state) = target_place(guess, catalog);
+            NotableGuess {
+                city,
+                state,
+                distance: guess.distance,
+                score: guess.score,
+                created_at: guess.created_at,
+            }
+        };
+
+        let mut distances: Vec<f32> = guesses.iter().map(|g| g.distance).collect();
+        distances.sort_by(|a, b| a.total_cmp(b));
+
+        let best_guess = guesses
+            .iter()
+            .min_by(|a, b| a.distance.total_cmp(&b.distance))
+            .map(notable);
+        let worst_guess = guesses
+            .iter()
+            .max_by(|a, b| a.distance.total_cmp(&b.distance))
+            .map(notable);
+
+        let mut per_state: HashMap<String, usize> = HashMap::new();
+        for guess in guesses {
+            let (_, state) = target_place(guess, catalog);
+            *per_state.entry(state).or_default() += 1;
+        }
+        let mut guesses_per_state: Vec<StateCount> = per_state
+            .into_iter()
+            .map(|(state, count)| StateCount { state, count })
+            .collect();
+        guesses_per_state.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.state.cmp(&b.state)));
+
+        Self {
+            user_id,
+            distance_unit: DistanceUnit::Kilometers,
+            rank,
+            num_guesses: guesses.len(),
+            average_distance: average(&distances),
+            median_distance: median(&distances),
+            best_guess,
+            worst_guess,
+            score_histogram: score_histogram(guesses),
+            guesses_per_state,
+            trend: trend(guesses),
+        }
+    }
+
+    /// Converts every distance in the stats to the given unit, [new](UserStats::new) computes them in kilometers
+    pub fn in_unit(mut self, distance_unit: DistanceUnit) -> Self {
+        if self.distance_unit == distance_unit {
+            return self;
+        }
+
+        self.average_distance = self.average_distance.map(|distance| distance_unit.convert_km(distance));
+        self.median_distance = self.median_distance.map(|distance| distance_unit.convert_km(distance));
+        for guess in self.best_guess.iter_mut().chain(self.worst_guess.iter_mut()) {
+            guess.distance = distance_unit.convert_km(guess.distance);
+        }
+        for point in &mut self.trend {
+            point.average_distance = distance_unit.convert_km(point.average_distance);
+        }
+        self.distance_unit = distance_unit;
+
+        self
+    }
+}
+
+/// Name and state of the place a guess was made for. Custom map locations and spots on land were copied onto the guess,
+/// catalog cities are looked up in the catalog
+fn target_place(guess: &Guess, catalog: &CityCatalog) -> (String, String) {
+    if let Some(name) = &guess.target_name {
+        let region = guess.target_region.clone().unwrap_or_else(|| NO_REGION.to_string());
+        return (name.clone(), region);
+    }
+
+    match catalog.find(guess.city_id) {
+        Some(city) => (city.city.clone(), city.state.clone()),
+        None => (String::new(), UNKNOWN_STATE.to_string()),
+    }
+}
+
+/// How much of the best possible score a guess got, between 0 and 1.
+/// The best score is that of the scoring strategy with the [multiplier](crate::models::difficulty::Difficulty::apply) of the difficulty on top
+fn score_fraction(guess: &Guess) -> f32 {
+    let max_score = guess.difficulty.apply(guess.scoring.unwrap_or_default().strategy().max_score());
+    if max_score <= 0 {
+        return 0.0;
+    }
+
+    (guess.score as f32 / max_score as f32).clamp(0.0, 1.0)
+}
+
+fn average(values: &[f32]) -> Option<f32> {
+    if values.is_empty() {
+        return None;
+    }
+
+    Some(values.iter().sum::<f32>() / values.len() as f32)
+}
+
+/// Median of already sorted values
+fn median(sorted: &[f32]) -> Option<f32> {
+    if sorted.is_empty() {
+        return None;
+    }
+
+    let middle = sorted.len() / 2;
+    match sorted.len() % 2 {
+        0 => Some((sorted[middle - 1] + sorted[middle]) / 2.0),
+        _ => Some(sorted[middle]),
+    }
+}
+
+fn score_histogram(guesses: &[Guess]) -> Vec<HistogramBucket> {
+    let bucket_size = 100 / HISTOGRAM_BUCKETS as u32;
+    let mut buckets: Vec<HistogramBucket> = (0..HISTOGRAM_BUCKETS as u32)
+        .map(|i| HistogramBucket {
+            from_percent: i * bucket_size,
+            to_percent: (i + 1) * bucket_size,
+            count: 0,
+        })
+        .collect();
+
+    for guess in guesses {
+        // A perfect score goes into the last bucket instead of one past the end
+        let index = ((score_fraction(guess) * HISTOGRAM_BUCKETS as f32) as usize).min(HISTOGRAM_BUCKETS - 1);
+        buckets[index].count += 1;
+    }
+
+    buckets
+}
+
+fn trend(guesses: &[Guess]) -> Vec<TrendPoint> {
+    let mut per_day: BTreeMap<NaiveDate, Vec<&Guess>> = BTreeMap::new();
+    for guess in guesses {
+        per_day.entry(guess.created_at.date_naive()).or_default().push(guess);
+    }
+
+    per_day
+        .into_iter()
+        .map(|(date, day_guesses)| {
+            let count = day_guesses.len() as f32;
+            TrendPoint {
+                date,
+                num_guesses: day_guesses.len(),
+                average_distance: day_guesses.iter().map(|g| g.distance).sum::<f32>() / count,
+                average_score_percent: day_guesses.iter().map(|g| score_fraction(g)).sum::<f32>() / count * 100.0,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::city::City;
+    use crate::models::difficulty::Difficulty;
+    use crate::models::game::GameMode;
+    use crate::scoring::ScoringKind;
+
+    fn catalog() -> CityCatalog {
+        CityCatalog::from_cities(vec![City {
+            id: 1,
+            city: "Boise City".to_string(),
+            state: "Idaho".to_string(),
+            rank: 1,
+            ..City::default()
+        }])
+    }
+
+    fn guess(city_id: i32, distance: f32) -> Guess {
+        Guess {
+            id: 1,
+            user_id: 1,
+            round_id: None,
+            city_id,
+            target_name: None,
+            target_region: None,
+            target_lat: None,
+            target_lng: None,
+            guess_lat: 0.0,
+            guess_lng: 0.0,
+            distance,
+            score: 50,
+            scoring: None,
+            difficulty: Difficulty::Medium,
+            game_mode: GameMode::Classic,
+            created_at: Utc::now(),
+        }
+    }
+
+    /// A guess for a random spot on land, named after its coordinates like [Round::at_point](crate::models::round::Round::at_point) does
+    fn land_guess(region: Option<&str>, distance: f32) -> Guess {
+        Guess {
+            target_name: Some("39.5000, -116.9000".to_string()),
+            target_region: region.map(str::to_string),
+            target_lat: Some(39.5),
+            target_lng: Some(-116.9),
+            ..guess(0, distance)
+        }
+    }
+
+    #[test]
+    fn land_guesses_keep_their_place() {
+        let guesses = vec![guess(1, 100.0), land_guess(Some("Nevada"), 5.0), land_guess(None, 900.0)];
+        let stats = UserStats::new(1, None, &guesses, &catalog());
+
+        let best = stats.best_guess.unwrap();
+        assert_eq!((best.city.as_str(), best.state.as_str()), ("39.5000, -116.9000", "Nevada"));
+
+        let worst = stats.worst_guess.unwrap();
+        assert_eq!(worst.state, NO_REGION);
+
+        let states: Vec<(&str, usize)> = stats.guesses_per_state.iter().map(|s| (s.state.as_str(), s.count)).collect();
+        assert_eq!(states, vec![("Idaho", 1), ("Nevada", 1), (NO_REGION, 1)]);
+    }
+
+    #[test]
+    fn custom_map_guesses_keep_their_place() {
+        let lighthouse = Guess {
+            target_name: Some("Cape Disappointment".to_string()),
+            target_region: Some("Washington".to_string()),
+            target_lat: Some(46.28),
+            target_lng: Some(-124.05),
+            ..guess(0, 2.0)
+        };
+        let no_region = Guess {
+            target_name: Some("Our old house".to_string()),
+            target_lat: Some(43.6),
+            target_lng: Some(-116.2),
+            ..guess(0, 300.0)
+        };
+        // Map locations that are catalog cities are played, and stored, like any other city
+        let stats = UserStats::new(1, None, &[lighthouse, no_region, guess(1, 40.0)], &catalog());
+
+        let best = stats.best_guess.unwrap();
+        assert_eq!((best.city.as_str(), best.state.as_str()), ("Cape Disappointment", "Washington"));
+
+        let worst = stats.worst_guess.unwrap();
+        assert_eq!((worst.city.as_str(), worst.state.as_str()), ("Our old house", NO_REGION));
+
+        assert!(stats.guesses_per_state.iter().all(|s| s.state != UNKNOWN_STATE));
+    }
+
+    #[test]
+    fn cities_missing_from_the_catalog_are_unknown() {
+        let stats = UserStats::new(1, None, &[guess(42, 10.0)], &catalog());
+
+        assert_eq!(stats.guesses_per_state[0].state, UNKNOWN_STATE);
+        assert_eq!(stats.best_guess.unwrap().city, "");
+    }
+
+    #[test]
+    fn harder_guesses_are_measured_against_their_multiplied_best_score() {
+        let max_score = ScoringKind::default().strategy().max_score();
+        let scored = |difficulty: Difficulty, score: i32| Guess {
+            difficulty,
+            score: difficulty.apply(score),
+            scoring: Some(ScoringKind::default()),
+            ..guess(1, 10.0)
+        };
+
+        assert_eq!(score_fraction(&scored(Difficulty::Hard, max_score)), 1.0);
+        assert_eq!(score_fraction(&scored(Difficulty::Hard, max_score / 2)), 0.5);
+        assert_eq!(score_fraction(&scored(Difficulty::Expert, max_score / 2)), 0.5);
+        assert_eq!(score_fraction(&scored(Difficulty::Easy, max_score / 2)), 0.5);
+
+        // A perfect expert guess is worth twice the max score, but no more than a perfect medium one in the trend
+        let guesses = vec![scored(Difficulty::Expert, max_score), scored(Difficulty::Hard, max_score / 2)];
+        let stats = UserStats::new(1, None, &guesses, &catalog());
+        assert_eq!(stats.trend[0].average_score_percent, 75.0);
+        assert_eq!(stats.score_histogram[5].count, 1);
+        assert_eq!(stats.score_histogram[HISTOGRAM_BUCKETS - 1].count, 1);
+    }
+}
@@ -0,0 +1,173 @@
+use std::path::Path;
+
+use rand::Rng;
+use serde_json::Value;
+use tracing::info;
+
+use crate::error::AppError;
+use crate::regions::polygon::{MultiPolygon, Polygon};
+use crate::AppResult;
+
+/// Kilometers per degree of latitude
+const KM_PER_DEGREE: f32 = 111.32;
+
+/// Points near people are picked within this distance of a city
+pub const NEAR_CITY_RADIUS_KM: f32 = 50.0;
+
+/// How often a random point is drawn before giving up on finding one on land
+const MAX_ATTEMPTS: usize = 1000;
+
+/// Every piece of land random locations can be picked from, read from a GeoJSON file of land polygons.
+///
+/// Points are picked uniformly over the area of the land, not over latitudes and longitudes,
+/// so Greenland doesn't come up more often than it deserves.
+#[derive(Debug, Default)]
+pub struct Land {
+    polygons: Vec<Polygon>,
+
+    /// Running total of the polygon areas in square kilometers, a polygon is picked by where a random area falls in it
+    cumulative_area: Vec<f64>,
+}
+
+impl Land {
+    /// Reads the land from a GeoJSON file, see [from_geojson](Land::from_geojson)
+    ///
+    /// # Arguments:
+    /// * path: &[Path] - The GeoJSON file to read
+    ///
+    /// # Returns:
+    /// [AppResult]<[Self](Land)>
+    pub fn load(path: &Path) -> AppResult<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        let land = Self::from_geojson(&serde_json::from_str(&contents)?)?;
+        info!(
+            "Loaded {} land polygons, {:.0} km² in total, from {}",
+            land.len(),
+            land.area_km2(),
+            path.display()
+        );
+
+        Ok(land)
+    }
+
+    /// Builds the land out of a GeoJSON FeatureCollection, every Polygon and MultiPolygon in it is land.
+    /// Features without a geometry, or with points and lines, are left out.
+    ///
+    /// # Returns:
+    /// [AppResult]<[Self](Land)> - [AppError::InvalidBoundaries] saying which feature is wrong
+    pub fn from_geojson(collection: &Value) -> AppResult<Self> {
+        let features = collection
+            .get("features")
+            .and_then(Value::as_array)
+            .ok_or_else(|| AppError::InvalidBoundaries("Land has to be a GeoJSON FeatureCollection".to_string()))?;
+
+        let mut polygons = Vec::new();
+        for (number, feature) in features.iter().enumerate() {
+            let Some(geometry) = feature.get("geometry").filter(|geometry| {
+                matches!(geometry.get("type").and_then(Value::as_str), Some("Polygon" | "MultiPolygon"))
+            }) else {
+                continue;
+            };
+
+            let shape = MultiPolygon::from_geojson(geometry)
+                .map_err(|message| AppError::InvalidBoundaries(format!("Feature {}: {}", number + 1, message)))?;
+            polygons.extend(shape.polygons().iter().cloned());
+        }
+
+        Ok(Self::from_polygons(polygons))
+    }
+
+    pub fn from_polygons(polygons: Vec<Polygon>) -> Self {
+        let cumulative_area = polygons
+            .iter()
+            .scan(0.0, |total, polygon| {
+                *total += polygon.area_km2();
+                Some(*total)
+            })
+            .collect();
+
+        Self {
+            polygons,
+            cumulative_area,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.polygons.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.polygons.is_empty()
+    }
+
+    /// Area of all the land in square kilometers
+    pub fn area_km2(&self) -> f64 {
+        self.cumulative_area.last().copied().unwrap_or_default()
+    }
+
+    /// Whether the point is on land
+    pub fn contains(&self, latitude: f32, longitude: f32) -> bool {
+        self.polygons
+            .iter()
+            .any(|polygon| polygon.contains(latitude, longitude))
+    }
+
+    /// A point picked uniformly over all of the land.
+    /// A polygon is picked by its area first, then points are drawn from its bounding box until one is inside it.
+    ///
+    /// # Returns:
+    /// [Option]<(f32, f32)> - Latitude and longitude of the point, none when there is no land
+    pub fn random_point<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<(f32, f32)> {
+        let total = self.area_km2();
+        if total <= 0.0 {
+            return None;
+        }
+
+        let target = rng.gen_range(0.0..total);
+        let index = self
+            .cumulative_area
+            .partition_point(|&area| area <= target)
+            .min(self.polygons.len() - 1);
+        let polygon = &self.polygons[index];
+        let bbox = polygon.bbox();
+
+        // Drawing the sine of the latitude instead of the latitude keeps the points even over the area,
+        // rows of latitude get narrower towards the poles
+        let (low, high) = (bbox.south.to_radians().sin(), bbox.north.to_radians().sin());
+
+        (0..MAX_ATTEMPTS).find_map(|_| {
+            let latitude = rng.gen_range(low..=high).asin().to_degrees();
+            let longitude = rng.gen_range(bbox.west..=bbox.east);
+
+            polygon
+                .contains(latitude, longitude)
+                .then_some((latitude, longitude))
+        })
+    }
+
+    /// A point on land picked uniformly within `radius_km` of the given point
+    ///
+    /// # Returns:
+    /// [Option]<(f32, f32)> - Latitude and longitude of the point, none when no land was found around the point
+    pub fn random_point_near<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        latitude: f32,
+        longitude: f32,
+        radius_km: f32,
+    ) -> Option<(f32, f32)> {
+        let km_per_degree_longitude = KM_PER_DEGREE * latitude.to_radians().cos().max(0.01);
+
+        (0..MAX_ATTEMPTS).find_map(|_| {
+            // The square root spreads the points evenly over the disk instead of bunching them in the middle
+            let distance = radius_km * rng.gen::<f32>().sqrt();
+            let bearing = rng.gen_range(0.0..std::f32::consts::TAU);
+
+            let point_lat = (latitude + distance * bearing.cos() / KM_PER_DEGREE).clamp(-90.0, 90.0);
+            let point_lng = longitude + distance * bearing.sin() / km_per_degree_longitude;
+            let point_lng = (point_lng + 540.0).rem_euclid(360.0) - 180.0;
+
+            self.contains(point_lat, point_lng).then_some((point_lat, point_lng))
+        })
+    }
+}
@@ -0,0 +1,558 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+
+use serde_derive::Serialize;
+use serde_json::Value;
+use tracing::{info, warn};
+
+use crate::error::AppError;
+use crate::models::city::City;
+use crate::models::guess_mode::GuessMode;
+use crate::AppResult;
+
+pub mod land;
+pub mod polygon;
+
+use land::Land;
+use polygon::MultiPolygon;
+
+/// The boundaries shared by every request, see [BoundarySet]
+pub type Boundaries = Arc<BoundarySet>;
+
+/// Regions whose borders come this close are neighbours, covers the gaps simplified shapes leave between each other
+const NEIGHBOUR_TOLERANCE_KM: f32 = 1.0;
+
+/// A click this close to a region, but outside every region, still counts as a guess of that region.
+/// Simplified coastlines cut off a bit of land here and there, and nobody should lose a round for clicking the beach.
+const SNAP_DISTANCE_KM: f32 = 25.0;
+
+/// Fewest regions the cities of a game in a region mode have to be spread over, guessing the only region there is would be no game
+pub const MIN_REGIONS: usize = 2;
+
+/// One country or state
+#[derive(Serialize, Debug, Clone)]
+pub struct Region {
+    /// Short code, the postal code of a state or the ISO 3166-1 alpha-2 code of a country, always upper case
+    pub code: String,
+
+    pub name: String,
+
+    #[serde(skip)]
+    pub shape: MultiPolygon,
+
+    /// Codes of the regions sharing a border with this one
+    pub neighbours: Vec<String>,
+}
+
+/// Every region of one kind, countries or states, looked up by position, code or name
+#[derive(Debug, Default)]
+pub struct RegionIndex {
+    regions: Vec<Region>,
+
+    /// Position in `regions` by code
+    by_code: HashMap<String, usize>,
+}
+
+impl RegionIndex {
+    /// Reads the regions from a GeoJSON file, see [from_geojson](RegionIndex::from_geojson)
+    ///
+    /// # Arguments:
+    /// * path: &[Path] - The GeoJSON file to read
+    ///
+    /// # Returns:
+    /// [AppResult]<[Self](RegionIndex)>
+    pub fn load(path: &Path) -> AppResult<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        let index = Self::from_geojson(&serde_json::from_str(&contents)?)?;
+        info!("Loaded {} regions from {}", index.len(), path.display());
+
+        Ok(index)
+    }
+
+    /// Builds the index out of a GeoJSON FeatureCollection with a Polygon or MultiPolygon for every region.
+    /// The code is read from the `code` property, or the ones Census and Natural Earth files use, the name
+    /// from `name`. Neighbours are worked out from the shapes, unless the feature lists them in a `neighbours` property.
+    ///
+    /// # Returns:
+    /// [AppResult]<[Self](RegionIndex)> - [AppError::InvalidBoundaries] saying which feature is wrong
+    pub fn from_geojson(collection: &Value) -> AppResult<Self> {
+        let features = collection
+            .get("features")
+            .and_then(Value::as_array)
+            .ok_or_else(|| AppError::InvalidBoundaries("Boundaries have to be a GeoJSON FeatureCollection".to_string()))?;
+
+        let mut regions = Vec::with_capacity(features.len());
+        let mut listed_neighbours = Vec::with_capacity(features.len());
+
+        for (number, feature) in features.iter().enumerate() {
+            let invalid = |message: String| AppError::InvalidBoundaries(format!("Feature {}: {}", number + 1, message));
+            let property = |names: &[&str]| {
+                names.iter().find_map(|name| {
+                    feature
+                        .pointer(&format!("/properties/{}", name))
+                        .and_then(Value::as_str)
+                        .map(str::trim)
+                        .filter(|value| !value.is_empty())
+                })
+            };
+
+            let code = property(&["code", "STUSPS", "ISO_A2", "iso_a2", "postal"])
+                .ok_or_else(|| invalid("The feature has no code".to_string()))?
+                .to_uppercase();
+            let name = property(&["name", "NAME", "ADMIN"]).unwrap_or(&code).to_string();
+            let geometry = feature
+                .get("geometry")
+                .ok_or_else(|| invalid("The feature has no geometry".to_string()))?;
+            let shape = MultiPolygon::from_geojson(geometry).map_err(invalid)?;
+
+            listed_neighbours.push(feature.pointer("/properties/neighbours").and_then(Value::as_array).map(|codes| {
+                codes
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .map(str::to_uppercase)
+                    .collect::<Vec<_>>()
+            }));
+            regions.push(Region {
+                code,
+                name,
+                shape,
+                neighbours: Vec::new(),
+            });
+        }
+
+        for index in 0..regions.len() {
+            let neighbours = match listed_neighbours[index].take() {
+                Some(codes) => codes,
+                None => regions
+                    .iter()
+                    .enumerate()
+                    .filter(|&(other, region)| {
+                        other != index && region.shape.touches(&regions[index].shape, NEIGHBOUR_TOLERANCE_KM)
+                    })
+                    .map(|(_, region)| region.code.clone())
+                    .collect(),
+            };
+            regions[index].neighbours = neighbours;
+        }
+
+        let by_code = regions
+            .iter()
+            .enumerate()
+            .map(|(index, region)| (region.code.clone(), index))
+            .collect();
+
+        Ok(Self { regions, by_code })
+    }
+
+    pub fn len(&self) -> usize {
+        self.regions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    /// Every region, in the order they were loaded
+    pub fn all(&self) -> &[Region] {
+        &self.regions
+    }
+
+    /// The region the point is in, if any
+    pub fn locate(&self, latitude: f32, longitude: f32) -> Option<&Region> {
+        self.regions
+            .iter()
+            .find(|region| region.shape.contains(latitude, longitude))
+    }
+
+    /// The region closest to the point, if it is at most `max_km` away
+    pub fn nearest(&self, latitude: f32, longitude: f32, max_km: f32) -> Option<&Region> {
+        self.regions
+            .iter()
+            .map(|region| (region, region.shape.distance_km(latitude, longitude)))
+            .filter(|&(_, distance)| distance <= max_km)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(region, _)| region)
+    }
+
+    /// Looks a region up by its code or its name, ignoring case
+    pub fn find(&self, code_or_name: &str) -> Option<&Region> {
+        let text = code_or_name.trim();
+
+        self.by_code
+            .get(&text.to_uppercase())
+            .map(|&index| &self.regions[index])
+            .or_else(|| {
+                self.regions
+                    .iter()
+                    .find(|region| region.name.eq_ignore_ascii_case(text))
+            })
+    }
+
+    /// Whether the two regions share a border
+    pub fn are_neighbours(&self, code: &str, other: &str) -> bool {
+        self.by_code
+            .get(code)
+            .is_some_and(|&index| self.regions[index].neighbours.iter().any(|neighbour| neighbour == other))
+    }
+
+    /// The region a click counts as, the one it is in or one just off its coast
+    fn guessed(&self, latitude: f32, longitude: f32) -> Option<&Region> {
+        self.locate(latitude, longitude)
+            .or_else(|| self.nearest(latitude, longitude, SNAP_DISTANCE_KM))
+    }
+}
+
+/// The regions of one layer the way cities name them, the state names for [GuessMode::State] and the country codes
+/// for [GuessMode::Country]. Games in a region mode only pick cities in one of them, a city the boundaries don't know
+/// could never be guessed right, see [Game::city_filter](crate::models::game::Game::city_filter)
+#[derive(Debug, Clone, PartialEq)]
+pub struct KnownRegions {
+    mode: GuessMode,
+
+    /// Codes and names of the regions, upper case
+    labels: HashSet<String>,
+}
+
+impl KnownRegions {
+    /// What the city calls its region in this mode, upper case
+    pub fn label(&self, city: &City) -> String {
+        match self.mode {
+            GuessMode::State => city.state.to_uppercase(),
+            _ => city.country_code.to_uppercase(),
+        }
+    }
+
+    /// Whether the city is in one of the regions
+    pub fn contains(&self, city: &City) -> bool {
+        self.labels.contains(&self.label(city))
+    }
+}
+
+/// How a region guess turned out
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Correct,
+
+    /// The guessed region borders the right one
+    Neighbour,
+
+    Wrong,
+}
+
+/// The result of judging a guess in a [region mode](GuessMode::is_region)
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RegionGuess {
+    /// Code of the region the guess fell in, none when it fell outside every region
+    pub guessed_code: Option<String>,
+    pub guessed_name: Option<String>,
+
+    /// Name of the region the city is in
+    pub target_name: String,
+
+    pub verdict: Verdict,
+
+    /// Part of the full score the guess is worth, between 0 and 1
+    pub credit: f32,
+}
+
+impl RegionGuess {
+    /// The score of the guess, `max_score` for the right region and a part of it for a neighbour
+    pub fn score(&self, max_score: i32) -> i32 {
+        (max_score as f32 * self.credit).round() as i32
+    }
+}
+
+/// The country and state boundaries guesses are judged by when a game isn't played by distance,
+/// and the land random locations are picked from, read once at startup.
+///
+/// The bundled shapes in `resources/boundaries` are simplified by hand and only hold the US, Canada and Mexico as countries,
+/// the land leaves out small islands and Antarctica. Any GeoJSON FeatureCollection of polygons can be used instead,
+/// like the Natural Earth admin 0 countries and land polygons or the Census cartographic state boundaries.
+/// Games in a region mode only pick places inside the regions of their layer, see [KnownRegions].
+#[derive(Debug, Default)]
+pub struct BoundarySet {
+    pub countries: RegionIndex,
+    pub states: RegionIndex,
+    pub land: Land,
+
+    /// Part of the full score a guess in a neighbouring region is worth
+    pub neighbour_credit: f32,
+}
+
+impl BoundarySet {
+    /// Reads the boundaries from the files set in the .env file.
+    /// A file that is missing or broken is logged and leaves its layer empty, games in that mode can't be started then.
+    ///
+    /// # .env variables
+    /// * COUNTRY_BOUNDARY_FILE (optional) - GeoJSON file of the countries, defaults to `resources/boundaries/countries.geojson`
+    /// * STATE_BOUNDARY_FILE (optional) - GeoJSON file of the US states, defaults to `resources/boundaries/us_states.geojson`
+    /// * LAND_FILE (optional) - GeoJSON file of the land, defaults to `resources/boundaries/land.geojson`
+    /// * NEIGHBOUR_CREDIT (optional) - Part of the score a neighbouring region is worth, between 0 and 1, defaults to 0.5
+    pub fn from_env() -> Self {
+        let layer = |variable: &str, default: &str| {
+            let file = std::env::var(variable).unwrap_or_else(|_| default.to_string());
+
+            RegionIndex::load(Path::new(&file)).unwrap_or_else(|err| {
+                warn!("Could not load the boundaries from {}: {:?}", file, err);
+                RegionIndex::default()
+            })
+        };
+
+        let land_file = std::env::var("LAND_FILE").unwrap_or_else(|_| "resources/boundaries/land.geojson".to_string());
+        let land = Land::load(Path::new(&land_file)).unwrap_or_else(|err| {
+            warn!("Could not load the land from {}: {:?}", land_file, err);
+            Land::default()
+        });
+
+        let neighbour_credit = std::env::var("NEIGHBOUR_CREDIT")
+            .ok()
+            .and_then(|credit| credit.parse::<f32>().ok())
+            .filter(|credit| (0.0..=1.0).contains(credit))
+            .unwrap_or(0.5);
+
+        Self {
+            countries: layer("COUNTRY_BOUNDARY_FILE", "resources/boundaries/countries.geojson"),
+            states: layer("STATE_BOUNDARY_FILE", "resources/boundaries/us_states.geojson"),
+            land,
+            neighbour_credit,
+        }
+    }
+
+    /// Name of the state a point is in, or of its country outside the states, for naming random locations
+    pub fn region_name(&self, latitude: f32, longitude: f32) -> Option<String> {
+        self.states
+            .locate(latitude, longitude)
+            .or_else(|| self.countries.locate(latitude, longitude))
+            .map(|region| region.name.clone())
+    }
+
+    /// The regions guesses of the given mode are judged against, none for [GuessMode::Point]
+    pub fn layer(&self, mode: GuessMode) -> Option<&RegionIndex> {
+        match mode {
+            GuessMode::Point => None,
+            GuessMode::Country => Some(&self.countries),
+            GuessMode::State => Some(&self.states),
+        }
+    }
+
+    /// The regions of the layer guesses of the given mode are judged against, none for [GuessMode::Point]
+    pub fn known_regions(&self, mode: GuessMode) -> Option<KnownRegions> {
+        let regions = self.layer(mode)?;
+
+        Some(KnownRegions {
+            mode,
+            labels: regions
+                .all()
+                .iter()
+                .flat_map(|region| [region.code.clone(), region.name.to_uppercase()])
+                .collect(),
+        })
+    }
+
+    /// Judges a guess by the region it fell in.
+    ///
+    /// The region of the city comes from its state or country code when the boundaries know it, otherwise from
+    /// where the city is. Custom map locations outside every region are compared by the region name their owner gave them,
+    /// and can't be guessed as a neighbour.
+    ///
+    /// # Arguments:
+    /// * mode: [GuessMode] - Which boundaries to judge by
+    /// * target: &[City] - The city the user had to guess
+    /// * latitude: f32 - Latitude of the guess
+    /// * longitude: f32 - Longitude of the guess
+    ///
+    /// # Returns:
+    /// [AppResult]<[RegionGuess]> - [AppError::InvalidGuessMode] for [GuessMode::Point]
+    pub fn judge(&self, mode: GuessMode, target: &City, latitude: f32, longitude: f32) -> AppResult<RegionGuess> {
+        let regions = self
+            .layer(mode)
+            .ok_or_else(|| AppError::InvalidGuessMode("Pinpoint guesses are scored by distance".to_string()))?;
+
+        let target_label = match mode {
+            GuessMode::State => &target.state,
+            _ => &target.country_code,
+        };
+        let target_region = regions
+            .find(target_label)
+            .or_else(|| regions.guessed(target.latitude, target.longitude));
+        let guessed = regions.guessed(latitude, longitude);
+
+        let verdict = match (target_region, guessed) {
+            (_, None) => Verdict::Wrong,
+            (Some(target), Some(guessed)) if target.code == guessed.code => Verdict::Correct,
+            (Some(target), Some(guessed)) if regions.are_neighbours(&target.code, &guessed.code) => Verdict::Neighbour,
+            (Some(_), Some(_)) => Verdict::Wrong,
+            (None, Some(guessed))
+                if guessed.code.eq_ignore_ascii_case(target_label) || guessed.name.eq_ignore_ascii_case(target_label) =>
+            {
+                Verdict::Correct
+            }
+            (None, Some(_)) => Verdict::Wrong,
+        };
+
+        let credit = match verdict {
+            Verdict::Correct => 1.0,
+            Verdict::Neighbour => self.neighbour_credit,
+            Verdict::Wrong => 0.0,
+        };
+
+        Ok(RegionGuess {
+            guessed_code: guessed.map(|region| region.code.clone()),
+            guessed_name: guessed.map(|region| region.name.clone()),
+            target_name: target_region
+                .map(|region| region.name.clone())
+                .unwrap_or_else(|| target_label.clone()),
+            verdict,
+            credit,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn square(west: f32, south: f32, size: f32) -> Value {
+        json!([[[west, south], [west + size, south], [west + size, south + size], [west, south + size], [west, south]]])
+    }
+
+    /// Alpha has a hole in the middle and borders Beta on its east side, Gamma is far away from both
+    fn boundaries() -> BoundarySet {
+        let mut alpha = square(0.0, 0.0, 10.0);
+        alpha.as_array_mut().unwrap().push(square(4.0, 4.0, 2.0)[0].clone());
+
+        let states = RegionIndex::from_geojson(&json!({
+            "type": "FeatureCollection",
+            "features": [
+                {"type": "Feature", "properties": {"code": "al", "name": "Alpha"}, "geometry": {"type": "Polygon", "coordinates": alpha}},
+                {"type": "Feature", "properties": {"code": "BE", "name": "Beta"}, "geometry": {"type": "Polygon", "coordinates": square(10.0, 0.0, 10.0)}},
+                {"type": "Feature", "properties": {"code": "GA", "name": "Gamma"}, "geometry": {"type": "Polygon", "coordinates": square(50.0, 50.0, 5.0)}}
+            ]
+        }))
+        .unwrap();
+
+        BoundarySet {
+            states,
+            neighbour_credit: 0.5,
+            ..BoundarySet::default()
+        }
+    }
+
+    fn city(state: &str, latitude: f32, longitude: f32) -> City {
+        City {
+            city: "Somewhere".to_string(),
+            state: state.to_string(),
+            country_code: "US".to_string(),
+            latitude,
+            longitude,
+            ..City::default()
+        }
+    }
+
+    #[test]
+    fn regions_are_found_by_code_name_and_position() {
+        let regions = boundaries().states;
+
+        assert_eq!(regions.find("AL").unwrap().name, "Alpha");
+        assert_eq!(regions.find(" alpha ").unwrap().code, "AL");
+        assert_eq!(regions.locate(5.0, 15.0).unwrap().code, "BE");
+        assert!(regions.locate(5.0, 5.0).is_none());
+        assert_eq!(regions.nearest(-0.1, 5.0, 25.0).unwrap().code, "AL");
+        assert!(regions.nearest(-1.0, 5.0, 25.0).is_none());
+    }
+
+    #[test]
+    fn neighbours_are_worked_out_from_the_shapes() {
+        let regions = boundaries().states;
+
+        assert!(regions.are_neighbours("AL", "BE"));
+        assert!(regions.are_neighbours("BE", "AL"));
+        assert!(!regions.are_neighbours("AL", "GA"));
+        assert!(regions.find("GA").unwrap().neighbours.is_empty());
+    }
+
+    #[test]
+    fn a_guess_inside_the_region_is_correct() {
+        let boundaries = boundaries();
+        let guess = boundaries.judge(GuessMode::State, &city("Alpha", 2.0, 2.0), 8.0, 3.0).unwrap();
+
+        assert_eq!(guess.verdict, Verdict::Correct);
+        assert_eq!(guess.guessed_code.as_deref(), Some("AL"));
+        assert_eq!(guess.target_name, "Alpha");
+        assert_eq!(guess.score(5000), 5000);
+    }
+
+    #[test]
+    fn a_guess_in_a_hole_is_not_in_the_region() {
+        let boundaries = boundaries();
+        let guess = boundaries.judge(GuessMode::State, &city("Alpha", 2.0, 2.0), 5.0, 5.0).unwrap();
+
+        assert_eq!(guess.verdict, Verdict::Wrong);
+        assert_eq!(guess.guessed_code, None);
+        assert_eq!(guess.score(5000), 0);
+    }
+
+    #[test]
+    fn a_guess_just_off_the_edge_snaps_to_the_region() {
+        let boundaries = boundaries();
+        let target = city("Alpha", 2.0, 2.0);
+
+        // About 11 km south of the border, and just inside the hole
+        assert_eq!(boundaries.judge(GuessMode::State, &target, -0.1, 5.0).unwrap().verdict, Verdict::Correct);
+        assert_eq!(boundaries.judge(GuessMode::State, &target, 4.1, 5.0).unwrap().verdict, Verdict::Correct);
+
+        // About 55 km off is too far
+        let far = boundaries.judge(GuessMode::State, &target, -0.5, 5.0).unwrap();
+        assert_eq!(far.verdict, Verdict::Wrong);
+        assert_eq!(far.guessed_code, None);
+    }
+
+    #[test]
+    fn a_guess_in_a_neighbour_gets_part_of_the_score() {
+        let boundaries = boundaries();
+        let target = city("Alpha", 2.0, 2.0);
+
+        let neighbour = boundaries.judge(GuessMode::State, &target, 5.0, 15.0).unwrap();
+        assert_eq!(neighbour.verdict, Verdict::Neighbour);
+        assert_eq!(neighbour.guessed_name.as_deref(), Some("Beta"));
+        assert_eq!(neighbour.credit, 0.5);
+        assert_eq!(neighbour.score(5000), 2500);
+
+        let far_away = boundaries.judge(GuessMode::State, &target, 52.0, 52.0).unwrap();
+        assert_eq!(far_away.verdict, Verdict::Wrong);
+        assert_eq!(far_away.score(5000), 0);
+    }
+
+    #[test]
+    fn an_unknown_region_is_found_by_where_the_city_is() {
+        let boundaries = boundaries();
+        let guess = boundaries.judge(GuessMode::State, &city("Atlantis", 5.0, 15.0), 3.0, 12.0).unwrap();
+
+        assert_eq!(guess.verdict, Verdict::Correct);
+        assert_eq!(guess.target_name, "Beta");
+    }
+
+    #[test]
+    fn pinpoint_guesses_are_not_judged_by_region() {
+        assert!(boundaries().judge(GuessMode::Point, &city("Alpha", 2.0, 2.0), 2.0, 2.0).is_err());
+    }
+
+    #[test]
+    fn known_regions_hold_the_cities_the_boundaries_can_judge() {
+        let boundaries = boundaries();
+        let states = boundaries.known_regions(GuessMode::State).unwrap();
+
+        assert!(states.contains(&city("Alpha", 0.0, 0.0)));
+        assert!(states.contains(&city("AL", 0.0, 0.0)));
+        assert!(states.contains(&city("gamma", 0.0, 0.0)));
+        assert!(!states.contains(&city("Atlantis", 5.0, 15.0)));
+        assert_eq!(states.label(&city("Beta", 0.0, 0.0)), "BETA");
+
+        // No countries were loaded
+        assert!(!boundaries.known_regions(GuessMode::Country).unwrap().contains(&city("Alpha", 0.0, 0.0)));
+        assert!(boundaries.known_regions(GuessMode::Point).is_none());
+    }
+}
@@ -0,0 +1,448 @@
+use serde_json::Value;
+
+use crate::geodesy::EARTH_RADIUS_KM;
+
+/// A position the way GeoJSON writes it, longitude first
+pub type Position = [f32; 2];
+
+/// Kilometers per degree of latitude, and of longitude at the equator
+const KM_PER_DEGREE: f32 = 111.32;
+
+/// The smallest box of latitudes and longitudes around a shape
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub south: f32,
+    pub west: f32,
+    pub north: f32,
+    pub east: f32,
+}
+
+impl BoundingBox {
+    fn around<'a>(positions: impl IntoIterator<Item = &'a Position>) -> Option<Self> {
+        positions.into_iter().fold(None, |bbox, &[lng, lat]| {
+            Some(match bbox {
+                None => Self {
+                    south: lat,
+                    west: lng,
+                    north: lat,
+                    east: lng,
+                },
+                Some(bbox) => Self {
+                    south: bbox.south.min(lat),
+                    west: bbox.west.min(lng),
+                    north: bbox.north.max(lat),
+                    east: bbox.east.max(lng),
+                },
+            })
+        })
+    }
+
+    fn union(self, other: Self) -> Self {
+        Self {
+            south: self.south.min(other.south),
+            west: self.west.min(other.west),
+            north: self.north.max(other.north),
+            east: self.east.max(other.east),
+        }
+    }
+
+    pub fn contains(&self, latitude: f32, longitude: f32) -> bool {
+        (self.south..=self.north).contains(&latitude) && (self.west..=self.east).contains(&longitude)
+    }
+
+    /// The box grown by the given number of degrees on every side
+    pub fn grown(&self, degrees: f32) -> Self {
+        Self {
+            south: self.south - degrees,
+            west: self.west - degrees,
+            north: self.north + degrees,
+            east: self.east + degrees,
+        }
+    }
+
+    pub fn intersects(&self, other: &Self) -> bool {
+        self.south <= other.north && other.south <= self.north && self.west <= other.east && other.west <= self.east
+    }
+}
+
+/// An area bounded by an outer ring, with optional holes cut out of it.
+/// Every ring is closed, its last position is its first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polygon {
+    exterior: Vec<Position>,
+    holes: Vec<Vec<Position>>,
+    bbox: BoundingBox,
+}
+
+impl Polygon {
+    /// Builds a polygon out of its rings, closing rings that aren't closed yet
+    ///
+    /// # Returns:
+    /// [Result]<[Polygon], [String]> - Or what is wrong with the rings
+    pub fn new(exterior: Vec<Position>, holes: Vec<Vec<Position>>) -> Result<Self, String> {
+        let exterior = closed(exterior)?;
+        let holes = holes.into_iter().map(closed).collect::<Result<Vec<_>, _>>()?;
+        let bbox = BoundingBox::around(&exterior).ok_or_else(|| "The polygon has no positions".to_string())?;
+
+        Ok(Self { exterior, holes, bbox })
+    }
+
+    pub fn exterior(&self) -> &[Position] {
+        &self.exterior
+    }
+
+    pub fn holes(&self) -> &[Vec<Position>] {
+        &self.holes
+    }
+
+    pub fn bbox(&self) -> BoundingBox {
+        self.bbox
+    }
+
+    /// Area of the polygon on the earth in square kilometers, its holes taken out
+    pub fn area_km2(&self) -> f64 {
+        (ring_area_km2(&self.exterior) - self.holes.iter().map(|hole| ring_area_km2(hole)).sum::<f64>()).max(0.0)
+    }
+
+    /// Whether the point is inside the polygon, and not in one of its holes
+    pub fn contains(&self, latitude: f32, longitude: f32) -> bool {
+        self.bbox.contains(latitude, longitude)
+            && ring_contains(&self.exterior, latitude, longitude)
+            && !self.holes.iter().any(|hole| ring_contains(hole, latitude, longitude))
+    }
+
+    fn rings(&self) -> impl Iterator<Item = &[Position]> {
+        std::iter::once(self.exterior.as_slice()).chain(self.holes.iter().map(Vec::as_slice))
+    }
+}
+
+/// Any number of polygons making up one shape, like a state and its islands
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MultiPolygon {
+    polygons: Vec<Polygon>,
+    bbox: Option<BoundingBox>,
+}
+
+impl MultiPolygon {
+    pub fn new(polygons: Vec<Polygon>) -> Self {
+        let bbox = polygons.iter().map(Polygon::bbox).reduce(BoundingBox::union);
+
+        Self { polygons, bbox }
+    }
+
+    /// Reads a GeoJSON `Polygon` or `MultiPolygon` geometry
+    ///
+    /// # Returns:
+    /// [Result]<[MultiPolygon], [String]> - Or what is wrong with the geometry
+    pub fn from_geojson(geometry: &Value) -> Result<Self, String> {
+        let coordinates = geometry
+            .get("coordinates")
+            .ok_or_else(|| "The geometry has no coordinates".to_string())?;
+
+        let polygons = match geometry.get("type").and_then(Value::as_str) {
+            Some("Polygon") => vec![polygon_from_geojson(coordinates)?],
+            Some("MultiPolygon") => coordinates
+                .as_array()
+                .ok_or_else(|| "The coordinates of a MultiPolygon have to be a list".to_string())?
+                .iter()
+                .map(polygon_from_geojson)
+                .collect::<Result<Vec<_>, _>>()?,
+            Some(other) => return Err(format!("Only polygons can be boundaries, not a {}", other)),
+            None => return Err("The geometry has no type".to_string()),
+        };
+
+        Ok(Self::new(polygons))
+    }
+
+    pub fn polygons(&self) -> &[Polygon] {
+        &self.polygons
+    }
+
+    pub fn bbox(&self) -> Option<BoundingBox> {
+        self.bbox
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.polygons.is_empty()
+    }
+
+    pub fn contains(&self, latitude: f32, longitude: f32) -> bool {
+        self.polygons
+            .iter()
+            .any(|polygon| polygon.contains(latitude, longitude))
+    }
+
+    /// How far the point is from the edge of the shape, in kilometers, 0 for points inside it.
+    /// Distances are measured on a flat map around the point, which is close enough for the few
+    /// kilometers this is used for.
+    pub fn distance_km(&self, latitude: f32, longitude: f32) -> f32 {
+        if self.contains(latitude, longitude) {
+            return 0.0;
+        }
+
+        self.edges()
+            .map(|(from, to)| segment_distance_km([longitude, latitude], from, to))
+            .fold(f32::INFINITY, f32::min)
+    }
+
+    /// Whether the edges of the two shapes come within the given distance of each other anywhere,
+    /// shapes sharing a border or a single corner touch
+    pub fn touches(&self, other: &Self, tolerance_km: f32) -> bool {
+        let (Some(own), Some(theirs)) = (self.bbox, other.bbox) else {
+            return false;
+        };
+        let margin = tolerance_km / KM_PER_DEGREE;
+        if !own.grown(margin).intersects(&theirs) {
+            return false;
+        }
+
+        let close = |shape: &Self, [lng, lat]: Position| {
+            shape
+                .edges()
+                .any(|(from, to)| segment_distance_km([lng, lat], from, to) <= tolerance_km)
+        };
+
+        self.vertices()
+            .filter(|&&[lng, lat]| theirs.grown(margin).contains(lat, lng))
+            .any(|&vertex| close(other, vertex))
+            || other
+                .vertices()
+                .filter(|&&[lng, lat]| own.grown(margin).contains(lat, lng))
+                .any(|&vertex| close(self, vertex))
+    }
+
+    fn vertices(&self) -> impl Iterator<Item = &Position> {
+        self.polygons.iter().flat_map(Polygon::rings).flatten()
+    }
+
+    fn edges(&self) -> impl Iterator<Item = (Position, Position)> + '_ {
+        self.polygons
+            .iter()
+            .flat_map(Polygon::rings)
+            .flat_map(|ring| ring.windows(2).map(|edge| (edge[0], edge[1])))
+    }
+}
+
+fn polygon_from_geojson(coordinates: &Value) -> Result<Polygon, String> {
+    let mut rings = coordinates
+        .as_array()
+        .ok_or_else(|| "The rings of a polygon have to be a list".to_string())?
+        .iter()
+        .map(ring_from_geojson)
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter();
+
+    let exterior = rings.next().ok_or_else(|| "The polygon has no rings".to_string())?;
+
+    Polygon::new(exterior, rings.collect())
+}
+
+fn ring_from_geojson(ring: &Value) -> Result<Vec<Position>, String> {
+    ring.as_array()
+        .ok_or_else(|| "A ring has to be a list of positions".to_string())?
+        .iter()
+        .map(|position| {
+            let number = |index: usize| {
+                position
+                    .get(index)
+                    .and_then(Value::as_f64)
+                    .map(|value| value as f32)
+                    .ok_or_else(|| format!("{} is not a position", position))
+            };
+            let (longitude, latitude) = (number(0)?, number(1)?);
+
+            if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+                return Err(format!("{} is not a position on earth", position));
+            }
+
+            Ok([longitude, latitude])
+        })
+        .collect()
+}
+
+fn closed(mut ring: Vec<Position>) -> Result<Vec<Position>, String> {
+    if ring.first() != ring.last() {
+        ring.push(ring[0]);
+    }
+
+    // A triangle is the smallest ring, three corners and the first one again
+    if ring.len() < 4 {
+        return Err(format!("A ring needs at least 3 corners, this one has {}", ring.len().saturating_sub(1)));
+    }
+
+    Ok(ring)
+}
+
+/// Area of a ring in square kilometers, measured on a cylindrical equal-area projection of the earth
+/// so the edges can be summed up like on a flat map
+fn ring_area_km2(ring: &[Position]) -> f64 {
+    let twice_area: f64 = ring
+        .windows(2)
+        .map(|edge| {
+            let ([lng_a, lat_a], [lng_b, lat_b]) = (edge[0], edge[1]);
+            let (x_a, x_b) = ((lng_a as f64).to_radians(), (lng_b as f64).to_radians());
+            let (y_a, y_b) = ((lat_a as f64).to_radians().sin(), (lat_b as f64).to_radians().sin());
+
+            x_a * y_b - x_b * y_a
+        })
+        .sum();
+
+    (twice_area / 2.0).abs() * EARTH_RADIUS_KM * EARTH_RADIUS_KM
+}
+
+/// Even-odd rule, a ray going east from the point crosses the edge of the ring an odd number of times
+/// if the point is inside it
+fn ring_contains(ring: &[Position], latitude: f32, longitude: f32) -> bool {
+    ring.windows(2).fold(false, |inside, edge| {
+        let ([lng_a, lat_a], [lng_b, lat_b]) = (edge[0], edge[1]);
+
+        if (lat_a > latitude) != (lat_b > latitude)
+            && longitude < (lng_b - lng_a) * (latitude - lat_a) / (lat_b - lat_a) + lng_a
+        {
+            !inside
+        } else {
+            inside
+        }
+    })
+}
+
+/// Distance between a point and an edge in kilometers, on a flat map centered on the point
+fn segment_distance_km(point: Position, from: Position, to: Position) -> f32 {
+    let scale = point[1].to_radians().cos() * KM_PER_DEGREE;
+    let project = |[lng, lat]: Position| ((lng - point[0]) * scale, (lat - point[1]) * KM_PER_DEGREE);
+
+    let (ax, ay) = project(from);
+    let (bx, by) = project(to);
+    let (dx, dy) = (bx - ax, by - ay);
+    let length = dx * dx + dy * dy;
+
+    let t = if length == 0.0 {
+        0.0
+    } else {
+        (-(ax * dx + ay * dy) / length).clamp(0.0, 1.0)
+    };
+
+    (ax + t * dx).hypot(ay + t * dy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// A square from 0 to 10 degrees with a square hole from 4 to 6 degrees
+    fn square_with_hole() -> Polygon {
+        Polygon::new(
+            vec![[0.0, 0.0], [10.0, 0.0], [10.0, 10.0], [0.0, 10.0]],
+            vec![vec![[4.0, 4.0], [6.0, 4.0], [6.0, 6.0], [4.0, 6.0]]],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn points_inside_are_contained() {
+        let polygon = square_with_hole();
+
+        assert!(polygon.contains(1.0, 1.0));
+        assert!(polygon.contains(9.99, 5.0));
+        assert!(polygon.contains(5.0, 3.9));
+    }
+
+    #[test]
+    fn points_outside_or_in_a_hole_are_not_contained() {
+        let polygon = square_with_hole();
+
+        assert!(!polygon.contains(5.0, 5.0));
+        assert!(!polygon.contains(4.5, 5.5));
+        assert!(!polygon.contains(-0.01, 5.0));
+        assert!(!polygon.contains(5.0, 10.01));
+        assert!(!polygon.contains(50.0, 50.0));
+    }
+
+    #[test]
+    fn rings_are_closed_and_need_three_corners() {
+        let polygon = Polygon::new(vec![[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]], Vec::new()).unwrap();
+        assert_eq!(polygon.exterior().first(), polygon.exterior().last());
+        assert_eq!(polygon.exterior().len(), 4);
+
+        assert!(Polygon::new(vec![[0.0, 0.0], [1.0, 0.0], [0.0, 0.0]], Vec::new()).is_err());
+    }
+
+    #[test]
+    fn distance_is_zero_inside_and_measured_to_the_nearest_edge_outside() {
+        let shape = MultiPolygon::new(vec![square_with_hole()]);
+
+        assert_eq!(shape.distance_km(1.0, 1.0), 0.0);
+
+        // Half a degree south of the south edge, and in the middle of the hole a degree from its edges
+        assert!((shape.distance_km(-0.5, 5.0) - 0.5 * KM_PER_DEGREE).abs() < 0.1);
+        assert!((shape.distance_km(5.0, 5.0) - KM_PER_DEGREE).abs() < 0.5);
+
+        // Right on the edge counts as on it, whether the even-odd rule puts it in or out
+        assert!(shape.distance_km(0.0, 5.0) < 0.001);
+        assert!(shape.distance_km(5.0, 10.0) < 0.001);
+    }
+
+    #[test]
+    fn shapes_sharing_a_border_or_a_corner_touch() {
+        let square = |west: f32, south: f32| {
+            MultiPolygon::new(vec![Polygon::new(
+                vec![[west, south], [west + 1.0, south], [west + 1.0, south + 1.0], [west, south + 1.0]],
+                Vec::new(),
+            )
+            .unwrap()])
+        };
+
+        assert!(square(0.0, 0.0).touches(&square(1.0, 0.0), 1.0));
+        assert!(square(0.0, 0.0).touches(&square(1.0, 1.0), 1.0));
+        assert!(square(0.0, 0.0).touches(&square(1.005, 0.0), 1.0));
+        assert!(!square(0.0, 0.0).touches(&square(1.1, 0.0), 1.0));
+        assert!(!square(0.0, 0.0).touches(&MultiPolygon::default(), 1.0));
+    }
+
+    #[test]
+    fn area_leaves_out_the_holes() {
+        let one_degree = Polygon::new(vec![[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]], Vec::new()).unwrap();
+        assert!((one_degree.area_km2() - 12_364.0).abs() < 10.0);
+
+        let polygon = square_with_hole();
+        let without_hole = Polygon::new(polygon.exterior().to_vec(), Vec::new()).unwrap();
+        let hole = Polygon::new(polygon.holes()[0].clone(), Vec::new()).unwrap();
+        assert!((polygon.area_km2() - (without_hole.area_km2() - hole.area_km2())).abs() < 1.0);
+    }
+
+    #[test]
+    fn reads_polygons_and_multipolygons() {
+        let polygon = MultiPolygon::from_geojson(&json!({
+            "type": "Polygon",
+            "coordinates": [[[0, 0], [10, 0], [10, 10], [0, 10], [0, 0]], [[4, 4], [6, 4], [6, 6], [4, 6], [4, 4]]]
+        }))
+        .unwrap();
+        assert_eq!(polygon.polygons(), &[square_with_hole()]);
+
+        let islands = MultiPolygon::from_geojson(&json!({
+            "type": "MultiPolygon",
+            "coordinates": [[[[0, 0], [1, 0], [0, 1]]], [[[20, 20], [21, 20], [20, 21]]]]
+        }))
+        .unwrap();
+        assert_eq!(islands.polygons().len(), 2);
+        assert!(islands.contains(20.2, 20.2));
+        assert_eq!(
+            islands.bbox(),
+            Some(BoundingBox {
+                south: 0.0,
+                west: 0.0,
+                north: 21.0,
+                east: 21.0
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_geometries_that_are_not_polygons_on_earth() {
+        assert!(MultiPolygon::from_geojson(&json!({"type": "Point", "coordinates": [0, 0]})).is_err());
+        assert!(MultiPolygon::from_geojson(&json!({"type": "Polygon"})).is_err());
+        assert!(MultiPolygon::from_geojson(&json!({"type": "Polygon", "coordinates": [[[0, 0], [200, 0], [0, 1]]]})).is_err());
+        assert!(MultiPolygon::from_geojson(&json!({"type": "Polygon", "coordinates": []})).is_err());
+    }
+}
@@ -8,13 +8,33 @@ use sqlx::PgPool;
 use tower_http::services::*;
 
 
+use std::sync::Arc;
+
+use crate::catalog::{Catalog, CityCatalog};
 use crate::db::Store;
 use crate::handlers::root;
+use crate::imagery::prefetch::RoundPool;
+use crate::imagery::provider_from_env;
+use crate::regions::BoundarySet;
+use crate::state::AppState;
 use crate::{handlers, layers};
 
 /// File handles all our routes and requests
 pub async fn app(pool: PgPool) -> Router {
-    let db = Store::with_pool(pool);
+    let store = Store::with_pool(pool);
+    let catalog: Catalog = Arc::new(
+        CityCatalog::from_store(&store)
+            .await
+            .unwrap_or_else(|err| panic!("Could not load the city catalog: {:?}", err)),
+    );
+    let imagery = provider_from_env();
+    let state = AppState {
+        store,
+        rounds: RoundPool::from_env(imagery.clone(), catalog.clone()),
+        catalog,
+        imagery,
+        boundaries: Arc::new(BoundarySet::from_env()),
+    };
 
     let (cors_layer, trace_layer) = layers::get_layers();
 
@@ -25,7 +45,31 @@ pub async fn app(pool: PgPool) -> Router {
         .route("/users", post(handlers::register))
         .route("/login", post(handlers::login))
         .route("/guess", post(handlers::guess_location))
+        .route("/rounds/:id/hints", post(handlers::take_hint))
+        .route("/games", post(handlers::new_game))
+        .route("/games/new", get(handlers::new_game_settings))
+        .route("/games/:id/summary", get(handlers::game_summary))
         .route("/leaderboard", get(handlers::leaderboard))
+        .route("/maps", get(handlers::list_maps).post(handlers::create_map))
+        .route("/maps/import", post(handlers::import_map))
+        .route("/maps/:id", get(handlers::view_map).post(handlers::update_map))
+        .route("/maps/:id/import", post(handlers::import_into_map))
+        .route("/maps/:id/export/:format", get(handlers::export_map))
+        .route("/maps/:id/locations", post(handlers::add_map_location))
+        .route("/maps/:id/locations/:location_id/delete", post(handlers::remove_map_location))
+        .route("/maps/:id/play", post(handlers::play_map))
+        .route("/maps/:id/leaderboard", get(handlers::map_leaderboard))
+        .route("/cities/near", get(handlers::nearby_cities))
+        .route("/daily", get(handlers::daily_challenge))
+        .route("/daily/leaderboard", get(handlers::daily_leaderboard))
+        .route("/daily/archive", get(handlers::daily_archive))
+        .route("/daily/:date", get(handlers::daily_leaderboard_for_date))
+        .route("/images/rounds/:id", get(handlers::round_image))
+        .route("/images/results/:id", get(handlers::result_image))
+        .route("/imagery/stats", get(handlers::imagery_stats))
+        .route("/me", get(handlers::my_profile))
+        .route("/users/:id", get(handlers::user_profile))
+        .route("/settings", get(handlers::settings).post(handlers::save_settings))
         .route("/protected", get(handlers::protected))
         // Catch all route, AKA: 404
         .route("/*_", get(handle_404)) // '/*_' will match anything not in our routes above
@@ -33,7 +77,7 @@ pub async fn app(pool: PgPool) -> Router {
         // .merge(city_routes())
         .layer(cors_layer)
         .layer(trace_layer)
-        .with_state(db)
+        .with_state(state)
 }
 
 async fn handle_404() -> Response<Body> {
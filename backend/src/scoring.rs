@@ -0,0 +1,226 @@
+use std::str::FromStr;
+
+use serde_derive::{Deserialize, Serialize};
+
+/// Scores are computed by one of these strategies. Each game picks one when it is created and
+/// every round remembers which one scored it, so old scores can always be explained.
+pub trait ScoringStrategy: Send + Sync {
+    /// Which strategy this is, this is what gets stored next to a scored round
+    fn kind(&self) -> ScoringKind;
+
+    /// The best score a single guess can get
+    fn max_score(&self) -> i32;
+
+    /// Turns the distance of a guess, in kilometers, into a score between 0 and [max_score](ScoringStrategy::max_score)
+    fn score(&self, distance_km: f32) -> i32;
+}
+
+/// The built in scoring strategies, stored as text in the database
+#[derive(Serialize, Deserialize, sqlx::Type, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum ScoringKind {
+    #[default]
+    LinearClamped,
+    ExponentialDecay,
+    Banded,
+}
+
+impl ScoringKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ScoringKind::LinearClamped => "linear_clamped",
+            ScoringKind::ExponentialDecay => "exponential_decay",
+            ScoringKind::Banded => "banded",
+        }
+    }
+
+    /// The strategy implementing this kind of scoring
+    pub fn strategy(self) -> &'static dyn ScoringStrategy {
+        match self {
+            ScoringKind::LinearClamped => &LINEAR_CLAMPED,
+            ScoringKind::ExponentialDecay => &EXPONENTIAL_DECAY,
+            ScoringKind::Banded => &BANDED,
+        }
+    }
+
+    /// Gets the configured scoring strategy from the .env file, falling back to the default
+    /// if it is missing or not one we know about. Games scored different ways are ranked on separate
+    /// leaderboards, see [Store::get_top_num_users](crate::db::Store::get_top_num_users).
+    ///
+    /// # .env variables
+    /// * SCORING_STRATEGY - One of `linear_clamped`, `exponential_decay` or `banded`
+    pub fn from_env() -> Self {
+        std::env::var("SCORING_STRATEGY")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or_default()
+    }
+}
+
+impl FromStr for ScoringKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linear_clamped" => Ok(ScoringKind::LinearClamped),
+            "exponential_decay" => Ok(ScoringKind::ExponentialDecay),
+            "banded" => Ok(ScoringKind::Banded),
+            other => Err(format!("Unknown scoring strategy: {}", other)),
+        }
+    }
+}
+
+/// Loses a fixed amount of points per kilometer, never going below 0
+pub struct LinearClamped {
+    pub max_score: i32,
+    pub points_per_km: f32,
+}
+
+const LINEAR_CLAMPED: LinearClamped = LinearClamped {
+    max_score: 100,
+    points_per_km: 0.5,
+};
+
+impl ScoringStrategy for LinearClamped {
+    fn kind(&self) -> ScoringKind {
+        ScoringKind::LinearClamped
+    }
+
+    fn max_score(&self) -> i32 {
+        self.max_score
+    }
+
+    fn score(&self, distance_km: f32) -> i32 {
+        let score = self.max_score as f32 - distance_km * self.points_per_km;
+        score.clamp(0.0, self.max_score as f32).round() as i32
+    }
+}
+
+/// The GeoGuessr style curve, `max_score * e^(-distance / scale_km)`.
+/// Close guesses lose points fast, far away guesses slowly trail off towards 0.
+pub struct ExponentialDecay {
+    pub max_score: i32,
+    pub scale_km: f32,
+}
+
+/// GeoGuessr uses a tenth of the size of the map as the scale, which is about 1492 km for the whole world
+const EXPONENTIAL_DECAY: ExponentialDecay = ExponentialDecay {
+    max_score: 5000,
+    scale_km: 1492.7,
+};
+
+impl ScoringStrategy for ExponentialDecay {
+    fn kind(&self) -> ScoringKind {
+        ScoringKind::ExponentialDecay
+    }
+
+    fn max_score(&self) -> i32 {
+        self.max_score
+    }
+
+    fn score(&self, distance_km: f32) -> i32 {
+        let score = self.max_score as f32 * (-distance_km.max(0.0) / self.scale_km).exp();
+        score.round() as i32
+    }
+}
+
+/// Fixed tiers of points, the first band the distance falls into decides the score
+pub struct Banded {
+    /// Pairs of (furthest distance in kilometers, points), ordered from closest to furthest
+    pub bands: &'static [(f32, i32)],
+}
+
+const BANDED: Banded = Banded {
+    bands: &[
+        (25.0, 100),
+        (100.0, 75),
+        (250.0, 50),
+        (500.0, 25),
+        (1000.0, 10),
+    ],
+};
+
+impl ScoringStrategy for Banded {
+    fn kind(&self) -> ScoringKind {
+        ScoringKind::Banded
+    }
+
+    fn max_score(&self) -> i32 {
+        self.bands.iter().map(|(_, points)| *points).max().unwrap_or(0)
+    }
+
+    fn score(&self, distance_km: f32) -> i32 {
+        self.bands
+            .iter()
+            .find(|(max_km, _)| distance_km <= *max_km)
+            .map(|(_, points)| *points)
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn linear_clamped_loses_half_a_point_per_km_until_200_km() {
+        let strategy = ScoringKind::LinearClamped.strategy();
+
+        assert_eq!(strategy.max_score(), 100);
+        assert_eq!(strategy.score(0.0), 100);
+        assert_eq!(strategy.score(1.0), 100);
+        assert_eq!(strategy.score(100.0), 50);
+        assert_eq!(strategy.score(199.0), 1);
+        assert_eq!(strategy.score(200.0), 0);
+        assert_eq!(strategy.score(201.0), 0);
+        assert_eq!(strategy.score(20_000.0), 0);
+    }
+
+    #[test]
+    fn exponential_decay_trails_off_with_distance() {
+        let strategy = ScoringKind::ExponentialDecay.strategy();
+
+        assert_eq!(strategy.max_score(), 5000);
+        assert_eq!(strategy.score(0.0), 5000);
+        assert_eq!(strategy.score(-5.0), 5000);
+
+        // One scale down the curve is e^-1 of the best score, and it never quite reaches 0
+        assert_eq!(strategy.score(1492.7), 1839);
+        assert_eq!(strategy.score(1492.7 * 2.0), 677);
+        assert_eq!(strategy.score(10_000.0), 6);
+        assert_eq!(strategy.score(20_000.0), 0);
+
+        let mut last = strategy.score(0.0);
+        for km in (100..20_000).step_by(100) {
+            let score = strategy.score(km as f32);
+            assert!(score <= last, "{} km", km);
+            last = score;
+        }
+    }
+
+    #[test]
+    fn banded_scores_by_the_first_band_the_distance_is_in() {
+        let strategy = ScoringKind::Banded.strategy();
+
+        assert_eq!(strategy.max_score(), 100);
+        assert_eq!(strategy.score(0.0), 100);
+        assert_eq!(strategy.score(25.0), 100);
+        assert_eq!(strategy.score(25.1), 75);
+        assert_eq!(strategy.score(100.0), 75);
+        assert_eq!(strategy.score(250.0), 50);
+        assert_eq!(strategy.score(500.0), 25);
+        assert_eq!(strategy.score(1000.0), 10);
+        assert_eq!(strategy.score(1000.1), 0);
+        assert_eq!(strategy.score(20_000.0), 0);
+    }
+
+    #[test]
+    fn every_kind_is_scored_by_its_own_strategy() {
+        for kind in [ScoringKind::LinearClamped, ScoringKind::ExponentialDecay, ScoringKind::Banded] {
+            assert_eq!(kind.strategy().kind(), kind);
+            assert_eq!(kind.as_str().parse::<ScoringKind>(), Ok(kind));
+        }
+        assert!("quadratic".parse::<ScoringKind>().is_err());
+    }
+}
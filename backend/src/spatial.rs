@@ -0,0 +1,357 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use crate::geodesy::EARTH_RADIUS_KM;
+
+/// A [k-d tree](https://en.wikipedia.org/wiki/K-d_tree) over points on the earth, for finding the points closest to a
+/// location, or every point around it, without checking all of them.
+/// Points are given as `[latitude, longitude]` and identified by their position in the list the tree was built from.
+///
+/// Every point is turned into a point on the unit sphere, the straight line distance between two of those only grows
+/// with the distance along the surface, so the tree can split on plain x, y and z and still has no trouble with the
+/// antimeridian or the poles.
+#[derive(Debug, Clone, Default)]
+pub struct SphereTree {
+    /// Points on the unit sphere, in the order they were given
+    points: Vec<[f64; 3]>,
+
+    /// Positions in `points` laid out as the tree. The median of a range is the node splitting it,
+    /// everything before it is on the low side and everything after it on the high side
+    nodes: Vec<usize>,
+}
+
+/// A point found by a search, ordered by how far it is so the furthest one is at the top of a [BinaryHeap]
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Found {
+    /// Straight line distance through the unit sphere
+    chord: f64,
+    index: usize,
+}
+
+impl Eq for Found {}
+
+impl PartialOrd for Found {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Found {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.chord.total_cmp(&other.chord).then(self.index.cmp(&other.index))
+    }
+}
+
+impl SphereTree {
+    /// Builds the tree, every point has to be a valid latitude and longitude
+    pub fn new(points: Vec<[f32; 2]>) -> Self {
+        let points: Vec<[f64; 3]> = points
+            .into_iter()
+            .map(|[latitude, longitude]| to_unit_vector(latitude, longitude))
+            .collect();
+        let mut nodes: Vec<usize> = (0..points.len()).collect();
+        Self::build(&points, &mut nodes, 0);
+
+        Self { points, nodes }
+    }
+
+    /// Puts the median of `nodes` along the axis for this depth in the middle, and does the same for both halves
+    fn build(points: &[[f64; 3]], nodes: &mut [usize], depth: usize) {
+        if nodes.len() <= 1 {
+            return;
+        }
+
+        let axis = depth % 3;
+        let middle = nodes.len() / 2;
+        nodes.select_nth_unstable_by(middle, |&a, &b| points[a][axis].total_cmp(&points[b][axis]));
+
+        let (low, high) = nodes.split_at_mut(middle);
+        Self::build(points, low, depth + 1);
+        Self::build(points, &mut high[1..], depth + 1);
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// The point closest to the given location
+    ///
+    /// # Returns:
+    /// [Option]<(usize, f32)> - Position of the point and its distance in kilometers, none when the tree is empty
+    pub fn nearest(&self, latitude: f32, longitude: f32) -> Option<(usize, f32)> {
+        self.k_nearest(latitude, longitude, 1).into_iter().next()
+    }
+
+    /// The `k` points closest to the given location, or all of them when there are fewer
+    ///
+    /// # Returns:
+    /// [Vec]<(usize, f32)> - Positions of the points and their distance in kilometers, closest first
+    pub fn k_nearest(&self, latitude: f32, longitude: f32, k: usize) -> Vec<(usize, f32)> {
+        if k == 0 {
+            return Vec::new();
+        }
+
+        let target = to_unit_vector(latitude, longitude);
+        let mut best = BinaryHeap::with_capacity(k + 1);
+        self.search_nearest(&target, k, 0, self.nodes.len(), 0, &mut best);
+
+        Self::in_km(best.into_vec())
+    }
+
+    /// Every point within `radius_km` of the given location
+    ///
+    /// # Returns:
+    /// [Vec]<(usize, f32)> - Positions of the points and their distance in kilometers, closest first
+    pub fn within_radius(&self, latitude: f32, longitude: f32, radius_km: f32) -> Vec<(usize, f32)> {
+        if radius_km < 0.0 {
+            return Vec::new();
+        }
+
+        let target = to_unit_vector(latitude, longitude);
+        let mut found = Vec::new();
+        self.search_radius(&target, km_to_chord(radius_km), 0, self.nodes.len(), 0, &mut found);
+
+        Self::in_km(found)
+    }
+
+    /// Looks through the nodes between `start` and `end`, keeping the `k` closest points found in `best`
+    fn search_nearest(
+        &self,
+        target: &[f64; 3],
+        k: usize,
+        start: usize,
+        end: usize,
+        depth: usize,
+        best: &mut BinaryHeap<Found>,
+    ) {
+        if start >= end {
+            return;
+        }
+
+        let middle = start + (end - start) / 2;
+        let index = self.nodes[middle];
+        let point = &self.points[index];
+
+        best.push(Found {
+            chord: chord(target, point),
+            index,
+        });
+        if best.len() > k {
+            best.pop();
+        }
+
+        let axis = depth % 3;
+        let offset = target[axis] - point[axis];
+        let (near, far) = if offset < 0.0 {
+            ((start, middle), (middle + 1, end))
+        } else {
+            ((middle + 1, end), (start, middle))
+        };
+
+        self.search_nearest(target, k, near.0, near.1, depth + 1, best);
+
+        // The far side can only hold something closer if the splitting plane is closer than the furthest point kept
+        let furthest = best.peek().map_or(f64::INFINITY, |found| found.chord);
+        if best.len() < k || offset.abs() <= furthest {
+            self.search_nearest(target, k, far.0, far.1, depth + 1, best);
+        }
+    }
+
+    /// Looks through the nodes between `start` and `end`, adding every point within `radius` to `found`
+    fn search_radius(
+        &self,
+        target: &[f64; 3],
+        radius: f64,
+        start: usize,
+        end: usize,
+        depth: usize,
+        found: &mut Vec<Found>,
+    ) {
+        if start >= end {
+            return;
+        }
+
+        let middle = start + (end - start) / 2;
+        let index = self.nodes[middle];
+        let point = &self.points[index];
+
+        let distance = chord(target, point);
+        if distance <= radius {
+            found.push(Found { chord: distance, index });
+        }
+
+        let axis = depth % 3;
+        let offset = target[axis] - point[axis];
+        if offset <= radius {
+            self.search_radius(target, radius, start, middle, depth + 1, found);
+        }
+        if offset >= -radius {
+            self.search_radius(target, radius, middle + 1, end, depth + 1, found);
+        }
+    }
+
+    /// Sorts what a search found, closest first, with the distances along the surface
+    fn in_km(mut found: Vec<Found>) -> Vec<(usize, f32)> {
+        found.sort_unstable();
+
+        found
+            .into_iter()
+            .map(|found| (found.index, chord_to_km(found.chord)))
+            .collect()
+    }
+}
+
+fn to_unit_vector(latitude: f32, longitude: f32) -> [f64; 3] {
+    let (latitude, longitude) = ((latitude as f64).to_radians(), (longitude as f64).to_radians());
+
+    [
+        latitude.cos() * longitude.cos(),
+        latitude.cos() * longitude.sin(),
+        latitude.sin(),
+    ]
+}
+
+/// Straight line distance between two points on the unit sphere
+fn chord(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+/// Kilometers along the surface between two points the given chord apart
+fn chord_to_km(chord: f64) -> f32 {
+    (2.0 * (chord / 2.0).min(1.0).asin() * EARTH_RADIUS_KM) as f32
+}
+
+/// The chord between two points the given kilometers apart along the surface, anything past the
+/// other side of the earth is as far as two points can get
+fn km_to_chord(km: f32) -> f64 {
+    let angle = (km as f64 / EARTH_RADIUS_KM).min(std::f64::consts::PI);
+
+    2.0 * (angle / 2.0).sin()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geodesy::haversine_km;
+    use proptest::prelude::*;
+
+    /// Every point sorted by its distance to the location, the way the tree orders what it finds
+    fn brute_force(points: &[[f32; 2]], latitude: f32, longitude: f32) -> Vec<Found> {
+        let target = to_unit_vector(latitude, longitude);
+        let mut found: Vec<Found> = points
+            .iter()
+            .enumerate()
+            .map(|(index, &[lat, lng])| Found {
+                chord: chord(&target, &to_unit_vector(lat, lng)),
+                index,
+            })
+            .collect();
+        found.sort_unstable();
+
+        found
+    }
+
+    fn indexes(found: &[(usize, f32)]) -> Vec<usize> {
+        found.iter().map(|&(index, _)| index).collect()
+    }
+
+    fn latitude() -> impl Strategy<Value = f32> {
+        prop_oneof![-90.0..=90.0f32, 89.0..=90.0f32, -90.0..=-89.0f32]
+    }
+
+    fn longitude() -> impl Strategy<Value = f32> {
+        prop_oneof![-180.0..=180.0f32, 179.0..=180.0f32, -180.0..=-179.0f32]
+    }
+
+    fn points() -> impl Strategy<Value = Vec<[f32; 2]>> {
+        prop::collection::vec((latitude(), longitude()).prop_map(|(lat, lng)| [lat, lng]), 0..200)
+    }
+
+    #[test]
+    fn nearest_sees_across_the_antimeridian() {
+        let tree = SphereTree::new(vec![[0.0, 179.9], [0.0, -179.9], [0.0, 170.0], [10.0, -179.9]]);
+
+        assert_eq!(tree.nearest(0.0, -179.95).map(|(index, _)| index), Some(1));
+        assert_eq!(tree.nearest(0.0, 179.99).map(|(index, _)| index), Some(0));
+        assert_eq!(indexes(&tree.k_nearest(0.0, 180.0, 2)), vec![0, 1]);
+        assert_eq!(indexes(&tree.within_radius(0.0, 180.0, 20.0)), vec![0, 1]);
+    }
+
+    #[test]
+    fn every_longitude_is_close_at_the_poles() {
+        let tree = SphereTree::new(vec![[90.0, 0.0], [89.9, 123.0], [-90.0, 0.0], [89.9, -57.0], [80.0, 0.0]]);
+
+        let (index, distance) = tree.nearest(90.0, -100.0).unwrap();
+        assert_eq!(index, 0);
+        assert!(distance < 0.001, "{}", distance);
+
+        let mut around = indexes(&tree.within_radius(90.0, 45.0, 12.0));
+        around.sort_unstable();
+        assert_eq!(around, vec![0, 1, 3]);
+
+        let (index, distance) = tree.nearest(-89.99, 170.0).unwrap();
+        assert_eq!(index, 2);
+        assert!((distance - 1.112).abs() < 0.01, "{}", distance);
+    }
+
+    #[test]
+    fn asking_for_more_points_than_there_are_gives_all_of_them() {
+        let tree = SphereTree::new(vec![[0.0, 0.0], [0.0, 2.0], [0.0, 1.0]]);
+
+        assert_eq!(indexes(&tree.k_nearest(0.0, 0.0, 10)), vec![0, 2, 1]);
+        assert!(tree.k_nearest(0.0, 0.0, 0).is_empty());
+        assert_eq!(tree.within_radius(0.0, 0.0, 20_100.0).len(), 3);
+        assert!(tree.within_radius(0.0, 0.0, -1.0).is_empty());
+    }
+
+    #[test]
+    fn an_empty_tree_finds_nothing() {
+        let tree = SphereTree::new(Vec::new());
+
+        assert!(tree.is_empty());
+        assert_eq!(tree.nearest(0.0, 0.0), None);
+        assert!(tree.k_nearest(0.0, 0.0, 5).is_empty());
+        assert!(tree.within_radius(0.0, 0.0, 100.0).is_empty());
+    }
+
+    proptest! {
+        #[test]
+        fn nearest_matches_brute_force(points in points(), lat in latitude(), lng in longitude()) {
+            let tree = SphereTree::new(points.clone());
+            let expected = brute_force(&points, lat, lng).first().map(|found| found.index);
+
+            prop_assert_eq!(tree.nearest(lat, lng).map(|(index, _)| index), expected);
+        }
+
+        #[test]
+        fn k_nearest_matches_brute_force(points in points(), lat in latitude(), lng in longitude(), k in 0..250usize) {
+            let tree = SphereTree::new(points.clone());
+            let found = tree.k_nearest(lat, lng, k);
+            let expected: Vec<usize> = brute_force(&points, lat, lng).iter().take(k).map(|found| found.index).collect();
+
+            prop_assert_eq!(indexes(&found), expected);
+            for &(index, distance) in &found {
+                let [point_lat, point_lng] = points[index];
+                let haversine = haversine_km(lat as f64, lng as f64, point_lat as f64, point_lng as f64) as f32;
+                prop_assert!((distance - haversine).abs() < 0.01 + haversine * 1e-5, "{} vs {}", distance, haversine);
+            }
+        }
+
+        #[test]
+        fn within_radius_matches_brute_force(points in points(), lat in latitude(), lng in longitude(), radius_km in 0.0..21_000.0f32) {
+            let tree = SphereTree::new(points.clone());
+            let radius = km_to_chord(radius_km);
+            let expected: Vec<usize> = brute_force(&points, lat, lng)
+                .iter()
+                .filter(|found| found.chord <= radius)
+                .map(|found| found.index)
+                .collect();
+
+            prop_assert_eq!(indexes(&tree.within_radius(lat, lng, radius_km)), expected);
+        }
+    }
+}
@@ -0,0 +1,18 @@
+use axum_macros::FromRef;
+
+use crate::catalog::Catalog;
+use crate::db::Store;
+use crate::imagery::prefetch::RoundPool;
+use crate::imagery::Imagery;
+use crate::regions::Boundaries;
+
+/// Everything the handlers share. Handlers only ask for the part they need,
+/// like `State<Store>` or `State<Imagery>`, thanks to [FromRef].
+#[derive(Clone, FromRef)]
+pub struct AppState {
+    pub store: Store,
+    pub catalog: Catalog,
+    pub imagery: Imagery,
+    pub rounds: RoundPool,
+    pub boundaries: Boundaries,
+}